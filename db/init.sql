@@ -1,3 +1 @@
-SET timezone = 'Europe/Moscow';
-CREATE EXTENSION pgcrypto;
-
+SET timezone = 'UTC';
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS session(
+    id TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    device TEXT,
+    user_agent TEXT,
+    ip TEXT,
+    creation_date TIMESTAMPTZ NOT NULL,
+    last_seen_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL,
+    purge_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS session_account_id_index ON session(account_id);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS password_reset_token(
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS password_reset_token_account_id_index ON password_reset_token(account_id);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS verification_token;
+ALTER TABLE account DROP COLUMN IF EXISTS verified;
@@ -0,0 +1,10 @@
+-- Accounts which existed before the verification was introduced are considered verified
+ALTER TABLE account ADD COLUMN IF NOT EXISTS verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE account ALTER COLUMN verified SET DEFAULT FALSE;
+CREATE TABLE IF NOT EXISTS verification_token(
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS verification_token_account_id_index ON verification_token(account_id);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS todo_account_id_index;
+ALTER TABLE todo DROP CONSTRAINT IF EXISTS todo_account_id_fkey;
@@ -0,0 +1,5 @@
+-- Todos of accounts which no longer exist can't satisfy the constraint
+DELETE FROM todo WHERE account_id NOT IN (SELECT id FROM account);
+ALTER TABLE todo
+    ADD CONSTRAINT todo_account_id_fkey FOREIGN KEY (account_id) REFERENCES account(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS todo_account_id_index ON todo(account_id);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS api_token(
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    scope TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    creation_date TIMESTAMPTZ NOT NULL,
+    last_used_date TIMESTAMPTZ
+);
+CREATE INDEX IF NOT EXISTS api_token_account_id_index ON api_token(account_id);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS two_factor(
+    account_id INTEGER PRIMARY KEY REFERENCES account(id) ON DELETE CASCADE,
+    secret TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    last_used_step BIGINT,
+    creation_date TIMESTAMPTZ NOT NULL
+);
+CREATE TABLE IF NOT EXISTS two_factor_recovery_code(
+    account_id INTEGER NOT NULL REFERENCES two_factor(account_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (account_id, code_hash)
+);
+CREATE TABLE IF NOT EXISTS two_factor_challenge(
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    device TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS two_factor_challenge_account_id_index ON two_factor_challenge(account_id);
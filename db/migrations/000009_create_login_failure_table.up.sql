@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS login_failure(
+    key TEXT PRIMARY KEY,
+    count INTEGER NOT NULL,
+    last_failure_date TIMESTAMPTZ NOT NULL,
+    purge_date TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,3 @@
+-- The first admin is promoted by hand, with `UPDATE account SET role = 'admin' WHERE email = '...'`
+ALTER TABLE account ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user';
+ALTER TABLE account ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS auth_event;
+DROP FUNCTION IF EXISTS auth_event_append_only();
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS auth_event(
+    id BIGSERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    ip TEXT,
+    user_agent TEXT,
+    creation_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS auth_event_account_id_index ON auth_event(account_id, creation_date);
+-- The events are never changed. they are only removed along with their account
+CREATE OR REPLACE FUNCTION auth_event_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'auth_event rows can''t be updated';
+END;
+$$ LANGUAGE plpgsql;
+DROP TRIGGER IF EXISTS auth_event_append_only ON auth_event;
+CREATE TRIGGER auth_event_append_only BEFORE UPDATE ON auth_event
+    FOR EACH ROW EXECUTE PROCEDURE auth_event_append_only();
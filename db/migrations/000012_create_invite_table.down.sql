@@ -0,0 +1,2 @@
+ALTER TABLE account DROP COLUMN IF EXISTS invite_id;
+DROP TABLE IF EXISTS invite;
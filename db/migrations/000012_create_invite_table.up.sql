@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS invite(
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    code_hash TEXT UNIQUE NOT NULL,
+    max_uses INTEGER NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS invite_account_id_index ON invite(account_id);
+-- The invite an account registered with, if any
+ALTER TABLE account ADD COLUMN IF NOT EXISTS invite_id INTEGER REFERENCES invite(id) ON DELETE SET NULL;
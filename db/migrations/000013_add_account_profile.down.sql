@@ -0,0 +1,6 @@
+ALTER TABLE todo DROP COLUMN IF EXISTS due_date;
+ALTER TABLE account DROP COLUMN IF EXISTS todo_sort;
+ALTER TABLE account DROP COLUMN IF EXISTS week_start;
+ALTER TABLE account DROP COLUMN IF EXISTS locale;
+ALTER TABLE account DROP COLUMN IF EXISTS timezone;
+ALTER TABLE account DROP COLUMN IF EXISTS display_name;
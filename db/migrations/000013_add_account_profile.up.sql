@@ -0,0 +1,7 @@
+-- The dates the users see, like what is due today, are computed in their timezone rather than the server's one
+ALTER TABLE account ADD COLUMN IF NOT EXISTS display_name VARCHAR(50);
+ALTER TABLE account ADD COLUMN IF NOT EXISTS timezone TEXT NOT NULL DEFAULT 'UTC';
+ALTER TABLE account ADD COLUMN IF NOT EXISTS locale TEXT NOT NULL DEFAULT 'en';
+ALTER TABLE account ADD COLUMN IF NOT EXISTS week_start TEXT NOT NULL DEFAULT 'monday';
+ALTER TABLE account ADD COLUMN IF NOT EXISTS todo_sort TEXT NOT NULL DEFAULT 'last_edit_date';
+ALTER TABLE todo ADD COLUMN IF NOT EXISTS due_date DATE;
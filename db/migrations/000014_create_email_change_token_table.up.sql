@@ -0,0 +1,9 @@
+-- An account has at most one pending email. it replaces the current one once the token sent to it is used
+CREATE TABLE IF NOT EXISTS email_change_token(
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS email_change_token_account_id_index ON email_change_token(account_id);
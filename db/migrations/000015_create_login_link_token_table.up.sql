@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS login_link_token(
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    -- The name of the device the login was asked from, given to the session
+    device TEXT,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS login_link_token_account_id_index ON login_link_token(account_id);
@@ -0,0 +1,20 @@
+-- A login started at the OpenID Connect provider. it's consumed by the provider's callback
+CREATE TABLE IF NOT EXISTS oidc_state(
+    state_hash TEXT PRIMARY KEY,
+    nonce TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    -- The name of the device the login was started from, given to the session
+    device TEXT,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+-- The provider's identities an account can login with. the subject never changes, unlike the email
+CREATE TABLE IF NOT EXISTS oidc_identity(
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    creation_date TIMESTAMPTZ NOT NULL,
+    UNIQUE (issuer, subject)
+);
+CREATE INDEX IF NOT EXISTS oidc_identity_account_id_index ON oidc_identity(account_id);
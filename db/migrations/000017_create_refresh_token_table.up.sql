@@ -0,0 +1,15 @@
+-- The refresh tokens of the logins made with tokens. each use replaces the token by the next one of its family, the
+-- chain of tokens of a single login
+CREATE TABLE IF NOT EXISTS refresh_token(
+    token_hash TEXT PRIMARY KEY,
+    family_id TEXT NOT NULL,
+    account_id INTEGER NOT NULL REFERENCES account(id) ON DELETE CASCADE,
+    -- The name of the device the login was made from
+    device TEXT,
+    -- The used tokens are kept until their family expires, so that a reuse can be detected
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    creation_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS refresh_token_family_id_index ON refresh_token(family_id);
+CREATE INDEX IF NOT EXISTS refresh_token_account_id_index ON refresh_token(account_id);
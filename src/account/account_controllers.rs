@@ -1,19 +1,123 @@
-use crate::account::account_models::AccountDbExecutor;
+use crate::account::account_models::{AccountDbExecutor, AccountProfile, WeekStart};
+use crate::account::account_passwords::{self, PasswordErrors};
+use crate::auth_events::auth_event_controllers::record_auth_event;
+use crate::auth_events::auth_event_models::AuthEventKind;
+use crate::common::cookies;
+use crate::common::requests;
 use crate::common::responses::ServerResponse;
+use crate::common::tokens;
 use crate::common::validators::{ValidationErrors, Validator};
+use crate::jwt::jwt_controllers::{start_token_session, JwtErrors};
+use crate::jwt::jwt_models::JwtDbExecutor;
+use crate::mail::mailer::Email;
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::middlewares::csrf;
+use crate::sessions::session_models::Session;
+use crate::sessions::session_store::SessionErrors;
+use crate::settings::{RegistrationMode, Settings, VerificationPolicy};
+use crate::todos::todo_models::TodoSort;
+use crate::two_factor::totp::Totp;
+use crate::two_factor::two_factor_controllers::normalize_recovery_code;
+use crate::two_factor::two_factor_models::TwoFactorDbExecutor;
 use crate::AppState;
 use crate::DbErrors;
-use actix_web::{self, cookie, dev, error, http, web};
-use redis::{AsyncCommands, RedisError};
+use actix_web::{self, dev, error, http, web, HttpRequest};
+use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use uuid;
 
-const MONTH_IN_SECONDS: i64 = 2628000;
+const MAX_DISPLAY_NAME_LENGTH: usize = 50;
 
 #[derive(Deserialize)]
 pub struct AccountRequest {
     email: String,
     password: String,
+    // Only needed when the registrations are invite only
+    invite: Option<String>,
+}
+
+// Browsers get a session cookie, the clients which can't keep cookies ask for tokens. the cookie is the default
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginMode {
+    Cookie,
+    Token,
+}
+
+#[derive(Deserialize)]
+pub struct AccountLoginRequest {
+    email: String,
+    password: String,
+    device: Option<String>,
+    mode: Option<LoginMode>,
+}
+
+#[derive(Deserialize)]
+pub struct AccountLoginLinkRequest {
+    email: String,
+    device: Option<String>,
+}
+
+// The second step of a login with two-factor authentication. either a code or a recovery code is needed
+#[derive(Deserialize)]
+pub struct AccountLoginTwoFactorRequest {
+    challenge: String,
+    code: Option<String>,
+    recovery_code: Option<String>,
+    mode: Option<LoginMode>,
+}
+
+#[derive(Deserialize)]
+pub struct AccountPasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountPasswordForgotRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountPasswordResetRequest {
+    token: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountVerifyQuery {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountVerificationResendRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountEmailRequest {
+    email: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountEmailConfirmQuery {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountProfileRequest {
+    display_name: Option<String>,
+    timezone: Option<String>,
+    locale: Option<String>,
+    week_start: Option<WeekStart>,
+    todo_sort: Option<TodoSort>,
+}
+
+#[derive(Deserialize)]
+pub struct AccountDeletionRequest {
+    password: String,
 }
 
 #[derive(Serialize)]
@@ -21,11 +125,49 @@ pub struct AccountLoginResponse {
     account_id: i32,
 }
 
+// The password was right, but the login must be completed with a second factor
+#[derive(Serialize)]
+pub struct AccountLoginChallengeResponse {
+    challenge: String,
+    expiration_date: DateTime<Utc>,
+}
+
+// The email stays the same until the pending one is confirmed
+#[derive(Serialize)]
+pub struct AccountEmailResponse {
+    pending_email: String,
+    expiration_date: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct AccountSession {
+    #[serde(flatten)]
+    session: Session,
+    current: bool,
+}
+
+#[derive(Serialize)]
+pub struct AccountSessionsResponse {
+    sessions: Vec<AccountSession>,
+}
+
+// Receipt of a deleted account
+#[derive(Serialize)]
+pub struct AccountDeletionResponse {
+    account_id: i32,
+    email: String,
+    deletion_date: DateTime<Utc>,
+    deleted_todos: i64,
+    revoked_sessions: usize,
+}
+
 #[derive(Debug)]
 pub enum AccountRegistrationErrors {
     InvalidEmail,
     InvalidPassword,
     EmailExists,
+    Closed,
+    InvalidInvite,
     Server,
     Db,
 }
@@ -35,10 +177,18 @@ impl From<ValidationErrors> for AccountRegistrationErrors {
         match err {
             ValidationErrors::Email => AccountRegistrationErrors::InvalidEmail,
             ValidationErrors::Password => AccountRegistrationErrors::InvalidPassword,
+            // The registrations don't take a locale
+            ValidationErrors::Locale => AccountRegistrationErrors::Server,
         }
     }
 }
 
+impl From<PasswordErrors> for AccountRegistrationErrors {
+    fn from(_err: PasswordErrors) -> AccountRegistrationErrors {
+        AccountRegistrationErrors::Server
+    }
+}
+
 impl std::fmt::Display for AccountRegistrationErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -51,6 +201,8 @@ impl error::ResponseError for AccountRegistrationErrors {
             AccountRegistrationErrors::InvalidEmail => http::StatusCode::UNPROCESSABLE_ENTITY,
             AccountRegistrationErrors::InvalidPassword => http::StatusCode::UNPROCESSABLE_ENTITY,
             AccountRegistrationErrors::EmailExists => http::StatusCode::CONFLICT,
+            AccountRegistrationErrors::Closed => http::StatusCode::FORBIDDEN,
+            AccountRegistrationErrors::InvalidInvite => http::StatusCode::FORBIDDEN,
             AccountRegistrationErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
             AccountRegistrationErrors::Db => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -66,6 +218,10 @@ impl error::ResponseError for AccountRegistrationErrors {
             AccountRegistrationErrors::EmailExists => {
                 ServerResponse::new((), json!({"error": "Such an email already exists"}))
             }
+            AccountRegistrationErrors::Closed => ServerResponse::new((), json!({"error": "Registration is closed"})),
+            AccountRegistrationErrors::InvalidInvite => {
+                ServerResponse::new((), json!({"error": "Missing, expired or used up invite"}))
+            }
             AccountRegistrationErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
             AccountRegistrationErrors::Db => ServerResponse::new((), json!({"error": "Db error"})),
         };
@@ -79,6 +235,15 @@ impl error::ResponseError for AccountRegistrationErrors {
 #[derive(Debug)]
 pub enum AccountLoginErrors {
     InvalidInfo,
+    Unverified,
+    Disabled,
+    InvalidChallenge,
+    InvalidCode,
+    InvalidLink,
+    LinksDisabled,
+    TokensDisabled,
+    // Too many failed attempts. holds the number of seconds after which a new attempt can be made
+    Locked(i64),
     Server,
 }
 
@@ -98,9 +263,28 @@ impl From<error::Error> for AccountLoginErrors {
     }
 }
 
-impl From<RedisError> for AccountLoginErrors {
-    fn from(err: RedisError) -> AccountLoginErrors {
+impl From<SessionErrors> for AccountLoginErrors {
+    fn from(_err: SessionErrors) -> AccountLoginErrors {
+        AccountLoginErrors::Server
+    }
+}
+
+impl From<DbErrors> for AccountLoginErrors {
+    fn from(_err: DbErrors) -> AccountLoginErrors {
+        AccountLoginErrors::Server
+    }
+}
+
+impl From<PasswordErrors> for AccountLoginErrors {
+    fn from(_err: PasswordErrors) -> AccountLoginErrors {
+        AccountLoginErrors::Server
+    }
+}
+
+impl From<JwtErrors> for AccountLoginErrors {
+    fn from(err: JwtErrors) -> AccountLoginErrors {
         match err {
+            JwtErrors::Disabled => AccountLoginErrors::TokensDisabled,
             _ => AccountLoginErrors::Server,
         }
     }
@@ -116,6 +300,14 @@ impl error::ResponseError for AccountLoginErrors {
     fn status_code(&self) -> http::StatusCode {
         match *self {
             AccountLoginErrors::InvalidInfo => http::StatusCode::UNAUTHORIZED,
+            AccountLoginErrors::Unverified => http::StatusCode::FORBIDDEN,
+            AccountLoginErrors::Disabled => http::StatusCode::FORBIDDEN,
+            AccountLoginErrors::InvalidChallenge => http::StatusCode::UNAUTHORIZED,
+            AccountLoginErrors::InvalidCode => http::StatusCode::UNAUTHORIZED,
+            AccountLoginErrors::InvalidLink => http::StatusCode::UNAUTHORIZED,
+            AccountLoginErrors::LinksDisabled => http::StatusCode::NOT_FOUND,
+            AccountLoginErrors::TokensDisabled => http::StatusCode::NOT_FOUND,
+            AccountLoginErrors::Locked(_) => http::StatusCode::TOO_MANY_REQUESTS,
             AccountLoginErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -123,82 +315,1363 @@ impl error::ResponseError for AccountLoginErrors {
     fn error_response(&self) -> actix_web::HttpResponse {
         let response_json = match self {
             AccountLoginErrors::InvalidInfo => ServerResponse::new((), json!({"error": "Wrong email or password"})),
+            AccountLoginErrors::Unverified => ServerResponse::new((), json!({"error": "Email not verified"})),
+            AccountLoginErrors::Disabled => ServerResponse::new((), json!({"error": "Account disabled"})),
+            AccountLoginErrors::InvalidChallenge => {
+                ServerResponse::new((), json!({"error": "Invalid or expired challenge"}))
+            }
+            AccountLoginErrors::InvalidCode => ServerResponse::new((), json!({"error": "Invalid code"})),
+            AccountLoginErrors::InvalidLink => ServerResponse::new((), json!({"error": "Invalid or expired link"})),
+            AccountLoginErrors::LinksDisabled => ServerResponse::new((), json!({"error": "Login links are disabled"})),
+            AccountLoginErrors::TokensDisabled => {
+                ServerResponse::new((), json!({"error": "Token logins are disabled"}))
+            }
+            AccountLoginErrors::Locked(_) => {
+                ServerResponse::new((), json!({"error": "Too many failed attempts. try again later"}))
+            }
             AccountLoginErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
         };
 
-        dev::HttpResponseBuilder::new(self.status_code())
+        let mut response = dev::HttpResponseBuilder::new(self.status_code());
+        if let AccountLoginErrors::Locked(retry_after) = self {
+            response.set_header(http::header::RETRY_AFTER, retry_after.to_string());
+        }
+        response
             .set_header(http::header::CONTENT_TYPE, "application/json")
             .json(response_json)
     }
 }
 
-pub async fn account_register(
-    body: web::Json<AccountRequest>,
-    state: web::Data<AppState>,
-) -> actix_web::Result<actix_web::HttpResponse, AccountRegistrationErrors> {
-    Validator::email(&body.email)?;
-    Validator::password(&body.password)?;
+#[derive(Debug)]
+pub enum AccountLogoutErrors {
+    Server,
+}
 
-    let rows_count = AccountDbExecutor::register(&state.db_pool, &[&body.email, &body.password]).await;
+impl From<SessionErrors> for AccountLogoutErrors {
+    fn from(_err: SessionErrors) -> AccountLogoutErrors {
+        AccountLogoutErrors::Server
+    }
+}
 
-    match rows_count {
-        Ok(_count) => {
-            let response_json = ServerResponse::new((), ());
-            Ok(actix_web::HttpResponse::Ok().json(response_json))
+impl std::fmt::Display for AccountLogoutErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AccountLogoutErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountLogoutErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
-        Err(err) => {
-            warn!(target: "warnings", "Warn: {:?}", err);
+    }
 
-            match err {
-                DbErrors::Runtime => Err(AccountRegistrationErrors::Server),
-                DbErrors::Postgres(err) => match err.code() {
-                    None => Err(AccountRegistrationErrors::Db),
-                    Some(code) => match code.code() {
-                        "23505" => Err(AccountRegistrationErrors::EmailExists),
-                        _ => Err(AccountRegistrationErrors::Db),
-                    },
-                },
-            }
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountLogoutErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountSessionsErrors {
+    NotFound,
+    Server,
+}
+
+impl From<SessionErrors> for AccountSessionsErrors {
+    fn from(_err: SessionErrors) -> AccountSessionsErrors {
+        AccountSessionsErrors::Server
+    }
+}
+
+impl std::fmt::Display for AccountSessionsErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AccountSessionsErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountSessionsErrors::NotFound => http::StatusCode::NOT_FOUND,
+            AccountSessionsErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountSessionsErrors::NotFound => ServerResponse::new((), json!({"error": "Session not found"})),
+            AccountSessionsErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
 }
 
-pub async fn account_login(
-    body: web::Json<AccountRequest>,
-    state: web::Data<AppState>,
-) -> actix_web::Result<actix_web::HttpResponse, AccountLoginErrors> {
-    Validator::email(&body.email)?;
-    Validator::password(&body.password)?;
+#[derive(Debug)]
+pub enum AccountPasswordErrors {
+    InvalidPassword,
+    WrongPassword,
+    InvalidToken,
+    Server,
+}
 
-    let rows = AccountDbExecutor::login(&state.db_pool, &[&body.email, &body.password]).await;
-    match rows {
-        Ok(rows) => {
-            if rows.is_empty() {
-                return Err(AccountLoginErrors::InvalidInfo);
+impl From<ValidationErrors> for AccountPasswordErrors {
+    fn from(_err: ValidationErrors) -> AccountPasswordErrors {
+        AccountPasswordErrors::InvalidPassword
+    }
+}
+
+impl From<DbErrors> for AccountPasswordErrors {
+    fn from(_err: DbErrors) -> AccountPasswordErrors {
+        AccountPasswordErrors::Server
+    }
+}
+
+impl From<SessionErrors> for AccountPasswordErrors {
+    fn from(_err: SessionErrors) -> AccountPasswordErrors {
+        AccountPasswordErrors::Server
+    }
+}
+
+impl From<PasswordErrors> for AccountPasswordErrors {
+    fn from(_err: PasswordErrors) -> AccountPasswordErrors {
+        AccountPasswordErrors::Server
+    }
+}
+
+impl std::fmt::Display for AccountPasswordErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AccountPasswordErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountPasswordErrors::InvalidPassword => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AccountPasswordErrors::WrongPassword => http::StatusCode::FORBIDDEN,
+            AccountPasswordErrors::InvalidToken => http::StatusCode::BAD_REQUEST,
+            AccountPasswordErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountPasswordErrors::InvalidPassword => ServerResponse::new(
+                (),
+                json!({"error": "Invalid password. the password must be at least 8 characters long"}),
+            ),
+            AccountPasswordErrors::WrongPassword => ServerResponse::new((), json!({"error": "Wrong password"})),
+            AccountPasswordErrors::InvalidToken => {
+                ServerResponse::new((), json!({"error": "Invalid or expired token"}))
             }
-            let row = &rows[0];
-            let account_id: i32 = row.get("id");
-            let session_id = uuid::Uuid::new_v4().to_string();
-            let _: () = state.redis_client.lock().await.set(account_id, &session_id).await?;
+            AccountPasswordErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountVerificationErrors {
+    InvalidToken,
+    Server,
+}
+
+impl From<DbErrors> for AccountVerificationErrors {
+    fn from(_err: DbErrors) -> AccountVerificationErrors {
+        AccountVerificationErrors::Server
+    }
+}
 
-            let response_cookie = http::CookieBuilder::new("session_id", session_id)
-                .max_age(MONTH_IN_SECONDS)
-                .secure(false)
-                .same_site(cookie::SameSite::Strict)
-                .http_only(true)
-                .finish();
+impl std::fmt::Display for AccountVerificationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
-            let response_json = ServerResponse::new(AccountLoginResponse { account_id }, ());
-            Ok(actix_web::HttpResponse::Ok()
-                .cookie(response_cookie)
-                .json(response_json))
+impl error::ResponseError for AccountVerificationErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountVerificationErrors::InvalidToken => http::StatusCode::BAD_REQUEST,
+            AccountVerificationErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
-        Err(err) => {
-            warn!(target: "warnings", "Warn: {:?}", err);
+    }
 
-            return Err(AccountLoginErrors::Server);
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountVerificationErrors::InvalidToken => {
+                ServerResponse::new((), json!({"error": "Invalid or expired token"}))
+            }
+            AccountVerificationErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountEmailErrors {
+    InvalidEmail,
+    WrongPassword,
+    EmailExists,
+    InvalidToken,
+    Server,
+}
+
+impl From<DbErrors> for AccountEmailErrors {
+    fn from(_err: DbErrors) -> AccountEmailErrors {
+        AccountEmailErrors::Server
+    }
+}
+
+impl From<PasswordErrors> for AccountEmailErrors {
+    fn from(_err: PasswordErrors) -> AccountEmailErrors {
+        AccountEmailErrors::Server
+    }
+}
+
+impl From<ValidationErrors> for AccountEmailErrors {
+    fn from(_err: ValidationErrors) -> AccountEmailErrors {
+        AccountEmailErrors::InvalidEmail
+    }
+}
+
+impl std::fmt::Display for AccountEmailErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AccountEmailErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountEmailErrors::InvalidEmail => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AccountEmailErrors::WrongPassword => http::StatusCode::FORBIDDEN,
+            AccountEmailErrors::EmailExists => http::StatusCode::CONFLICT,
+            AccountEmailErrors::InvalidToken => http::StatusCode::BAD_REQUEST,
+            AccountEmailErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountEmailErrors::InvalidEmail => ServerResponse::new((), json!({"error": "Invalid email"})),
+            AccountEmailErrors::WrongPassword => ServerResponse::new((), json!({"error": "Wrong password"})),
+            AccountEmailErrors::EmailExists => {
+                ServerResponse::new((), json!({"error": "Such an email already exists"}))
+            }
+            AccountEmailErrors::InvalidToken => ServerResponse::new((), json!({"error": "Invalid or expired token"})),
+            AccountEmailErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountProfileErrors {
+    InvalidDisplayName,
+    InvalidTimezone,
+    InvalidLocale,
+    Server,
+}
+
+impl From<DbErrors> for AccountProfileErrors {
+    fn from(_err: DbErrors) -> AccountProfileErrors {
+        AccountProfileErrors::Server
+    }
+}
+
+impl From<ValidationErrors> for AccountProfileErrors {
+    fn from(_err: ValidationErrors) -> AccountProfileErrors {
+        AccountProfileErrors::InvalidLocale
+    }
+}
+
+impl std::fmt::Display for AccountProfileErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AccountProfileErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountProfileErrors::InvalidDisplayName => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AccountProfileErrors::InvalidTimezone => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AccountProfileErrors::InvalidLocale => http::StatusCode::UNPROCESSABLE_ENTITY,
+            AccountProfileErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountProfileErrors::InvalidDisplayName => ServerResponse::new(
+                (),
+                json!({"error": "Invalid display name. the name must be at most 50 characters long"}),
+            ),
+            AccountProfileErrors::InvalidTimezone => ServerResponse::new(
+                (),
+                json!({"error": "Invalid timezone. the timezone must be an IANA name, such as Europe/Moscow"}),
+            ),
+            AccountProfileErrors::InvalidLocale => ServerResponse::new(
+                (),
+                json!({"error": "Invalid locale. the locale must be a language tag, such as en or pt-BR"}),
+            ),
+            AccountProfileErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountDeletionErrors {
+    WrongPassword,
+    NotFound,
+    Server,
+}
+
+impl From<DbErrors> for AccountDeletionErrors {
+    fn from(_err: DbErrors) -> AccountDeletionErrors {
+        AccountDeletionErrors::Server
+    }
+}
+
+impl From<SessionErrors> for AccountDeletionErrors {
+    fn from(_err: SessionErrors) -> AccountDeletionErrors {
+        AccountDeletionErrors::Server
+    }
+}
+
+impl From<PasswordErrors> for AccountDeletionErrors {
+    fn from(_err: PasswordErrors) -> AccountDeletionErrors {
+        AccountDeletionErrors::Server
+    }
+}
+
+impl std::fmt::Display for AccountDeletionErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AccountDeletionErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AccountDeletionErrors::WrongPassword => http::StatusCode::FORBIDDEN,
+            AccountDeletionErrors::NotFound => http::StatusCode::NOT_FOUND,
+            AccountDeletionErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AccountDeletionErrors::WrongPassword => ServerResponse::new((), json!({"error": "Wrong password"})),
+            AccountDeletionErrors::NotFound => ServerResponse::new((), json!({"error": "Account not found"})),
+            AccountDeletionErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// Emails a new verification link to the account, unless there is no such account or it's already verified
+async fn send_verification_email(state: &AppState, email: &str) -> Result<(), DbErrors> {
+    let token = uuid::Uuid::new_v4().to_simple().to_string();
+    let verification_timeout = state.settings.account.verification_timeout;
+    let current_date = Utc::now();
+    let expiration_date = current_date + verification_timeout;
+
+    let rows_count = AccountDbExecutor::create_verification_token(
+        &state.db_pool,
+        &[&email, &tokens::hash_token(&token), &current_date, &expiration_date],
+    )
+    .await?;
+    if rows_count == 0 {
+        return Ok(());
+    }
+
+    let email = Email::new(
+        email.to_string(),
+        "Verify your email".to_string(),
+        format!(
+            "Verify your email by opening the following link: {}/api/account/verify?token={}\n\n\
+            The link expires in {} hours.",
+            state.settings.mailer.public_url,
+            token,
+            verification_timeout.num_hours()
+        ),
+    );
+    if let Err(err) = state.mailer.send(&email).await {
+        warn!(target: "warnings", "Warn: {:?}", err);
+    }
+
+    Ok(())
+}
+
+pub async fn account_register(
+    body: web::Json<AccountRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountRegistrationErrors> {
+    let registration_mode = state.settings.account.registration_mode;
+    if registration_mode == RegistrationMode::Closed {
+        return Err(AccountRegistrationErrors::Closed);
+    }
+    Validator::email(&body.email)?;
+    Validator::password(&body.password)?;
+    let invite = match registration_mode {
+        RegistrationMode::InviteOnly => Some(body.invite.as_deref().ok_or(AccountRegistrationErrors::InvalidInvite)?),
+        _ => None,
+    };
+
+    let password_hash = account_passwords::hash_password(&body.password, &state.settings.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountRegistrationErrors::from(err)
+        })?;
+    let rows_count = match invite {
+        Some(invite) => {
+            AccountDbExecutor::register_with_invite(
+                &state.db_pool,
+                &[
+                    &body.email,
+                    &password_hash,
+                    &tokens::hash_token(invite.trim()),
+                    &Utc::now(),
+                ],
+            )
+            .await
+        }
+        None => AccountDbExecutor::register(&state.db_pool, &[&body.email, &password_hash]).await,
+    };
+
+    match rows_count {
+        Ok(0) => Err(AccountRegistrationErrors::InvalidInvite),
+        Ok(_count) => {
+            // The account exists at this point. if the email couldn't be sent, a new one can be asked for
+            if let Err(err) = send_verification_email(&state, &body.email).await {
+                warn!(target: "warnings", "Warn: {:?}", err);
+            }
+
+            let response_json = ServerResponse::new((), ());
+            Ok(actix_web::HttpResponse::Ok().json(response_json))
+        }
+        Err(err) => {
+            warn!(target: "warnings", "Warn: {:?}", err);
+
+            match err {
+                DbErrors::Runtime => Err(AccountRegistrationErrors::Server),
+                DbErrors::Postgres(err) => match err.code() {
+                    None => Err(AccountRegistrationErrors::Db),
+                    Some(code) => match code.code() {
+                        "23505" => Err(AccountRegistrationErrors::EmailExists),
+                        _ => Err(AccountRegistrationErrors::Db),
+                    },
+                },
+            }
+        }
+    }
+}
+
+// The failed logins are counted per email, against password guessing, and per ip, against trying a few passwords on
+// many accounts. returns the keys of the counters along with their number of allowed attempts
+fn login_failure_keys(email: &str, request: &HttpRequest, settings: &Settings) -> Vec<(String, i32)> {
+    let mut keys = vec![(
+        format!("email:{}", email.to_lowercase()),
+        settings.account.login_attempts,
+    )];
+    if let Some(ip) = requests::client_ip(request.peer_addr(), request.headers(), &settings.proxy.trusted_proxies) {
+        keys.push((format!("ip:{}", ip), settings.account.login_ip_attempts));
+    }
+
+    keys
+}
+
+async fn check_login_lockout(state: &AppState, keys: &[(String, i32)]) -> Result<(), AccountLoginErrors> {
+    let settings = &state.settings.account;
+    let current_date = Utc::now();
+
+    for (key, max_attempts) in keys {
+        let failures = state.session_store.get_login_failures(key).await.map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountLoginErrors::from(err)
+        })?;
+        let locked_until = failures
+            .and_then(|failures| {
+                failures.locked_until(*max_attempts, settings.login_lockout, settings.login_max_lockout)
+            })
+            .filter(|locked_until| *locked_until > current_date);
+
+        if let Some(locked_until) = locked_until {
+            // Rounded up, so that a client which waits for that long isn't refused again
+            let retry_after = ((locked_until - current_date).num_milliseconds() + 999) / 1000;
+            return Err(AccountLoginErrors::Locked(retry_after));
+        }
+    }
+
+    Ok(())
+}
+
+// The failure is recorded in the history of the account, if the email belongs to one, along with the lockout it
+// caused. the email's key is the first one
+async fn record_login_failure(
+    state: &AppState,
+    request: &HttpRequest,
+    keys: &[(String, i32)],
+    account_id: Option<i32>,
+) -> Result<(), AccountLoginErrors> {
+    let current_date = Utc::now();
+
+    let mut locked = false;
+    for (index, (key, max_attempts)) in keys.iter().enumerate() {
+        let failures = state
+            .session_store
+            .record_login_failure(key, current_date, state.settings.account.login_max_lockout)
+            .await
+            .map_err(|err| {
+                warn!(target: "warnings", "Warn: {:?}", err);
+                AccountLoginErrors::from(err)
+            })?;
+        locked |= index == 0 && failures.count() >= *max_attempts;
+    }
+
+    if let Some(account_id) = account_id {
+        record_auth_event(state, request, account_id, AuthEventKind::LoginFailure).await;
+        if locked {
+            record_auth_event(state, request, account_id, AuthEventKind::Lockout).await;
+        }
+    }
+
+    Ok(())
+}
+
+// Creates the session of a logged in account and responds with its cookie
+pub async fn start_session(
+    request: &HttpRequest,
+    state: &AppState,
+    account_id: i32,
+    device: Option<String>,
+) -> Result<actix_web::HttpResponse, SessionErrors> {
+    let session_settings = &state.settings.session;
+    let current_date = Utc::now();
+    let session = Session::new(
+        uuid::Uuid::new_v4().to_string(),
+        account_id,
+        device,
+        requests::user_agent(request.headers()),
+        requests::client_ip(
+            request.peer_addr(),
+            request.headers(),
+            &state.settings.proxy.trusted_proxies,
+        ),
+        current_date,
+        current_date + session_settings.absolute_timeout,
+    );
+    state
+        .session_store
+        .create(&session, session_settings.idle_timeout)
+        .await?;
+
+    // The csrf cookie lives as long as the session cookie. the auth middleware re-issues both of them
+    let cookie_settings = &state.settings.cookie;
+    let max_age = (session.idle_expiration_date(session_settings.idle_timeout) - current_date).num_seconds();
+    let session_cookie = cookies::session_cookie(session.id().to_string(), max_age, cookie_settings);
+    let csrf_cookie = cookies::csrf_cookie(
+        csrf::csrf_token(&cookie_settings.csrf_secret, session.id()),
+        max_age,
+        cookie_settings,
+    );
+
+    record_auth_event(state, request, account_id, AuthEventKind::LoginSuccess).await;
+
+    let response_json = ServerResponse::new(AccountLoginResponse { account_id }, ());
+    Ok(actix_web::HttpResponse::Ok()
+        .cookie(session_cookie)
+        .cookie(csrf_cookie)
+        .json(response_json))
+}
+
+// No session is created until the second factor is checked by account_login_two_factor
+pub async fn start_two_factor_challenge(
+    state: &AppState,
+    account_id: i32,
+    device: &Option<String>,
+) -> Result<actix_web::HttpResponse, AccountLoginErrors> {
+    let challenge = uuid::Uuid::new_v4().to_simple().to_string();
+    let current_date = Utc::now();
+    let expiration_date = current_date + state.settings.two_factor.challenge_timeout;
+
+    TwoFactorDbExecutor::create_challenge(
+        &state.db_pool,
+        &[
+            &tokens::hash_token(&challenge),
+            &account_id,
+            device,
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        AccountLoginErrors::from(err)
+    })?;
+
+    let response_json = ServerResponse::new(
+        AccountLoginChallengeResponse {
+            challenge,
+            expiration_date,
+        },
+        (),
+    );
+    Ok(actix_web::HttpResponse::Accepted().json(response_json))
+}
+
+pub async fn account_login(
+    request: HttpRequest,
+    body: web::Json<AccountLoginRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountLoginErrors> {
+    Validator::email(&body.email)?;
+    Validator::password(&body.password)?;
+    if body.mode == Some(LoginMode::Token) && state.settings.jwt.secret.is_none() {
+        return Err(AccountLoginErrors::TokensDisabled);
+    }
+
+    // Locked out emails and ips are refused before the password is checked, whether it's right or not
+    let failure_keys = login_failure_keys(&body.email, &request, &state.settings);
+    check_login_lockout(&state, &failure_keys).await?;
+
+    let rows = AccountDbExecutor::login(&state.db_pool, &[&body.email]).await;
+    match rows {
+        Ok(rows) => {
+            let password_matches = match rows.first() {
+                Some(row) => {
+                    account_passwords::check_password(&state, row.get("id"), &body.password, row.get("password")).await
+                }
+                // Hashes the password anyway, so that the response time doesn't tell which emails are registered
+                None => account_passwords::hash_password(&body.password, &state.settings.password)
+                    .await
+                    .map(|_hash| false),
+            }
+            .map_err(|err| {
+                warn!(target: "warnings", "Warn: {:?}", err);
+                AccountLoginErrors::from(err)
+            })?;
+            if !password_matches {
+                let account_id = rows.first().map(|row| row.get("id"));
+                record_login_failure(&state, &request, &failure_keys, account_id).await?;
+                return Err(AccountLoginErrors::InvalidInfo);
+            }
+            let row = &rows[0];
+            let account_id: i32 = row.get("id");
+            let verified: bool = row.get("verified");
+            if !verified && state.settings.account.verification_policy == VerificationPolicy::Login {
+                return Err(AccountLoginErrors::Unverified);
+            }
+            let disabled: bool = row.get("disabled");
+            if disabled {
+                return Err(AccountLoginErrors::Disabled);
+            }
+
+            let two_factor: bool = row.get("two_factor");
+            if two_factor {
+                return start_two_factor_challenge(&state, account_id, &body.device).await;
+            }
+
+            // Only a completed login forgives the email's failures. the ip's ones are left to expire
+            state.session_store.clear_login_failures(&failure_keys[0].0).await?;
+            match body.mode.unwrap_or(LoginMode::Cookie) {
+                LoginMode::Cookie => Ok(start_session(&request, &state, account_id, body.device.clone()).await?),
+                LoginMode::Token => Ok(start_token_session(&request, &state, account_id, body.device.clone()).await?),
+            }
+        }
+        Err(err) => {
+            warn!(target: "warnings", "Warn: {:?}", err);
+
+            return Err(AccountLoginErrors::Server);
+        }
+    }
+}
+
+// Like the forgotten password, responds the same way whether the email belongs to an account or not
+pub async fn account_login_link(
+    body: web::Json<AccountLoginLinkRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountLoginErrors> {
+    let account_settings = &state.settings.account;
+    if !account_settings.login_links {
+        return Err(AccountLoginErrors::LinksDisabled);
+    }
+    if Validator::email(&body.email).is_err() {
+        let response_json = ServerResponse::new((), ());
+        return Ok(actix_web::HttpResponse::Ok().json(response_json));
+    }
+
+    let token = uuid::Uuid::new_v4().to_simple().to_string();
+    let current_date = Utc::now();
+    let expiration_date = current_date + account_settings.login_link_timeout;
+    let rows_count = AccountDbExecutor::create_login_link_token(
+        &state.db_pool,
+        &[
+            &body.email,
+            &tokens::hash_token(&token),
+            &body.device,
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        AccountLoginErrors::from(err)
+    })?;
+
+    if rows_count > 0 {
+        let email = Email::new(
+            body.email.clone(),
+            "Your login link".to_string(),
+            format!(
+                "Login by opening the following link: {}/api/account/login/link/{}\n\n\
+                The link can be used once and expires in {} minutes. If you didn't ask for it, ignore this email.",
+                state.settings.mailer.public_url,
+                token,
+                account_settings.login_link_timeout.num_minutes()
+            ),
+        );
+        if let Err(err) = state.mailer.send(&email).await {
+            warn!(target: "warnings", "Warn: {:?}", err);
+        }
+    }
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// The link replaces the password, the second factor is still asked for
+pub async fn account_login_link_use(
+    request: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountLoginErrors> {
+    if !state.settings.account.login_links {
+        return Err(AccountLoginErrors::LinksDisabled);
+    }
+
+    let token_hash = tokens::hash_token(&path.into_inner());
+    let rows = AccountDbExecutor::use_login_link_token(&state.db_pool, &[&token_hash, &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountLoginErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(AccountLoginErrors::InvalidLink)?;
+    let account_id: i32 = row.get("id");
+    let device: Option<String> = row.get("device");
+
+    let two_factor: bool = row.get("two_factor");
+    if two_factor {
+        return start_two_factor_challenge(&state, account_id, &device).await;
+    }
+
+    Ok(start_session(&request, &state, account_id, device).await?)
+}
+
+pub async fn account_login_two_factor(
+    request: HttpRequest,
+    body: web::Json<AccountLoginTwoFactorRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountLoginErrors> {
+    if body.mode == Some(LoginMode::Token) && state.settings.jwt.secret.is_none() {
+        return Err(AccountLoginErrors::TokensDisabled);
+    }
+
+    let challenge_hash = tokens::hash_token(&body.challenge);
+    let current_date = Utc::now();
+    let rows = TwoFactorDbExecutor::get_challenge(&state.db_pool, &[&challenge_hash, &current_date])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountLoginErrors::from(err)
+        })?;
+    let (account_id, device, email): (i32, Option<String>, String) = match rows.first() {
+        Some(row) => (row.get("account_id"), row.get("device"), row.get("email")),
+        None => return Err(AccountLoginErrors::InvalidChallenge),
+    };
+
+    // The wrong codes count as failed logins too, otherwise new challenges could be asked for to keep guessing
+    let failure_keys = login_failure_keys(&email, &request, &state.settings);
+    check_login_lockout(&state, &failure_keys).await?;
+
+    let rows = TwoFactorDbExecutor::get(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountLoginErrors::from(err)
+        })?;
+    let totp = rows
+        .first()
+        .filter(|row| row.get::<_, bool>("enabled"))
+        .and_then(|row| Totp::from_base32(row.get("secret")))
+        .ok_or(AccountLoginErrors::InvalidChallenge)?;
+
+    // A code is only accepted once, even within its time step, so that an observed code can't be replayed
+    let rows_count = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => match totp.verify(code, current_date.timestamp()) {
+            Some(step) => TwoFactorDbExecutor::use_step(&state.db_pool, &[&account_id, &step]).await,
+            None => Ok(0),
+        },
+        (None, Some(recovery_code)) => {
+            let code_hash = tokens::hash_token(&normalize_recovery_code(recovery_code));
+            TwoFactorDbExecutor::use_recovery_code(&state.db_pool, &[&account_id, &code_hash]).await
+        }
+        (None, None) => Ok(0),
+    }
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        AccountLoginErrors::from(err)
+    })?;
+
+    if rows_count == 0 {
+        record_login_failure(&state, &request, &failure_keys, Some(account_id)).await?;
+
+        let max_attempts = state.settings.two_factor.challenge_attempts;
+        TwoFactorDbExecutor::fail_challenge(&state.db_pool, &[&challenge_hash, &max_attempts])
+            .await
+            .map_err(|err| {
+                warn!(target: "warnings", "Warn: {:?}", err);
+                AccountLoginErrors::from(err)
+            })?;
+
+        return Err(AccountLoginErrors::InvalidCode);
+    }
+
+    let rows_count = TwoFactorDbExecutor::consume_challenge(&state.db_pool, &[&challenge_hash])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountLoginErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(AccountLoginErrors::InvalidChallenge);
+    }
+
+    state.session_store.clear_login_failures(&failure_keys[0].0).await?;
+    match body.mode.unwrap_or(LoginMode::Cookie) {
+        LoginMode::Cookie => Ok(start_session(&request, &state, account_id, device).await?),
+        LoginMode::Token => Ok(start_token_session(&request, &state, account_id, device).await?),
+    }
+}
+
+pub async fn account_logout(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountLogoutErrors> {
+    // Requests made with an api token have no session to end
+    if let Some(session_id) = account.session_id() {
+        state.session_store.revoke(account.account_id(), session_id).await?;
+        record_auth_event(&state, &request, account.account_id(), AuthEventKind::Logout).await;
+    }
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok()
+        .del_cookie(&cookies::removed_session_cookie(&state.settings.cookie))
+        .del_cookie(&cookies::removed_csrf_cookie(&state.settings.cookie))
+        .json(response_json))
+}
+
+pub async fn account_sessions(
+    account: AuthenticatedAccount,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountSessionsErrors> {
+    let idle_timeout = state.settings.session.idle_timeout;
+    let current_date = Utc::now();
+
+    let mut sessions = state.session_store.list_for_account(account.account_id()).await?;
+    sessions.sort_by_key(|session| Reverse(session.last_seen_date()));
+
+    let sessions = sessions
+        .into_iter()
+        .filter(|session| !session.is_expired(current_date, idle_timeout))
+        .map(|session| AccountSession {
+            current: Some(session.id()) == account.session_id(),
+            session,
+        })
+        .collect();
+
+    let response_json = ServerResponse::new(AccountSessionsResponse { sessions }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn account_session_revoke(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountSessionsErrors> {
+    let account_id = account.account_id();
+    let revoked_session_id = path.into_inner();
+
+    // Sessions of other accounts are reported as missing
+    match state.session_store.get(&revoked_session_id).await? {
+        Some(session) if session.account_id() == account_id => (),
+        _ => return Err(AccountSessionsErrors::NotFound),
+    };
+    state.session_store.revoke(account_id, &revoked_session_id).await?;
+    record_auth_event(&state, &request, account_id, AuthEventKind::SessionRevocation).await;
+
+    let response_json = ServerResponse::new((), ());
+    let mut response = actix_web::HttpResponse::Ok();
+    if Some(revoked_session_id.as_str()) == account.session_id() {
+        response
+            .del_cookie(&cookies::removed_session_cookie(&state.settings.cookie))
+            .del_cookie(&cookies::removed_csrf_cookie(&state.settings.cookie));
+    }
+
+    Ok(response.json(response_json))
+}
+
+pub async fn account_password(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    body: web::Json<AccountPasswordRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountPasswordErrors> {
+    Validator::password(&body.new_password)?;
+
+    let account_id = account.account_id();
+    let rows = AccountDbExecutor::get_password(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    let current_hash: String = rows
+        .first()
+        .ok_or(AccountPasswordErrors::WrongPassword)?
+        .get("password");
+    let password_matches = account_passwords::verify_password(&body.current_password, &current_hash)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    if !password_matches {
+        return Err(AccountPasswordErrors::WrongPassword);
+    }
+
+    let new_hash = account_passwords::hash_password(&body.new_password, &state.settings.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    // The hash changed in between if another request changed the password first
+    let rows_count = AccountDbExecutor::change_password(&state.db_pool, &[&account_id, &current_hash, &new_hash])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(AccountPasswordErrors::WrongPassword);
+    }
+
+    // Whoever knew the old password is logged out everywhere except for the device which changed it. a change made
+    // with an api token logs out every session. the logins with tokens are all ended, they can't tell which one
+    // made the change
+    state.session_store.revoke_all(account_id, account.session_id()).await?;
+    JwtDbExecutor::revoke_all(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    record_auth_event(&state, &request, account_id, AuthEventKind::PasswordChange).await;
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// Responds the same way whether the email belongs to an account or not, so that it can't be used to find out
+// which emails are registered
+pub async fn account_password_forgot(
+    body: web::Json<AccountPasswordForgotRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountPasswordErrors> {
+    let response_json = ServerResponse::new((), ());
+    if Validator::email(&body.email).is_err() {
+        return Ok(actix_web::HttpResponse::Ok().json(response_json));
+    }
+
+    let token = uuid::Uuid::new_v4().to_simple().to_string();
+    let reset_timeout = state.settings.account.password_reset_timeout;
+    let current_date = Utc::now();
+    let expiration_date = current_date + reset_timeout;
+
+    let rows_count = AccountDbExecutor::create_password_reset_token(
+        &state.db_pool,
+        &[
+            &body.email,
+            &tokens::hash_token(&token),
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        AccountPasswordErrors::from(err)
+    })?;
+
+    if rows_count > 0 {
+        let email = Email::new(
+            body.email.clone(),
+            "Reset your password".to_string(),
+            format!(
+                "Use the following token to reset your password: {}\n\n\
+                The token expires in {} minutes. if you didn't ask to reset your password, ignore this email.",
+                token,
+                reset_timeout.num_minutes()
+            ),
+        );
+        // A failure isn't reported to the client, it would tell that the account exists
+        if let Err(err) = state.mailer.send(&email).await {
+            warn!(target: "warnings", "Warn: {:?}", err);
+        }
+    }
+
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn account_password_reset(
+    request: HttpRequest,
+    body: web::Json<AccountPasswordResetRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountPasswordErrors> {
+    Validator::password(&body.password)?;
+
+    let password_hash = account_passwords::hash_password(&body.password, &state.settings.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    let token_hash = tokens::hash_token(&body.token);
+    let rows = AccountDbExecutor::reset_password(&state.db_pool, &[&token_hash, &password_hash, &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    let account_id: i32 = match rows.first() {
+        Some(row) => row.get("id"),
+        None => return Err(AccountPasswordErrors::InvalidToken),
+    };
+
+    // The password may have been reset because the account was compromised
+    state.session_store.revoke_all(account_id, None).await?;
+    JwtDbExecutor::revoke_all(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountPasswordErrors::from(err)
+        })?;
+    record_auth_event(&state, &request, account_id, AuthEventKind::PasswordReset).await;
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn account_verify(
+    query: web::Query<AccountVerifyQuery>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountVerificationErrors> {
+    let token_hash = tokens::hash_token(&query.token);
+    let rows = AccountDbExecutor::verify(&state.db_pool, &[&token_hash, &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountVerificationErrors::from(err)
+        })?;
+    if rows.is_empty() {
+        return Err(AccountVerificationErrors::InvalidToken);
+    }
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// Like the forgotten password, responds the same way whether the email belongs to an unverified account or not
+pub async fn account_verification_resend(
+    body: web::Json<AccountVerificationResendRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountVerificationErrors> {
+    if Validator::email(&body.email).is_ok() {
+        send_verification_email(&state, &body.email).await.map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountVerificationErrors::from(err)
+        })?;
+    }
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// The new email is only used once it's confirmed through the link sent to it
+pub async fn account_email(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    body: web::Json<AccountEmailRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountEmailErrors> {
+    Validator::email(&body.email)?;
+
+    let account_id = account.account_id();
+    let password_matches = account_passwords::check_account_password(&state, account_id, &body.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountEmailErrors::from(err)
+        })?;
+    if !password_matches {
+        return Err(AccountEmailErrors::WrongPassword);
+    }
+    // Checked again by the unique constraint when the email is swapped in
+    let email_exists = AccountDbExecutor::email_exists(&state.db_pool, &[&body.email])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountEmailErrors::from(err)
+        })?;
+    if email_exists {
+        return Err(AccountEmailErrors::EmailExists);
+    }
+
+    let token = uuid::Uuid::new_v4().to_simple().to_string();
+    let email_change_timeout = state.settings.account.email_change_timeout;
+    let current_date = Utc::now();
+    let expiration_date = current_date + email_change_timeout;
+    AccountDbExecutor::create_email_change_token(
+        &state.db_pool,
+        &[
+            &account_id,
+            &body.email,
+            &tokens::hash_token(&token),
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        AccountEmailErrors::from(err)
+    })?;
+
+    record_auth_event(&state, &request, account_id, AuthEventKind::EmailChangeRequest).await;
+
+    let email = Email::new(
+        body.email.clone(),
+        "Confirm your new email".to_string(),
+        format!(
+            "Confirm your new email by opening the following link: {}/api/account/email/confirm?token={}\n\n\
+            The link expires in {} hours.",
+            state.settings.mailer.public_url,
+            token,
+            email_change_timeout.num_hours()
+        ),
+    );
+    if let Err(err) = state.mailer.send(&email).await {
+        warn!(target: "warnings", "Warn: {:?}", err);
+    }
+
+    let response_json = ServerResponse::new(
+        AccountEmailResponse {
+            pending_email: body.email.clone(),
+            expiration_date,
+        },
+        (),
+    );
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// The previous email is told about the change, in case it wasn't made by its owner
+pub async fn account_email_confirm(
+    request: HttpRequest,
+    query: web::Query<AccountEmailConfirmQuery>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountEmailErrors> {
+    let token_hash = tokens::hash_token(&query.token);
+    let rows = AccountDbExecutor::change_email(&state.db_pool, &[&token_hash, &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+
+            match &err {
+                DbErrors::Postgres(db_err) if db_err.code().map(|code| code.code()) == Some("23505") => {
+                    AccountEmailErrors::EmailExists
+                }
+                _ => AccountEmailErrors::from(err),
+            }
+        })?;
+    let row = rows.first().ok_or(AccountEmailErrors::InvalidToken)?;
+    let account_id: i32 = row.get("id");
+    let new_email: String = row.get("email");
+    let previous_email: String = row.get("previous_email");
+
+    let email = Email::new(
+        previous_email,
+        "Your email was changed".to_string(),
+        format!(
+            "The email of your account was changed to {}. If you didn't change it, reset your password and \
+            contact us.",
+            new_email
+        ),
+    );
+    if let Err(err) = state.mailer.send(&email).await {
+        warn!(target: "warnings", "Warn: {:?}", err);
+    }
+    record_auth_event(&state, &request, account_id, AuthEventKind::EmailChange).await;
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn account_profile(
+    account: AuthenticatedAccount,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountProfileErrors> {
+    let rows = AccountDbExecutor::get_profile(&state.db_pool, &[&account.account_id()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountProfileErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(AccountProfileErrors::Server)?;
+
+    let response_json = ServerResponse::new(AccountProfile::from_row(row), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// Only the fields which are given are changed
+pub async fn account_profile_update(
+    account: AuthenticatedAccount,
+    body: web::Json<AccountProfileRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountProfileErrors> {
+    let display_name = body.display_name.as_deref().map(str::trim);
+    if let Some(display_name) = display_name {
+        if display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+            return Err(AccountProfileErrors::InvalidDisplayName);
+        }
+    }
+    if let Some(locale) = &body.locale {
+        Validator::locale(locale)?;
+    }
+    if let Some(timezone) = &body.timezone {
+        let timezone_exists = AccountDbExecutor::timezone_exists(&state.db_pool, &[timezone])
+            .await
+            .map_err(|err| {
+                warn!(target: "warnings", "Warn: {:?}", err);
+                AccountProfileErrors::from(err)
+            })?;
+        if !timezone_exists {
+            return Err(AccountProfileErrors::InvalidTimezone);
+        }
+    }
+
+    let week_start = body.week_start.map(|week_start| week_start.as_str());
+    let todo_sort = body.todo_sort.map(|todo_sort| todo_sort.as_str());
+    let rows = AccountDbExecutor::update_profile(
+        &state.db_pool,
+        &[
+            &account.account_id(),
+            &display_name,
+            &body.timezone,
+            &body.locale,
+            &week_start,
+            &todo_sort,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        AccountProfileErrors::from(err)
+    })?;
+    let row = rows.first().ok_or(AccountProfileErrors::Server)?;
+
+    let response_json = ServerResponse::new(AccountProfile::from_row(row), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn account_delete(
+    account: AuthenticatedAccount,
+    body: web::Json<AccountDeletionRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AccountDeletionErrors> {
+    let account_id = account.account_id();
+    let password_matches = account_passwords::check_account_password(&state, account_id, &body.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountDeletionErrors::from(err)
+        })?;
+    if !password_matches {
+        return Err(AccountDeletionErrors::WrongPassword);
+    }
+    let sessions = state.session_store.list_for_account(account_id).await?;
+
+    let rows = AccountDbExecutor::delete(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AccountDeletionErrors::from(err)
+        })?;
+    // The password was right, so the account was deleted in between by another request
+    let row = rows.first().ok_or(AccountDeletionErrors::NotFound)?;
+
+    // The postgres store's sessions are removed by the cascade, the other stores are cleaned up here. the account is
+    // gone already, so a failure doesn't fail the request
+    if let Err(err) = state.session_store.revoke_all(account_id, None).await {
+        warn!(target: "warnings", "Warn: {:?}", err);
+    }
+
+    let response_json = ServerResponse::new(
+        AccountDeletionResponse {
+            account_id,
+            email: row.get("email"),
+            deletion_date: Utc::now(),
+            deleted_todos: row.get("todos_count"),
+            revoked_sessions: sessions.len(),
+        },
+        (),
+    );
+    Ok(actix_web::HttpResponse::Ok()
+        .del_cookie(&cookies::removed_session_cookie(&state.settings.cookie))
+        .del_cookie(&cookies::removed_csrf_cookie(&state.settings.cookie))
+        .json(response_json))
 }
 
 pub async fn accounts_reset(
@@ -1,7 +1,101 @@
+use crate::todos::todo_models::TodoSort;
 use crate::DbErrors;
 use deadpool_postgres::Pool;
 use postgres::types::ToSql;
 use postgres::Row;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountRole {
+    User,
+    // Can manage the other accounts through the admin api
+    Admin,
+}
+
+impl AccountRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountRole::User => "user",
+            AccountRole::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for AccountRole {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "user" => Ok(AccountRole::User),
+            "admin" => Ok(AccountRole::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekStart {
+    Monday,
+    Saturday,
+    Sunday,
+}
+
+impl WeekStart {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeekStart::Monday => "monday",
+            WeekStart::Saturday => "saturday",
+            WeekStart::Sunday => "sunday",
+        }
+    }
+}
+
+impl FromStr for WeekStart {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "monday" => Ok(WeekStart::Monday),
+            "saturday" => Ok(WeekStart::Saturday),
+            "sunday" => Ok(WeekStart::Sunday),
+            _ => Err(()),
+        }
+    }
+}
+
+// What the account shows and how it likes its dates. the timezone is an IANA name, such as `Europe/Moscow`
+#[derive(Serialize, Debug)]
+pub struct AccountProfile {
+    id: i32,
+    email: String,
+    verified: bool,
+    display_name: Option<String>,
+    timezone: String,
+    locale: String,
+    week_start: WeekStart,
+    todo_sort: TodoSort,
+}
+
+impl AccountProfile {
+    pub fn from_row(row: &Row) -> Self {
+        let week_start: String = row.get("week_start");
+        let todo_sort: String = row.get("todo_sort");
+
+        AccountProfile {
+            id: row.get("id"),
+            email: row.get("email"),
+            verified: row.get("verified"),
+            display_name: row.get("display_name"),
+            timezone: row.get("timezone"),
+            locale: row.get("locale"),
+            week_start: week_start.parse().unwrap_or(WeekStart::Monday),
+            todo_sort: todo_sort.parse().unwrap_or(TodoSort::LastEditDate),
+        }
+    }
+}
 
 pub struct AccountDbExecutor;
 
@@ -9,10 +103,37 @@ impl AccountDbExecutor {
     pub async fn register(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
         let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute("INSERT INTO account (email, password) VALUES ($1, $2)", params)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Takes one of the invite's uses along with the registration, so that a failed registration doesn't waste it. the
+    // params are the email, the password hash, the invite code's hash and the current date. returns 0 without registering
+    // when the invite can't be used
+    pub async fn register_with_invite(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            UPDATE invite SET uses = uses + 1
+            WHERE code_hash = $1 AND uses < max_uses AND expiration_date > $2
+            RETURNING id",
+                &params[2..],
+            )
+            .await?;
+        let invite_id: i32 = match rows.first() {
+            Some(row) => row.get("id"),
+            None => return Ok(0),
+        };
         let count = transaction
             .execute(
-                "INSERT INTO account (email, password) VALUES ($1, crypt($2, gen_salt('bf')))",
-                params,
+                "INSERT INTO account (email, password, invite_id) VALUES ($1, $2, $3)",
+                &[params[0], params[1], &invite_id],
             )
             .await?;
         transaction.commit().await?;
@@ -20,12 +141,346 @@ impl AccountDbExecutor {
         Ok(count)
     }
 
+    // The password hash is checked by the caller, see `account_passwords`
     pub async fn login(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, password, verified, disabled, EXISTS(
+                SELECT 1 FROM two_factor WHERE two_factor.account_id = account.id AND enabled
+            ) AS two_factor
+            FROM account
+            WHERE email = $1",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn get(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "SELECT id, email, verified, role, disabled FROM account WHERE id = $1",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn get_profile(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, email, verified, display_name, timezone, locale, week_start, todo_sort
+            FROM account
+            WHERE id = $1",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // The fields which are NULL are left as they are. an empty display name removes it
+    pub async fn update_profile(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            UPDATE account
+            SET display_name = CASE WHEN $2::TEXT IS NULL THEN display_name ELSE NULLIF($2, '') END,
+                timezone = COALESCE($3, timezone),
+                locale = COALESCE($4, locale),
+                week_start = COALESCE($5, week_start),
+                todo_sort = COALESCE($6, todo_sort)
+            WHERE id = $1
+            RETURNING id, email, verified, display_name, timezone, locale, week_start, todo_sort",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // The timezones are the ones postgres knows of, since it's the one computing the dates in them
+    pub async fn timezone_exists(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<bool, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let row = db_client
+            .query_one(
+                "SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1) AS exists",
+                params,
+            )
+            .await?;
+
+        Ok(row.get("exists"))
+    }
+
+    pub async fn get_password(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query("SELECT password FROM account WHERE id = $1", params)
+            .await?;
+
+        Ok(rows)
+    }
+
+    // Replaces the password hash only if it's still the one which was checked, so that a concurrent change isn't
+    // overwritten. returns the number of updated rows
+    pub async fn change_password(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "UPDATE account SET password = $3 WHERE id = $1 AND password = $2",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Replaces the account's previous reset tokens with a new one. the params hold the token's hash, see
+    // `tokens::hash_token`. returns the number of created tokens, which is 0 if there is no account with that email
+    pub async fn create_password_reset_token(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM password_reset_token
+                WHERE expiration_date < $3 OR account_id IN (SELECT id FROM account WHERE email = $1)
+            )
+            INSERT INTO password_reset_token (token_hash, account_id, creation_date, expiration_date)
+            SELECT $2, id, $3, $4 FROM account WHERE email = $1",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Consumes the token and sets the new password of its account. returns the account's id, or nothing if the
+    // token doesn't exist or has expired
+    pub async fn reset_password(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            WITH used_token AS (
+                DELETE FROM password_reset_token
+                WHERE token_hash = $1 AND expiration_date > $3
+                RETURNING account_id
+            )
+            UPDATE account SET password = $2
+            FROM used_token
+            WHERE account.id = used_token.account_id
+            RETURNING account.id",
+                params,
+            )
+            .await?;
+        if let Some(row) = rows.first() {
+            let account_id: i32 = row.get("id");
+            transaction
+                .execute("DELETE FROM password_reset_token WHERE account_id = $1", &[&account_id])
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // Replaces the account's previous verification tokens with a new one. returns the number of created tokens, which
+    // is 0 if there is no such account or it's already verified
+    pub async fn create_verification_token(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM verification_token
+                WHERE expiration_date < $3 OR account_id IN (SELECT id FROM account WHERE email = $1)
+            )
+            INSERT INTO verification_token (token_hash, account_id, creation_date, expiration_date)
+            SELECT $2, id, $3, $4 FROM account WHERE email = $1 AND NOT verified",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Consumes the token and marks its account as verified. returns the account's id, or nothing if the token doesn't
+    // exist or has expired
+    pub async fn verify(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            WITH used_token AS (
+                DELETE FROM verification_token
+                WHERE token_hash = $1 AND expiration_date > $2
+                RETURNING account_id
+            )
+            UPDATE account SET verified = TRUE
+            FROM used_token
+            WHERE account.id = used_token.account_id
+            RETURNING account.id",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // Replaces the account's previous link, if any. disabled accounts don't get one
+    pub async fn create_login_link_token(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM login_link_token
+                WHERE expiration_date < $4 OR account_id IN (SELECT id FROM account WHERE email = $1)
+            )
+            INSERT INTO login_link_token (token_hash, account_id, device, creation_date, expiration_date)
+            SELECT $2, id, $3, $4, $5 FROM account WHERE email = $1 AND NOT disabled",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Consumes the token. the email is verified along the way, since the link was sent to it. returns the account
+    // along with the device of the login, or nothing if the token doesn't exist, has expired or the account is disabled
+    pub async fn use_login_link_token(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            WITH used_token AS (
+                DELETE FROM login_link_token
+                WHERE token_hash = $1 AND expiration_date > $2
+                RETURNING account_id, device
+            )
+            UPDATE account SET verified = TRUE
+            FROM used_token
+            WHERE account.id = used_token.account_id AND NOT account.disabled
+            RETURNING account.id, used_token.device, EXISTS(
+                SELECT 1 FROM two_factor WHERE two_factor.account_id = account.id AND enabled
+            ) AS two_factor",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    pub async fn email_exists(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<bool, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let row = db_client
+            .query_one(
+                "SELECT EXISTS(SELECT 1 FROM account WHERE email = $1) AS exists",
+                params,
+            )
+            .await?;
+
+        Ok(row.get("exists"))
+    }
+
+    // Replaces the account's pending email, if any. the expired tokens of every account are removed along the way
+    pub async fn create_email_change_token(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM email_change_token WHERE expiration_date < $4 OR account_id = $1
+            )
+            INSERT INTO email_change_token (token_hash, account_id, email, creation_date, expiration_date)
+            VALUES ($3, $1, $2, $4, $5)",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Consumes the token and swaps the pending email in. the new email is verified, since the token was sent to it.
+    // the links sent to the previous email and the other pending emails are dropped, in case the previous mailbox was
+    // compromised. returns the account's id along with its previous email, or nothing if the token doesn't exist or
+    // has expired
+    pub async fn change_email(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            WITH used_token AS (
+                DELETE FROM email_change_token
+                WHERE token_hash = $1 AND expiration_date > $2
+                RETURNING account_id, email
+            ), previous AS (
+                SELECT account.id, account.email FROM account JOIN used_token ON account.id = used_token.account_id
+            )
+            UPDATE account SET email = used_token.email, verified = TRUE
+            FROM used_token, previous
+            WHERE account.id = used_token.account_id AND previous.id = account.id
+            RETURNING account.id, account.email, previous.email AS previous_email",
+                params,
+            )
+            .await?;
+        if let Some(row) = rows.first() {
+            let account_id: i32 = row.get("id");
+            for query in &[
+                "DELETE FROM password_reset_token WHERE account_id = $1",
+                "DELETE FROM login_link_token WHERE account_id = $1",
+                "DELETE FROM email_change_token WHERE account_id = $1",
+            ] {
+                transaction.execute(*query, &[&account_id]).await?;
+            }
+        }
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // Deletes the account along with its todos. the other owned rows (sessions, tokens) are removed by their
+    // `ON DELETE CASCADE` constraints. returns the deleted account and the number of deleted todos
+    pub async fn delete(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
         let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
         let rows = transaction
             .query(
-                "SELECT id FROM account WHERE email = $1 AND password = crypt($2, password)",
+                "
+            WITH deleted_todos AS (
+                DELETE FROM todo WHERE account_id = $1
+                RETURNING id
+            )
+            DELETE FROM account
+            WHERE id = $1
+            RETURNING id, email, (SELECT COUNT(*) FROM deleted_todos) AS todos_count",
                 params,
             )
             .await?;
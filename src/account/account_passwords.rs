@@ -0,0 +1,172 @@
+use crate::account::account_models::AccountDbExecutor;
+use crate::settings::PasswordSettings;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{error::BlockingError, web};
+use argon2::{Config, ThreadMode, Variant, Version};
+
+const HASH_LENGTH: u32 = 32;
+
+#[derive(Debug)]
+pub enum PasswordErrors {
+    Argon2(argon2::Error),
+    Bcrypt(bcrypt::BcryptError),
+    Db(DbErrors),
+    Runtime,
+}
+
+impl From<BlockingError<PasswordErrors>> for PasswordErrors {
+    fn from(err: BlockingError<PasswordErrors>) -> PasswordErrors {
+        match err {
+            BlockingError::Error(err) => err,
+            BlockingError::Canceled => PasswordErrors::Runtime,
+        }
+    }
+}
+
+impl From<DbErrors> for PasswordErrors {
+    fn from(err: DbErrors) -> PasswordErrors {
+        PasswordErrors::Db(err)
+    }
+}
+
+fn argon2_config(settings: &PasswordSettings) -> Config<'static> {
+    Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: settings.memory_cost,
+        time_cost: settings.time_cost,
+        lanes: settings.parallelism,
+        thread_mode: ThreadMode::Sequential,
+        secret: &[],
+        ad: &[],
+        hash_length: HASH_LENGTH,
+    }
+}
+
+// The hashes made by pgcrypto's `crypt` before the hashing moved here
+fn is_bcrypt(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
+// Whether a hash should be replaced by a new one, made with the current algorithm and parameters
+pub fn needs_rehash(hash: &str, settings: &PasswordSettings) -> bool {
+    let prefix = format!(
+        "$argon2id$v=19$m={},t={},p={}$",
+        settings.memory_cost, settings.time_cost, settings.parallelism
+    );
+
+    !hash.starts_with(&prefix)
+}
+
+// Hashing is slow on purpose, so it's run on the thread pool like the other blocking work
+pub async fn hash_password(password: &str, settings: &PasswordSettings) -> Result<String, PasswordErrors> {
+    let password = password.to_string();
+    let settings = settings.clone();
+
+    let hash = web::block(move || {
+        let salt = uuid::Uuid::new_v4();
+        argon2::hash_encoded(password.as_bytes(), salt.as_bytes(), &argon2_config(&settings))
+            .map_err(PasswordErrors::Argon2)
+    })
+    .await?;
+
+    Ok(hash)
+}
+
+pub async fn verify_password(password: &str, hash: &str) -> Result<bool, PasswordErrors> {
+    let password = password.to_string();
+    let hash = hash.to_string();
+
+    let matches = web::block(move || {
+        if is_bcrypt(&hash) {
+            bcrypt::verify(&password, &hash).map_err(PasswordErrors::Bcrypt)
+        } else {
+            argon2::verify_encoded(&hash, password.as_bytes()).map_err(PasswordErrors::Argon2)
+        }
+    })
+    .await?;
+
+    Ok(matches)
+}
+
+// Checks the password of an account against its stored hash. the outdated hash of a right password is replaced, which
+// doesn't fail the check if it goes wrong
+pub async fn check_password(
+    state: &AppState,
+    account_id: i32,
+    password: &str,
+    hash: &str,
+) -> Result<bool, PasswordErrors> {
+    if !verify_password(password, hash).await? {
+        return Ok(false);
+    }
+
+    let settings = &state.settings.password;
+    if needs_rehash(hash, settings) {
+        let result = match hash_password(password, settings).await {
+            Ok(new_hash) => AccountDbExecutor::change_password(&state.db_pool, &[&account_id, &hash, &new_hash])
+                .await
+                .map_err(PasswordErrors::from),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            warn!(target: "warnings", "Warn: {:?}", err);
+        }
+    }
+
+    Ok(true)
+}
+
+// Like `check_password`, loading the account's hash first. an account which doesn't exist has no right password
+pub async fn check_account_password(state: &AppState, account_id: i32, password: &str) -> Result<bool, PasswordErrors> {
+    let rows = AccountDbExecutor::get_password(&state.db_pool, &[&account_id]).await?;
+    let hash: String = match rows.first() {
+        Some(row) => row.get("password"),
+        None => return Ok(false),
+    };
+
+    check_password(state, account_id, password, &hash).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap parameters, the tests only check the format
+    fn create_settings() -> PasswordSettings {
+        PasswordSettings {
+            memory_cost: 1024,
+            time_cost: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_argon2_passwords() {
+        let settings = create_settings();
+        let hash = hash_password("12345678", &settings).await.unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(verify_password("12345678", &hash).await.unwrap());
+        assert!(!verify_password("87654321", &hash).await.unwrap());
+        assert!(!needs_rehash(&hash, &settings));
+        assert!(needs_rehash(
+            &hash,
+            &PasswordSettings {
+                time_cost: 2,
+                ..create_settings()
+            }
+        ));
+    }
+
+    // A hash made by pgcrypto with `crypt('12345678', gen_salt('bf'))`
+    #[actix_rt::test]
+    async fn test_bcrypt_passwords() {
+        let hash = "$2a$06$PyfKtobUBYrfOJBabMEVqu4YRiCmMmzwShR9aCniE8IMjJUL.njyG";
+
+        assert!(verify_password("12345678", hash).await.unwrap());
+        assert!(!verify_password("87654321", hash).await.unwrap());
+        assert!(needs_rehash(hash, &create_settings()));
+    }
+}
@@ -1,2 +1,3 @@
 pub mod account_controllers;
 pub mod account_models;
+pub mod account_passwords;
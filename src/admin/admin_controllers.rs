@@ -0,0 +1,242 @@
+use crate::admin::admin_models::{self, AdminAccount, AdminDbExecutor};
+use crate::common::pagination::{self, InvalidPagination};
+use crate::common::responses::ServerResponse;
+use crate::jwt::jwt_models::JwtDbExecutor;
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::sessions::session_store::SessionErrors;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct AdminAccountsRequest {
+    search: Option<String>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AdminAccountsResponse {
+    accounts: Vec<AdminAccount>,
+    // The number of matching accounts, ignoring the offset and the limit
+    total: i64,
+}
+
+#[derive(Serialize)]
+pub struct AdminAccountUsage {
+    todos: i64,
+    api_tokens: i64,
+    sessions: usize,
+}
+
+#[derive(Serialize)]
+pub struct AdminAccountResponse {
+    #[serde(flatten)]
+    account: AdminAccount,
+    usage: AdminAccountUsage,
+}
+
+#[derive(Serialize)]
+pub struct AdminLogoutResponse {
+    revoked_sessions: usize,
+}
+
+#[derive(Debug)]
+pub enum AdminErrors {
+    NotFound,
+    SelfDisable,
+    InvalidPagination,
+    Server,
+}
+
+impl From<DbErrors> for AdminErrors {
+    fn from(_err: DbErrors) -> AdminErrors {
+        AdminErrors::Server
+    }
+}
+
+impl From<SessionErrors> for AdminErrors {
+    fn from(_err: SessionErrors) -> AdminErrors {
+        AdminErrors::Server
+    }
+}
+
+impl From<InvalidPagination> for AdminErrors {
+    fn from(_err: InvalidPagination) -> AdminErrors {
+        AdminErrors::InvalidPagination
+    }
+}
+
+impl std::fmt::Display for AdminErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AdminErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AdminErrors::NotFound => http::StatusCode::NOT_FOUND,
+            AdminErrors::SelfDisable => http::StatusCode::CONFLICT,
+            AdminErrors::InvalidPagination => http::StatusCode::BAD_REQUEST,
+            AdminErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AdminErrors::NotFound => ServerResponse::new((), json!({"error": "Account not found"})),
+            AdminErrors::SelfDisable => {
+                ServerResponse::new((), json!({"error": "An admin can't disable their own account"}))
+            }
+            AdminErrors::InvalidPagination => ServerResponse::new((), json!({"error": "Invalid offset or limit"})),
+            AdminErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// Sessions which expired but weren't cleaned up yet aren't counted
+async fn count_sessions(state: &AppState, account_id: i32) -> Result<usize, AdminErrors> {
+    let idle_timeout = state.settings.session.idle_timeout;
+    let current_date = Utc::now();
+    let sessions = state.session_store.list_for_account(account_id).await?;
+
+    Ok(sessions
+        .iter()
+        .filter(|session| !session.is_expired(current_date, idle_timeout))
+        .count())
+}
+
+// Revokes every session of the account, along with its logins with tokens. returns how many sessions were still
+// active
+async fn revoke_sessions(state: &AppState, account_id: i32) -> Result<usize, AdminErrors> {
+    let count = count_sessions(state, account_id).await?;
+    state.session_store.revoke_all(account_id, None).await?;
+    JwtDbExecutor::revoke_all(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+
+    Ok(count)
+}
+
+pub async fn admin_accounts(
+    query: web::Query<AdminAccountsRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AdminErrors> {
+    let (offset, limit) = pagination::page(query.offset, query.limit)?;
+
+    let search = query.search.as_deref().map(admin_models::search_pattern);
+    let rows = AdminDbExecutor::get_accounts(&state.db_pool, &[&search, &offset, &limit])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+    let total = AdminDbExecutor::count_accounts(&state.db_pool, &[&search])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+
+    let accounts = rows.iter().map(AdminAccount::from_row).collect();
+
+    let response_json = ServerResponse::new(AdminAccountsResponse { accounts, total }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn admin_account(
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AdminErrors> {
+    let account_id = path.into_inner();
+    let rows = AdminDbExecutor::get_account(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(AdminErrors::NotFound)?;
+
+    let usage = AdminAccountUsage {
+        todos: row.get("todos_count"),
+        api_tokens: row.get("api_tokens_count"),
+        sessions: count_sessions(&state, account_id).await?,
+    };
+    let account = AdminAccount::from_row(row);
+
+    let response_json = ServerResponse::new(AdminAccountResponse { account, usage }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// A disabled account can't login and its api tokens are refused. its sessions are ended right away
+pub async fn admin_account_disable(
+    account: AuthenticatedAccount,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AdminErrors> {
+    let account_id = path.into_inner();
+    if account_id == account.account_id() {
+        return Err(AdminErrors::SelfDisable);
+    }
+
+    let rows = AdminDbExecutor::set_disabled(&state.db_pool, &[&account_id, &true])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(AdminErrors::NotFound)?;
+    revoke_sessions(&state, account_id).await?;
+
+    let response_json = ServerResponse::new(AdminAccount::from_row(row), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn admin_account_enable(
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AdminErrors> {
+    let account_id = path.into_inner();
+    let rows = AdminDbExecutor::set_disabled(&state.db_pool, &[&account_id, &false])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(AdminErrors::NotFound)?;
+
+    let response_json = ServerResponse::new(AdminAccount::from_row(row), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// Ends every session of the account. its api tokens keep working, the account can revoke them itself
+pub async fn admin_account_logout(
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AdminErrors> {
+    let account_id = path.into_inner();
+    let rows = AdminDbExecutor::get_account(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AdminErrors::from(err)
+        })?;
+    if rows.is_empty() {
+        return Err(AdminErrors::NotFound);
+    }
+
+    let revoked_sessions = revoke_sessions(&state, account_id).await?;
+
+    let response_json = ServerResponse::new(AdminLogoutResponse { revoked_sessions }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
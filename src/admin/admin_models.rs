@@ -0,0 +1,119 @@
+use crate::account::account_models::AccountRole;
+use crate::DbErrors;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct AdminAccount {
+    id: i32,
+    email: String,
+    role: AccountRole,
+    verified: bool,
+    disabled: bool,
+}
+
+impl AdminAccount {
+    pub fn from_row(row: &Row) -> Self {
+        let role: String = row.get("role");
+
+        AdminAccount {
+            id: row.get("id"),
+            email: row.get("email"),
+            role: role.parse().unwrap_or(AccountRole::User),
+            verified: row.get("verified"),
+            disabled: row.get("disabled"),
+        }
+    }
+}
+
+// Escapes the LIKE wildcards, so that the search matches the text as it was typed
+pub fn search_pattern(search: &str) -> String {
+    let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+pub struct AdminDbExecutor;
+
+impl AdminDbExecutor {
+    // Lists a page of the accounts whose email matches the pattern, or of all of them without one
+    pub async fn get_accounts(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, email, role, verified, disabled
+            FROM account
+            WHERE $1::TEXT IS NULL OR email ILIKE $1
+            ORDER BY id
+            OFFSET $2
+            LIMIT $3",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // The number of accounts get_accounts pages through. counted on its own, since a page past the end has no rows
+    pub async fn count_accounts(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<i64, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let row = db_client
+            .query_one(
+                "SELECT COUNT(*) AS total FROM account WHERE $1::TEXT IS NULL OR email ILIKE $1",
+                params,
+            )
+            .await?;
+
+        Ok(row.get("total"))
+    }
+
+    pub async fn get_account(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, email, role, verified, disabled,
+                (SELECT COUNT(*) FROM todo WHERE todo.account_id = account.id) AS todos_count,
+                (SELECT COUNT(*) FROM api_token WHERE api_token.account_id = account.id) AS api_tokens_count
+            FROM account
+            WHERE id = $1",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // Returns the updated account, or nothing if it doesn't exist
+    pub async fn set_disabled(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            UPDATE account SET disabled = $2
+            WHERE id = $1
+            RETURNING id, email, role, verified, disabled",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_pattern() {
+        assert_eq!(search_pattern("dima"), "%dima%");
+        assert_eq!(search_pattern("100%_"), "%100\\%\\_%");
+        assert_eq!(search_pattern("a\\b"), "%a\\\\b%");
+    }
+}
@@ -0,0 +1,2 @@
+pub mod admin_controllers;
+pub mod admin_models;
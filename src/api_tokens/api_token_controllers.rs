@@ -0,0 +1,176 @@
+use crate::api_tokens::api_token_models::{ApiToken, ApiTokenDbExecutor, ApiTokenScope};
+use crate::auth_events::auth_event_controllers::record_auth_event;
+use crate::auth_events::auth_event_models::AuthEventKind;
+use crate::common::responses::ServerResponse;
+use crate::common::tokens;
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web, HttpRequest};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const MAX_NAME_LENGTH: usize = 50;
+
+#[derive(Deserialize)]
+pub struct ApiTokenCreateRequest {
+    name: String,
+    scope: ApiTokenScope,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenCreateResponse {
+    #[serde(flatten)]
+    api_token: ApiToken,
+    // The only time the token is shown. it can't be recovered later
+    token: String,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokensResponse {
+    api_tokens: Vec<ApiToken>,
+}
+
+#[derive(Debug)]
+pub enum ApiTokenErrors {
+    InvalidName,
+    NotFound,
+    SessionRequired,
+    Server,
+}
+
+impl From<DbErrors> for ApiTokenErrors {
+    fn from(_err: DbErrors) -> ApiTokenErrors {
+        ApiTokenErrors::Server
+    }
+}
+
+impl std::fmt::Display for ApiTokenErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for ApiTokenErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            ApiTokenErrors::InvalidName => http::StatusCode::UNPROCESSABLE_ENTITY,
+            ApiTokenErrors::NotFound => http::StatusCode::NOT_FOUND,
+            ApiTokenErrors::SessionRequired => http::StatusCode::FORBIDDEN,
+            ApiTokenErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            ApiTokenErrors::InvalidName => ServerResponse::new(
+                (),
+                json!({"error": "Invalid name. the name must be between 1 and 50 characters long"}),
+            ),
+            ApiTokenErrors::NotFound => ServerResponse::new((), json!({"error": "Token not found"})),
+            ApiTokenErrors::SessionRequired => {
+                ServerResponse::new((), json!({"error": "API tokens can't be managed with an API token"}))
+            }
+            ApiTokenErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// A leaked token mustn't be able to create new ones, so the tokens are only managed from a logged in session
+fn require_session(account: &AuthenticatedAccount) -> Result<(), ApiTokenErrors> {
+    match account.session_id() {
+        Some(_) => Ok(()),
+        None => Err(ApiTokenErrors::SessionRequired),
+    }
+}
+
+pub async fn api_token_create(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    body: web::Json<ApiTokenCreateRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, ApiTokenErrors> {
+    require_session(&account)?;
+
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiTokenErrors::InvalidName);
+    }
+
+    let token = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().to_simple(),
+        uuid::Uuid::new_v4().to_simple()
+    );
+    let rows = ApiTokenDbExecutor::create(
+        &state.db_pool,
+        &[
+            &account.account_id(),
+            &name,
+            &body.scope.as_str(),
+            &tokens::hash_token(&token),
+            &Utc::now(),
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        ApiTokenErrors::from(err)
+    })?;
+
+    let api_token = ApiToken::from_row(&rows[0]);
+    record_auth_event(&state, &request, account.account_id(), AuthEventKind::ApiTokenCreation).await;
+    let response_json = ServerResponse::new(ApiTokenCreateResponse { api_token, token }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn api_tokens_get(
+    account: AuthenticatedAccount,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, ApiTokenErrors> {
+    require_session(&account)?;
+
+    let rows = ApiTokenDbExecutor::get(&state.db_pool, &[&account.account_id()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            ApiTokenErrors::from(err)
+        })?;
+    let api_tokens = rows.iter().map(ApiToken::from_row).collect();
+
+    let response_json = ServerResponse::new(ApiTokensResponse { api_tokens }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn api_token_revoke(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, ApiTokenErrors> {
+    require_session(&account)?;
+
+    let rows_count = ApiTokenDbExecutor::revoke(&state.db_pool, &[&account.account_id(), &path.into_inner()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            ApiTokenErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(ApiTokenErrors::NotFound);
+    }
+    record_auth_event(
+        &state,
+        &request,
+        account.account_id(),
+        AuthEventKind::ApiTokenRevocation,
+    )
+    .await;
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
@@ -0,0 +1,134 @@
+use crate::DbErrors;
+use actix_web::http::Method;
+use chrono::prelude::*;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenScope {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl ApiTokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenScope::ReadOnly => "read_only",
+            ApiTokenScope::ReadWrite => "read_write",
+        }
+    }
+
+    // Read only tokens can't be used for requests which change anything
+    pub fn allows(&self, method: &Method) -> bool {
+        match self {
+            ApiTokenScope::ReadOnly => matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS),
+            ApiTokenScope::ReadWrite => true,
+        }
+    }
+}
+
+impl FromStr for ApiTokenScope {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "read_only" => Ok(ApiTokenScope::ReadOnly),
+            "read_write" => Ok(ApiTokenScope::ReadWrite),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiToken {
+    id: i32,
+    name: String,
+    scope: ApiTokenScope,
+    creation_date: DateTime<Utc>,
+    last_used_date: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn from_row(row: &Row) -> Self {
+        let scope: String = row.get("scope");
+
+        ApiToken {
+            id: row.get("id"),
+            name: row.get("name"),
+            scope: scope.parse().unwrap_or(ApiTokenScope::ReadOnly),
+            creation_date: row.get("creation_date"),
+            last_used_date: row.get("last_used_date"),
+        }
+    }
+}
+
+pub struct ApiTokenDbExecutor;
+
+// The tokens themselves are never stored, only their hashes. see `tokens::hash_token`
+impl ApiTokenDbExecutor {
+    pub async fn create(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            INSERT INTO api_token(account_id, name, scope, token_hash, creation_date)
+            VALUES($1, $2, $3, $4, $5)
+            RETURNING id, name, scope, creation_date, last_used_date",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    pub async fn get(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, name, scope, creation_date, last_used_date
+            FROM api_token
+            WHERE account_id = $1
+            ORDER BY creation_date DESC",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn revoke(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute("DELETE FROM api_token WHERE account_id = $1 AND id = $2", params)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Finds the token's account and marks the token as used. the tokens of disabled accounts aren't accepted
+    pub async fn authenticate(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            UPDATE api_token SET last_used_date = $2
+            FROM account
+            WHERE token_hash = $1
+                AND account.id = api_token.account_id AND NOT account.disabled
+            RETURNING api_token.id, api_token.account_id, api_token.scope",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+}
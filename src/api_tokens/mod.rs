@@ -0,0 +1,2 @@
+pub mod api_token_controllers;
+pub mod api_token_models;
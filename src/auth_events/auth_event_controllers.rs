@@ -0,0 +1,114 @@
+use crate::auth_events::auth_event_models::{AuthEvent, AuthEventDbExecutor, AuthEventKind};
+use crate::common::pagination::{self, InvalidPagination};
+use crate::common::requests;
+use crate::common::responses::ServerResponse;
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web, HttpRequest};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct AuthEventsRequest {
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AuthEventsResponse {
+    events: Vec<AuthEvent>,
+}
+
+#[derive(Debug)]
+pub enum AuthEventErrors {
+    InvalidPagination,
+    Server,
+}
+
+impl From<DbErrors> for AuthEventErrors {
+    fn from(_err: DbErrors) -> AuthEventErrors {
+        AuthEventErrors::Server
+    }
+}
+
+impl From<InvalidPagination> for AuthEventErrors {
+    fn from(_err: InvalidPagination) -> AuthEventErrors {
+        AuthEventErrors::InvalidPagination
+    }
+}
+
+impl std::fmt::Display for AuthEventErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for AuthEventErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            AuthEventErrors::InvalidPagination => http::StatusCode::BAD_REQUEST,
+            AuthEventErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            AuthEventErrors::InvalidPagination => ServerResponse::new((), json!({"error": "Invalid offset or limit"})),
+            AuthEventErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// Records the event along with where the request came from
+pub async fn record_auth_event(state: &AppState, request: &HttpRequest, account_id: i32, kind: AuthEventKind) {
+    let ip = requests::client_ip(
+        request.peer_addr(),
+        request.headers(),
+        &state.settings.proxy.trusted_proxies,
+    );
+    let user_agent = requests::user_agent(request.headers());
+
+    create_auth_event(state, account_id, kind, ip, user_agent).await;
+}
+
+// A failure doesn't fail the request which caused the event, it's only logged
+pub async fn create_auth_event(
+    state: &AppState,
+    account_id: i32,
+    kind: AuthEventKind,
+    ip: Option<String>,
+    user_agent: Option<String>,
+) {
+    let result = AuthEventDbExecutor::create(
+        &state.db_pool,
+        &[&account_id, &kind.as_str(), &ip, &user_agent, &Utc::now()],
+    )
+    .await;
+    if let Err(err) = result {
+        warn!(target: "warnings", "Warn: {:?}", err);
+    }
+}
+
+// The account's own security history
+pub async fn auth_events_get(
+    account: AuthenticatedAccount,
+    query: web::Query<AuthEventsRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, AuthEventErrors> {
+    let (offset, limit) = pagination::page(query.offset, query.limit)?;
+    let rows = AuthEventDbExecutor::get(&state.db_pool, &[&account.account_id(), &offset, &limit])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AuthEventErrors::from(err)
+        })?;
+    let events = rows.iter().filter_map(AuthEvent::from_row).collect();
+
+    let response_json = ServerResponse::new(AuthEventsResponse { events }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
@@ -0,0 +1,147 @@
+use crate::DbErrors;
+use chrono::prelude::*;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventKind {
+    LoginSuccess,
+    // A wrong password or second factor for the account
+    LoginFailure,
+    // The failures locked the account's email out
+    Lockout,
+    Logout,
+    // A session was used after it expired
+    SessionExpiration,
+    SessionRevocation,
+    PasswordChange,
+    PasswordReset,
+    ApiTokenCreation,
+    ApiTokenRevocation,
+    TwoFactorActivation,
+    TwoFactorDeactivation,
+    // A change of email was asked for, it's only made once the new email confirms it
+    EmailChangeRequest,
+    EmailChange,
+    // The account was linked to an identity of the OpenID Connect provider
+    IdentityLink,
+    // A refresh token was used twice, so its login was revoked
+    RefreshTokenReuse,
+}
+
+impl AuthEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthEventKind::LoginSuccess => "login_success",
+            AuthEventKind::LoginFailure => "login_failure",
+            AuthEventKind::Lockout => "lockout",
+            AuthEventKind::Logout => "logout",
+            AuthEventKind::SessionExpiration => "session_expiration",
+            AuthEventKind::SessionRevocation => "session_revocation",
+            AuthEventKind::PasswordChange => "password_change",
+            AuthEventKind::PasswordReset => "password_reset",
+            AuthEventKind::ApiTokenCreation => "api_token_creation",
+            AuthEventKind::ApiTokenRevocation => "api_token_revocation",
+            AuthEventKind::TwoFactorActivation => "two_factor_activation",
+            AuthEventKind::TwoFactorDeactivation => "two_factor_deactivation",
+            AuthEventKind::EmailChangeRequest => "email_change_request",
+            AuthEventKind::EmailChange => "email_change",
+            AuthEventKind::IdentityLink => "identity_link",
+            AuthEventKind::RefreshTokenReuse => "refresh_token_reuse",
+        }
+    }
+}
+
+impl FromStr for AuthEventKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "login_success" => Ok(AuthEventKind::LoginSuccess),
+            "login_failure" => Ok(AuthEventKind::LoginFailure),
+            "lockout" => Ok(AuthEventKind::Lockout),
+            "logout" => Ok(AuthEventKind::Logout),
+            "session_expiration" => Ok(AuthEventKind::SessionExpiration),
+            "session_revocation" => Ok(AuthEventKind::SessionRevocation),
+            "password_change" => Ok(AuthEventKind::PasswordChange),
+            "password_reset" => Ok(AuthEventKind::PasswordReset),
+            "api_token_creation" => Ok(AuthEventKind::ApiTokenCreation),
+            "api_token_revocation" => Ok(AuthEventKind::ApiTokenRevocation),
+            "two_factor_activation" => Ok(AuthEventKind::TwoFactorActivation),
+            "two_factor_deactivation" => Ok(AuthEventKind::TwoFactorDeactivation),
+            "email_change_request" => Ok(AuthEventKind::EmailChangeRequest),
+            "email_change" => Ok(AuthEventKind::EmailChange),
+            "identity_link" => Ok(AuthEventKind::IdentityLink),
+            "refresh_token_reuse" => Ok(AuthEventKind::RefreshTokenReuse),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuthEvent {
+    id: i64,
+    kind: AuthEventKind,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    creation_date: DateTime<Utc>,
+}
+
+impl AuthEvent {
+    // Returns None for the kinds which this version doesn't know
+    pub fn from_row(row: &Row) -> Option<Self> {
+        let kind: String = row.get("kind");
+
+        Some(AuthEvent {
+            id: row.get("id"),
+            kind: kind.parse().ok()?,
+            ip: row.get("ip"),
+            user_agent: row.get("user_agent"),
+            creation_date: row.get("creation_date"),
+        })
+    }
+}
+
+pub struct AuthEventDbExecutor;
+
+// The events are only ever added, the table refuses updates
+impl AuthEventDbExecutor {
+    pub async fn create(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            INSERT INTO auth_event (account_id, kind, ip, user_agent, creation_date)
+            VALUES ($1, $2, $3, $4, $5)",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // The newest events first
+    pub async fn get(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, kind, ip, user_agent, creation_date
+            FROM auth_event
+            WHERE account_id = $1
+            ORDER BY creation_date DESC, id DESC
+            OFFSET $2
+            LIMIT $3",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod auth_event_controllers;
+pub mod auth_event_models;
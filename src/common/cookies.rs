@@ -0,0 +1,69 @@
+use crate::settings::{CookieSameSite, CookieSettings};
+use actix_web::{cookie, http};
+
+pub const SESSION_COOKIE: &str = "session_id";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const OIDC_STATE_COOKIE: &str = "oidc_state";
+const OIDC_PATH: &str = "/api/account/oidc";
+
+// The cookies are sent to every route of the api, not only to the one which set them
+fn cookie_builder(name: &'static str, value: String, settings: &CookieSettings) -> http::CookieBuilder {
+    let same_site = match settings.same_site {
+        CookieSameSite::Strict => cookie::SameSite::Strict,
+        CookieSameSite::Lax => cookie::SameSite::Lax,
+    };
+    let builder = http::CookieBuilder::new(name, value)
+        .path("/")
+        .secure(settings.secure)
+        .same_site(same_site);
+
+    match &settings.domain {
+        Some(domain) => builder.domain(domain.clone()),
+        None => builder,
+    }
+}
+
+pub fn session_cookie(session_id: String, max_age: i64, settings: &CookieSettings) -> http::Cookie<'static> {
+    cookie_builder(SESSION_COOKIE, session_id, settings)
+        .max_age(max_age)
+        .http_only(true)
+        .finish()
+}
+
+// Readable by the frontend, which repeats it in the csrf header of its state-changing requests
+pub fn csrf_cookie(csrf_token: String, max_age: i64, settings: &CookieSettings) -> http::Cookie<'static> {
+    cookie_builder(CSRF_COOKIE, csrf_token, settings)
+        .max_age(max_age)
+        .finish()
+}
+
+// Used with `del_cookie` to make the client drop the session cookie. the path and the domain must match the ones it
+// was set with
+pub fn removed_session_cookie(settings: &CookieSettings) -> http::Cookie<'static> {
+    cookie_builder(SESSION_COOKIE, String::new(), settings).finish()
+}
+
+pub fn removed_csrf_cookie(settings: &CookieSettings) -> http::Cookie<'static> {
+    cookie_builder(CSRF_COOKIE, String::new(), settings).finish()
+}
+
+// Ties the login at the OpenID Connect provider to the browser which started it. it's always lax, since the provider
+// sends the browser back with a cross-site redirect, and only sent to the oidc routes
+pub fn oidc_state_cookie(state: String, max_age: i64, settings: &CookieSettings) -> http::Cookie<'static> {
+    cookie_builder(OIDC_STATE_COOKIE, state, settings)
+        .path(OIDC_PATH)
+        .same_site(cookie::SameSite::Lax)
+        .max_age(max_age)
+        .http_only(true)
+        .finish()
+}
+
+// Already expired, since it's added to a response which is built by then
+pub fn removed_oidc_state_cookie(settings: &CookieSettings) -> http::Cookie<'static> {
+    cookie_builder(OIDC_STATE_COOKIE, String::new(), settings)
+        .path(OIDC_PATH)
+        .same_site(cookie::SameSite::Lax)
+        .max_age(0)
+        .http_only(true)
+        .finish()
+}
@@ -1,2 +1,6 @@
+pub mod cookies;
+pub mod pagination;
+pub mod requests;
 pub mod responses;
+pub mod tokens;
 pub mod validators;
@@ -0,0 +1,31 @@
+// The lists are served a page at a time. a larger page is cut down rather than loading a whole table at once
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidPagination;
+
+// Returns the offset and the limit to query with
+pub fn page(offset: Option<i64>, limit: Option<i64>) -> Result<(i64, i64), InvalidPagination> {
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if offset < 0 || limit < 0 {
+        return Err(InvalidPagination);
+    }
+
+    Ok((offset, limit.min(MAX_LIMIT)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        assert_eq!(page(None, None), Ok((0, DEFAULT_LIMIT)));
+        assert_eq!(page(Some(10), Some(0)), Ok((10, 0)));
+        assert_eq!(page(Some(10), Some(1000)), Ok((10, MAX_LIMIT)));
+        assert_eq!(page(Some(-1), None), Err(InvalidPagination));
+        assert_eq!(page(None, Some(-1)), Err(InvalidPagination));
+    }
+}
@@ -0,0 +1,82 @@
+use actix_web::http::{header, HeaderMap};
+use std::net::{IpAddr, SocketAddr};
+
+pub fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+// The client is the peer, unless the peer is one of the trusted proxies. each proxy appends the address it got the
+// request from to `X-Forwarded-For`, so the client is the last address which isn't a trusted proxy. the header is
+// ignored otherwise, since the clients can send any address in it
+pub fn client_ip(peer_addr: Option<SocketAddr>, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> Option<String> {
+    let mut ip = peer_addr?.ip();
+    if trusted_proxies.contains(&ip) {
+        let forwarded: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        for address in forwarded.iter().rev() {
+            match address.parse::<IpAddr>() {
+                Ok(address) => {
+                    ip = address;
+                    if !trusted_proxies.contains(&address) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    Some(ip.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::HeaderValue;
+
+    fn forwarded_headers(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_static(value),
+        );
+
+        headers
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxy: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let client: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+        let trusted_proxies: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let headers = forwarded_headers("198.51.100.1, 203.0.113.9, 10.0.0.2");
+
+        assert_eq!(client_ip(None, &headers, &trusted_proxies), None);
+        assert_eq!(
+            client_ip(Some(client), &HeaderMap::new(), &trusted_proxies),
+            Some("203.0.113.7".to_string())
+        );
+        // A client can't pick its address
+        assert_eq!(
+            client_ip(Some(client), &headers, &trusted_proxies),
+            Some("203.0.113.7".to_string())
+        );
+        assert_eq!(client_ip(Some(proxy), &headers, &[]), Some("10.0.0.1".to_string()));
+        // Only the address added by the proxies is taken, the ones before it may have been sent by the client
+        assert_eq!(
+            client_ip(Some(proxy), &headers, &trusted_proxies),
+            Some("203.0.113.9".to_string())
+        );
+        assert_eq!(
+            client_ip(Some(proxy), &forwarded_headers("garbage"), &trusted_proxies),
+            Some("10.0.0.1".to_string())
+        );
+    }
+}
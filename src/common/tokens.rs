@@ -0,0 +1,23 @@
+use sha2::{Digest, Sha256};
+
+// Only the hashes of the tokens given out are stored. they're hashed here rather than in the queries, so that the
+// tokens themselves never reach the database or its logs
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
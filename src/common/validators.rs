@@ -4,6 +4,7 @@ use regex::Regex;
 pub enum ValidationErrors {
     Email,
     Password,
+    Locale,
 }
 
 pub struct Validator;
@@ -25,6 +26,16 @@ impl Validator {
             false => Err(ValidationErrors::Password),
         }
     }
+
+    // A language tag such as `en` or `pt-BR`. whether the language is actually supported is up to the frontend
+    pub fn locale(input: &str) -> Result<(), ValidationErrors> {
+        let locale_regex =
+            Regex::new(r###"^[a-zA-Z]{2,3}(-[a-zA-Z0-9]{2,8})*$"###).expect("Couldn't create locale regex");
+        match locale_regex.is_match(input) {
+            true => Ok(()),
+            false => Err(ValidationErrors::Locale),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -82,4 +93,24 @@ mod tests {
             assert_eq!(Validator::password(password), expected_results[index]);
         }
     }
+
+    #[test]
+    fn test_locale_validator() {
+        let locales = vec!["en", "pt-BR", "zh-Hant-TW", "fil", "", "e", "en_US", "en-", "english"];
+        let expected_results = vec![
+            Ok(()),
+            Ok(()),
+            Ok(()),
+            Ok(()),
+            Err(ValidationErrors::Locale),
+            Err(ValidationErrors::Locale),
+            Err(ValidationErrors::Locale),
+            Err(ValidationErrors::Locale),
+            Err(ValidationErrors::Locale),
+        ];
+
+        for (index, locale) in locales.iter().enumerate() {
+            assert_eq!(Validator::locale(locale), expected_results[index]);
+        }
+    }
 }
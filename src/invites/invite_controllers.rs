@@ -0,0 +1,201 @@
+use crate::account::account_models::{AccountDbExecutor, AccountRole};
+use crate::common::responses::ServerResponse;
+use crate::common::tokens;
+use crate::invites::invite_models::{Invite, InviteDbExecutor};
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const MAX_USES: i32 = 1000;
+
+#[derive(Deserialize)]
+pub struct InviteCreateRequest {
+    max_uses: Option<i32>,
+    expiration_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct InviteCreateResponse {
+    #[serde(flatten)]
+    invite: Invite,
+    // The only time the code is shown. it can't be recovered later
+    code: String,
+}
+
+#[derive(Serialize)]
+pub struct InvitesResponse {
+    invites: Vec<Invite>,
+}
+
+#[derive(Debug)]
+pub enum InviteErrors {
+    InvalidMaxUses,
+    InvalidExpirationDate,
+    Forbidden,
+    SessionRequired,
+    NotFound,
+    Server,
+}
+
+impl From<DbErrors> for InviteErrors {
+    fn from(_err: DbErrors) -> InviteErrors {
+        InviteErrors::Server
+    }
+}
+
+impl std::fmt::Display for InviteErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for InviteErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            InviteErrors::InvalidMaxUses => http::StatusCode::UNPROCESSABLE_ENTITY,
+            InviteErrors::InvalidExpirationDate => http::StatusCode::UNPROCESSABLE_ENTITY,
+            InviteErrors::Forbidden => http::StatusCode::FORBIDDEN,
+            InviteErrors::SessionRequired => http::StatusCode::FORBIDDEN,
+            InviteErrors::NotFound => http::StatusCode::NOT_FOUND,
+            InviteErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            InviteErrors::InvalidMaxUses => ServerResponse::new(
+                (),
+                json!({"error": "Invalid max uses. an invite can be used between 1 and 1000 times"}),
+            ),
+            InviteErrors::InvalidExpirationDate => ServerResponse::new(
+                (),
+                json!({"error": "Invalid expiration date. the date must be in the future"}),
+            ),
+            InviteErrors::Forbidden => ServerResponse::new((), json!({"error": "Only admins can invite people"})),
+            InviteErrors::SessionRequired => {
+                ServerResponse::new((), json!({"error": "Invites can't be managed with a token"}))
+            }
+            InviteErrors::NotFound => ServerResponse::new((), json!({"error": "Invite not found"})),
+            InviteErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// An invite lets people into the instance, so like the api tokens it can't be handed out or revoked with a stolen
+// api token or access token
+fn require_session(account: &AuthenticatedAccount) -> Result<(), InviteErrors> {
+    match account.session_id() {
+        Some(_) => Ok(()),
+        None => Err(InviteErrors::SessionRequired),
+    }
+}
+
+// Unless every account is allowed to invite people, only the admins are
+async fn require_inviter(state: &AppState, account: &AuthenticatedAccount) -> Result<(), InviteErrors> {
+    if state.settings.account.user_invites {
+        return Ok(());
+    }
+
+    let rows = AccountDbExecutor::get(&state.db_pool, &[&account.account_id()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            InviteErrors::from(err)
+        })?;
+    let role: String = match rows.first() {
+        Some(row) => row.get("role"),
+        None => return Err(InviteErrors::Forbidden),
+    };
+    if role.parse() != Ok(AccountRole::Admin) {
+        return Err(InviteErrors::Forbidden);
+    }
+
+    Ok(())
+}
+
+pub async fn invite_create(
+    account: AuthenticatedAccount,
+    body: web::Json<InviteCreateRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, InviteErrors> {
+    require_session(&account)?;
+    require_inviter(&state, &account).await?;
+
+    let max_uses = body.max_uses.unwrap_or(1);
+    if !(1..=MAX_USES).contains(&max_uses) {
+        return Err(InviteErrors::InvalidMaxUses);
+    }
+    let current_date = Utc::now();
+    let expiration_date = body
+        .expiration_date
+        .unwrap_or_else(|| current_date + state.settings.account.invite_timeout);
+    if expiration_date <= current_date {
+        return Err(InviteErrors::InvalidExpirationDate);
+    }
+
+    let code = uuid::Uuid::new_v4().to_simple().to_string();
+    let rows = InviteDbExecutor::create(
+        &state.db_pool,
+        &[
+            &account.account_id(),
+            &tokens::hash_token(&code),
+            &max_uses,
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        InviteErrors::from(err)
+    })?;
+
+    let invite = Invite::from_row(&rows[0]);
+    let response_json = ServerResponse::new(InviteCreateResponse { invite, code }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn invites_get(
+    account: AuthenticatedAccount,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, InviteErrors> {
+    let rows = InviteDbExecutor::get(&state.db_pool, &[&account.account_id()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            InviteErrors::from(err)
+        })?;
+    let invites = rows.iter().map(Invite::from_row).collect();
+
+    let response_json = ServerResponse::new(InvitesResponse { invites }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// The accounts which already registered with the invite are kept
+pub async fn invite_revoke(
+    account: AuthenticatedAccount,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, InviteErrors> {
+    require_session(&account)?;
+
+    let rows_count = InviteDbExecutor::revoke(&state.db_pool, &[&account.account_id(), &path.into_inner()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            InviteErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(InviteErrors::NotFound);
+    }
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
@@ -0,0 +1,77 @@
+use crate::DbErrors;
+use chrono::prelude::*;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct Invite {
+    id: i32,
+    max_uses: i32,
+    uses: i32,
+    creation_date: DateTime<Utc>,
+    expiration_date: DateTime<Utc>,
+}
+
+impl Invite {
+    pub fn from_row(row: &Row) -> Self {
+        Invite {
+            id: row.get("id"),
+            max_uses: row.get("max_uses"),
+            uses: row.get("uses"),
+            creation_date: row.get("creation_date"),
+            expiration_date: row.get("expiration_date"),
+        }
+    }
+}
+
+pub struct InviteDbExecutor;
+
+// Like the api tokens, the codes themselves are never stored, only their hashes. the registrations which use them
+// are in `AccountDbExecutor::register_with_invite`
+impl InviteDbExecutor {
+    pub async fn create(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            INSERT INTO invite(account_id, code_hash, max_uses, creation_date, expiration_date)
+            VALUES($1, $2, $3, $4, $5)
+            RETURNING id, max_uses, uses, creation_date, expiration_date",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    pub async fn get(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, max_uses, uses, creation_date, expiration_date
+            FROM invite
+            WHERE account_id = $1
+            ORDER BY creation_date DESC",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn revoke(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute("DELETE FROM invite WHERE account_id = $1 AND id = $2", params)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod invite_controllers;
+pub mod invite_models;
@@ -0,0 +1,145 @@
+use chrono::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+#[derive(Debug, PartialEq)]
+pub enum AccessTokenErrors {
+    Invalid,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccessTokenHeader {
+    alg: String,
+    typ: String,
+}
+
+// The account is the subject. the login's refresh token family is kept too, to tell the logins apart
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AccessTokenClaims {
+    pub sub: String,
+    pub sid: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+fn mac(secret: &str, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.input(message.as_bytes());
+
+    mac
+}
+
+fn encode_part<T: Serialize>(part: &T) -> String {
+    let json = serde_json::to_string(part).expect("Can't serialize the token");
+    base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(part: &str) -> Result<T, AccessTokenErrors> {
+    let bytes = base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|_err| AccessTokenErrors::Invalid)?;
+    serde_json::from_slice(&bytes).map_err(|_err| AccessTokenErrors::Invalid)
+}
+
+// The api tokens are plain hex, the access tokens are jwts made of three parts
+pub fn is_access_token(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+// A HS256 jwt, only the api itself needs to check it
+pub fn sign(claims: &AccessTokenClaims, secret: &str) -> String {
+    let header = AccessTokenHeader {
+        alg: "HS256".to_string(),
+        typ: "JWT".to_string(),
+    };
+    let message = format!("{}.{}", encode_part(&header), encode_part(claims));
+    let signature = mac(secret, &message).result().code();
+
+    format!(
+        "{}.{}",
+        message,
+        base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+    )
+}
+
+// Checks the signature before anything else. the algorithm isn't taken from the token, so that a token can't ask
+// for another one
+pub fn verify(token: &str, secret: &str, current_date: DateTime<Utc>) -> Result<AccessTokenClaims, AccessTokenErrors> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(AccessTokenErrors::Invalid);
+    }
+
+    let message = format!("{}.{}", parts[0], parts[1]);
+    let signature =
+        base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).map_err(|_err| AccessTokenErrors::Invalid)?;
+    mac(secret, &message)
+        .verify(&signature)
+        .map_err(|_err| AccessTokenErrors::Invalid)?;
+
+    let header: AccessTokenHeader = decode_part(parts[0])?;
+    if header.alg != "HS256" {
+        return Err(AccessTokenErrors::Invalid);
+    }
+    let claims: AccessTokenClaims = decode_part(parts[1])?;
+    if claims.exp <= current_date.timestamp() {
+        return Err(AccessTokenErrors::Expired);
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d";
+
+    fn claims(current_date: DateTime<Utc>) -> AccessTokenClaims {
+        AccessTokenClaims {
+            sub: "1".to_string(),
+            sid: "family".to_string(),
+            iat: current_date.timestamp(),
+            exp: current_date.timestamp() + 900,
+        }
+    }
+
+    #[test]
+    fn test_access_token_verify() {
+        let current_date = Utc::now();
+        let token = sign(&claims(current_date), SECRET);
+
+        assert!(is_access_token(&token));
+        assert!(!is_access_token("0123456789abcdef0123456789abcdef"));
+        assert_eq!(verify(&token, SECRET, current_date), Ok(claims(current_date)));
+        assert_eq!(
+            verify(&token, "another secret", current_date),
+            Err(AccessTokenErrors::Invalid)
+        );
+
+        let expired_date = current_date + chrono::Duration::seconds(900);
+        assert_eq!(verify(&token, SECRET, expired_date), Err(AccessTokenErrors::Expired));
+    }
+
+    #[test]
+    fn test_access_token_tampering() {
+        let current_date = Utc::now();
+        let token = sign(&claims(current_date), SECRET);
+        let parts: Vec<&str> = token.split('.').collect();
+
+        let mut other_claims = claims(current_date);
+        other_claims.sub = "2".to_string();
+        let tampered = format!("{}.{}.{}", parts[0], encode_part(&other_claims), parts[2]);
+        assert_eq!(verify(&tampered, SECRET, current_date), Err(AccessTokenErrors::Invalid));
+
+        // A token which isn't signed
+        let header = AccessTokenHeader {
+            alg: "none".to_string(),
+            typ: "JWT".to_string(),
+        };
+        let unsigned = format!("{}.{}.", encode_part(&header), parts[1]);
+        assert_eq!(verify(&unsigned, SECRET, current_date), Err(AccessTokenErrors::Invalid));
+
+        assert_eq!(verify("a.b", SECRET, current_date), Err(AccessTokenErrors::Invalid));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::auth_events::auth_event_controllers::record_auth_event;
+use crate::auth_events::auth_event_models::AuthEventKind;
+use crate::common::responses::ServerResponse;
+use crate::common::tokens;
+use crate::jwt::access_tokens::{self, AccessTokenClaims};
+use crate::jwt::jwt_models::JwtDbExecutor;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web, HttpRequest};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid;
+
+#[derive(Deserialize)]
+pub struct JwtRefreshRequest {
+    refresh_token: String,
+}
+
+// The access token is sent as a bearer token, the refresh token only to get the next tokens
+#[derive(Serialize)]
+pub struct JwtTokenResponse {
+    account_id: i32,
+    access_token: String,
+    token_type: &'static str,
+    // In seconds
+    expires_in: i64,
+    refresh_token: String,
+    refresh_token_expiration_date: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum JwtErrors {
+    Disabled,
+    InvalidRefreshToken,
+    AccountDisabled,
+    Server,
+}
+
+impl From<DbErrors> for JwtErrors {
+    fn from(_err: DbErrors) -> JwtErrors {
+        JwtErrors::Server
+    }
+}
+
+impl std::fmt::Display for JwtErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for JwtErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            JwtErrors::Disabled => http::StatusCode::NOT_FOUND,
+            JwtErrors::InvalidRefreshToken => http::StatusCode::UNAUTHORIZED,
+            JwtErrors::AccountDisabled => http::StatusCode::FORBIDDEN,
+            JwtErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            JwtErrors::Disabled => ServerResponse::new((), json!({"error": "Token logins are disabled"})),
+            JwtErrors::InvalidRefreshToken => {
+                ServerResponse::new((), json!({"error": "Invalid or expired refresh token"}))
+            }
+            JwtErrors::AccountDisabled => ServerResponse::new((), json!({"error": "Account disabled"})),
+            JwtErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+fn token_response(
+    state: &AppState,
+    secret: &str,
+    account_id: i32,
+    family_id: &str,
+    refresh_token: String,
+    refresh_token_expiration_date: DateTime<Utc>,
+    current_date: DateTime<Utc>,
+) -> actix_web::HttpResponse {
+    let access_token_timeout = state.settings.jwt.access_token_timeout;
+    let claims = AccessTokenClaims {
+        sub: account_id.to_string(),
+        sid: family_id.to_string(),
+        iat: current_date.timestamp(),
+        exp: (current_date + access_token_timeout).timestamp(),
+    };
+
+    let response_json = ServerResponse::new(
+        JwtTokenResponse {
+            account_id,
+            access_token: access_tokens::sign(&claims, secret),
+            token_type: "Bearer",
+            expires_in: access_token_timeout.num_seconds(),
+            refresh_token,
+            refresh_token_expiration_date,
+        },
+        (),
+    );
+    actix_web::HttpResponse::Ok().json(response_json)
+}
+
+// The token counterpart of start_session, for the clients which can't keep cookies. each login starts a family of
+// refresh tokens
+pub async fn start_token_session(
+    request: &HttpRequest,
+    state: &AppState,
+    account_id: i32,
+    device: Option<String>,
+) -> Result<actix_web::HttpResponse, JwtErrors> {
+    let secret = state.settings.jwt.secret.as_deref().ok_or(JwtErrors::Disabled)?;
+    let family_id = uuid::Uuid::new_v4().to_simple().to_string();
+    let refresh_token = uuid::Uuid::new_v4().to_simple().to_string();
+    let current_date = Utc::now();
+    let expiration_date = current_date + state.settings.jwt.refresh_token_timeout;
+
+    JwtDbExecutor::create(
+        &state.db_pool,
+        &[
+            &tokens::hash_token(&refresh_token),
+            &family_id,
+            &account_id,
+            &device,
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        JwtErrors::from(err)
+    })?;
+
+    record_auth_event(state, request, account_id, AuthEventKind::LoginSuccess).await;
+
+    Ok(token_response(
+        state,
+        secret,
+        account_id,
+        &family_id,
+        refresh_token,
+        expiration_date,
+        current_date,
+    ))
+}
+
+// A refresh token can be used once. using it again means that it was stolen, so the whole login is revoked, whoever
+// holds its latest token
+pub async fn jwt_refresh(
+    request: HttpRequest,
+    body: web::Json<JwtRefreshRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, JwtErrors> {
+    let secret = state.settings.jwt.secret.as_deref().ok_or(JwtErrors::Disabled)?;
+    let next_token = uuid::Uuid::new_v4().to_simple().to_string();
+    let current_date = Utc::now();
+
+    let token_hash = tokens::hash_token(&body.refresh_token);
+    let next_token_hash = tokens::hash_token(&next_token);
+    let rows = JwtDbExecutor::rotate(&state.db_pool, &[&token_hash, &next_token_hash, &current_date])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            JwtErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(JwtErrors::InvalidRefreshToken)?;
+    let account_id: i32 = row.get("account_id");
+
+    let used: bool = row.get("used");
+    if used {
+        record_auth_event(&state, &request, account_id, AuthEventKind::RefreshTokenReuse).await;
+        return Err(JwtErrors::InvalidRefreshToken);
+    }
+    let expired: bool = row.get("expired");
+    if expired {
+        return Err(JwtErrors::InvalidRefreshToken);
+    }
+    let disabled: bool = row.get("disabled");
+    if disabled {
+        return Err(JwtErrors::AccountDisabled);
+    }
+
+    let family_id: String = row.get("family_id");
+    Ok(token_response(
+        &state,
+        secret,
+        account_id,
+        &family_id,
+        next_token,
+        row.get("expiration_date"),
+        current_date,
+    ))
+}
+
+// The logout of a login with tokens. its access tokens are still accepted until they expire
+pub async fn jwt_revoke(
+    body: web::Json<JwtRefreshRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, JwtErrors> {
+    if state.settings.jwt.secret.is_none() {
+        return Err(JwtErrors::Disabled);
+    }
+
+    let token_hash = tokens::hash_token(&body.refresh_token);
+    let rows_count = JwtDbExecutor::revoke(&state.db_pool, &[&token_hash])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            JwtErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(JwtErrors::InvalidRefreshToken);
+    }
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
@@ -0,0 +1,117 @@
+use crate::DbErrors;
+use chrono::prelude::*;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+
+pub struct JwtDbExecutor;
+
+// Like the other tokens, only the hashes of the refresh tokens are stored
+impl JwtDbExecutor {
+    // Starts a family with its first token. the expired families are cleaned up along the way
+    pub async fn create(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM refresh_token WHERE expiration_date < $5
+            )
+            INSERT INTO refresh_token (token_hash, family_id, account_id, device, creation_date, expiration_date)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Replaces the token by the next one of its family, which expires along with it. a token which was already used
+    // has leaked, the whole family is revoked instead. the params are the hashes of the token and of the next token and
+    // the current date. returns the token's family, whether it was used before, whether it has expired and whether
+    // the account is disabled, or nothing if the token doesn't exist
+    pub async fn rotate(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        // Locked, so that of two concurrent uses of a token the second one sees it used
+        let rows = transaction
+            .query(
+                "
+            SELECT refresh_token.family_id, refresh_token.account_id, refresh_token.device, refresh_token.used,
+                refresh_token.expiration_date, refresh_token.expiration_date <= $2 AS expired, account.disabled
+            FROM refresh_token
+            INNER JOIN account ON account.id = refresh_token.account_id
+            WHERE refresh_token.token_hash = $1
+            FOR UPDATE OF refresh_token",
+                &[params[0], params[2]],
+            )
+            .await?;
+        let row = match rows.first() {
+            Some(row) => row,
+            None => return Ok(rows),
+        };
+        let family_id: String = row.get("family_id");
+        let used: bool = row.get("used");
+        let expired: bool = row.get("expired");
+        let disabled: bool = row.get("disabled");
+
+        if used {
+            transaction
+                .execute("DELETE FROM refresh_token WHERE family_id = $1", &[&family_id])
+                .await?;
+        } else if !expired && !disabled {
+            transaction
+                .execute(
+                    "UPDATE refresh_token SET used = TRUE WHERE token_hash = $1",
+                    &params[..1],
+                )
+                .await?;
+            let account_id: i32 = row.get("account_id");
+            let device: Option<String> = row.get("device");
+            let expiration_date: DateTime<Utc> = row.get("expiration_date");
+            transaction
+                .execute(
+                    "
+                INSERT INTO refresh_token (token_hash, family_id, account_id, device, creation_date, expiration_date)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                    &[params[1], &family_id, &account_id, &device, params[2], &expiration_date],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // Revokes the family of the token. returns the number of revoked tokens
+    pub async fn revoke(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            DELETE FROM refresh_token WHERE family_id IN (
+                SELECT family_id FROM refresh_token WHERE token_hash = $1
+            )",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Ends every login with tokens of the account. their access tokens are still accepted until they expire
+    pub async fn revoke_all(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute("DELETE FROM refresh_token WHERE account_id = $1", params)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+}
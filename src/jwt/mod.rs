@@ -0,0 +1,3 @@
+pub mod access_tokens;
+pub mod jwt_controllers;
+pub mod jwt_models;
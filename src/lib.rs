@@ -3,19 +3,33 @@ extern crate log;
 #[macro_use]
 extern crate serde_json;
 
+use crate::mail::mailer::Mailer;
+use crate::sessions::session_store::SessionStore;
+use crate::settings::Settings;
 use deadpool_postgres::{Pool, PoolError};
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tokio_postgres;
 
 pub mod account;
+pub mod admin;
+pub mod api_tokens;
+pub mod auth_events;
 pub mod common;
+pub mod invites;
+pub mod jwt;
+pub mod mail;
 pub mod middlewares;
+pub mod oidc;
+pub mod sessions;
+pub mod settings;
 pub mod todos;
+pub mod two_factor;
 
 pub struct AppState {
     pub db_pool: Pool,
-    pub redis_client: Arc<Mutex<redis::aio::Connection>>,
+    pub session_store: Arc<dyn SessionStore>,
+    pub mailer: Arc<dyn Mailer>,
+    pub settings: Settings,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,40 @@
+use crate::mail::mailer::{Email, Mailer, MailerErrors};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use std::path::PathBuf;
+use tokio::fs;
+
+// Writes every email into its own JSON file instead of sending it. meant for development and tests
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        FileMailer {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailerErrors> {
+        fs::create_dir_all(&self.directory).await?;
+
+        // The files are named by date so that they can be read in the order they were sent
+        let file_name = format!("{}-{}.json", Utc::now().timestamp_nanos(), uuid::Uuid::new_v4());
+        let path = self.directory.join(file_name);
+        let content = serde_json::to_vec_pretty(email).map_err(|_e| MailerErrors::Runtime)?;
+        fs::write(&path, content).await?;
+
+        info!(
+            "Email \"{}\" to {} written to {}",
+            email.subject(),
+            email.to(),
+            path.display()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+use serde::Serialize;
+
+#[derive(Debug)]
+pub enum MailerErrors {
+    Smtp(lettre::smtp::error::Error),
+    Email(lettre_email::error::Error),
+    Io(std::io::Error),
+    Runtime,
+}
+
+impl From<lettre::smtp::error::Error> for MailerErrors {
+    fn from(err: lettre::smtp::error::Error) -> MailerErrors {
+        MailerErrors::Smtp(err)
+    }
+}
+
+impl From<lettre_email::error::Error> for MailerErrors {
+    fn from(err: lettre_email::error::Error) -> MailerErrors {
+        MailerErrors::Email(err)
+    }
+}
+
+impl From<std::io::Error> for MailerErrors {
+    fn from(err: std::io::Error) -> MailerErrors {
+        MailerErrors::Io(err)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Email {
+    to: String,
+    subject: String,
+    body: String,
+}
+
+impl Email {
+    pub fn new(to: String, subject: String, body: String) -> Self {
+        Email { to, subject, body }
+    }
+
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+// Sends the emails of the account flows. the implementation is selected at startup, see `MailerKind`
+#[async_trait(?Send)]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), MailerErrors>;
+}
@@ -0,0 +1,3 @@
+pub mod file_mailer;
+pub mod mailer;
+pub mod smtp_mailer;
@@ -0,0 +1,60 @@
+use crate::mail::mailer::{Email, Mailer, MailerErrors};
+use crate::settings::{MailerSettings, SmtpSecurity};
+use actix_web::{error::BlockingError, web};
+use async_trait::async_trait;
+use lettre::smtp::authentication::Credentials;
+use lettre::{ClientSecurity, ClientTlsParameters, SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+
+pub struct SmtpMailer {
+    client: SmtpClient,
+    from: String,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &MailerSettings) -> Result<Self, MailerErrors> {
+        let tls_parameters = || -> Result<ClientTlsParameters, MailerErrors> {
+            let connector = TlsConnector::new().map_err(|_e| MailerErrors::Runtime)?;
+            Ok(ClientTlsParameters::new(settings.smtp_host.clone(), connector))
+        };
+        let security = match settings.smtp_security {
+            SmtpSecurity::None => ClientSecurity::None,
+            SmtpSecurity::StartTls => ClientSecurity::Required(tls_parameters()?),
+            SmtpSecurity::Tls => ClientSecurity::Wrapper(tls_parameters()?),
+        };
+
+        let mut client = SmtpClient::new((settings.smtp_host.as_str(), settings.smtp_port), security)?;
+        if let (Some(username), Some(password)) = (&settings.smtp_username, &settings.smtp_password) {
+            client = client.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(SmtpMailer {
+            client,
+            from: settings.from.clone(),
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailerErrors> {
+        let message = EmailBuilder::new()
+            .from(self.from.as_str())
+            .to(email.to())
+            .subject(email.subject())
+            .text(email.body())
+            .build()?;
+
+        // lettre's transport is blocking, so it's run on the thread pool
+        let client = self.client.clone();
+        web::block(move || client.transport().send(message.into()))
+            .await
+            .map_err(|err| match err {
+                BlockingError::Error(err) => MailerErrors::Smtp(err),
+                BlockingError::Canceled => MailerErrors::Runtime,
+            })?;
+
+        Ok(())
+    }
+}
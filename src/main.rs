@@ -2,13 +2,36 @@
 extern crate log;
 
 use actix_web::{middleware, web, App, HttpServer};
+use deadpool::managed::{PoolConfig, Timeouts};
 use deadpool_postgres::{config::ConfigError, Config, Pool};
-use productivity::account::account_controllers::{account_login, account_register};
-use productivity::todos::todo_controllers::{todo_create, todo_delete, todo_edit, todo_get};
+use productivity::account::account_controllers::{
+    account_delete, account_email, account_email_confirm, account_login, account_login_link, account_login_link_use,
+    account_login_two_factor, account_logout, account_password, account_password_forgot, account_password_reset,
+    account_profile, account_profile_update, account_register, account_session_revoke, account_sessions,
+    account_verification_resend, account_verify,
+};
+use productivity::admin::admin_controllers::{
+    admin_account, admin_account_disable, admin_account_enable, admin_account_logout, admin_accounts,
+};
+use productivity::api_tokens::api_token_controllers::{api_token_create, api_token_revoke, api_tokens_get};
+use productivity::auth_events::auth_event_controllers::auth_events_get;
+use productivity::invites::invite_controllers::{invite_create, invite_revoke, invites_get};
+use productivity::jwt::jwt_controllers::{jwt_refresh, jwt_revoke};
+use productivity::mail::file_mailer::FileMailer;
+use productivity::mail::mailer::Mailer;
+use productivity::mail::smtp_mailer::SmtpMailer;
+use productivity::oidc::oidc_controllers::{oidc_callback, oidc_login};
+use productivity::sessions::session_memory_store::MemorySessionStore;
+use productivity::sessions::session_postgres_store::PostgresSessionStore;
+use productivity::sessions::session_redis_store::RedisSessionStore;
+use productivity::sessions::session_store::SessionStore;
+use productivity::settings::{MailerKind, RedisSettings, SessionStoreKind, Settings};
+use productivity::todos::todo_controllers::{
+    todo_create, todo_delete, todo_edit, todo_get, todo_get_one, todo_remove, todo_update, todos_create,
+};
+use productivity::two_factor::two_factor_controllers::{two_factor_confirm, two_factor_disable, two_factor_enroll};
 use productivity::{middlewares, AppState};
-use redis;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tokio_postgres::NoTls;
 
 fn create_db_pool() -> Result<Pool, ConfigError> {
@@ -28,13 +51,24 @@ fn create_db_pool() -> Result<Pool, ConfigError> {
     cfg.create_pool(NoTls)
 }
 
-async fn create_redis_client() -> redis::RedisResult<redis::aio::Connection> {
+// The connections are opened lazily, so the server starts even if Redis isn't reachable yet
+fn create_redis_pool(settings: &RedisSettings) -> redis::RedisResult<deadpool_redis::Pool> {
     let host = std::env::var("REDIS_HOST").expect("REDIS_HOST variable missing");
     let port = std::env::var("REDIS_PORT").expect("REDIS_PORT variable missing");
-    let client = redis::Client::open(format!("redis://{}:{}", host, port))?;
-    let connection = client.get_async_connection().await;
+    let timeout = settings.timeout.to_std().ok();
 
-    connection
+    let cfg = deadpool_redis::Config {
+        url: Some(format!("redis://{}:{}", host, port)),
+        pool: Some(PoolConfig {
+            max_size: settings.pool_size,
+            timeouts: Timeouts {
+                wait: timeout,
+                create: timeout,
+                recycle: timeout,
+            },
+        }),
+    };
+    cfg.create_pool()
 }
 
 #[actix_rt::main]
@@ -55,33 +89,195 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    let redis_client = match create_redis_client().await {
-        Ok(client) => Arc::new(Mutex::new(client)),
-        Err(err) => {
-            warn!(target: "warnings", "Warn: {:?}", err);
-            panic!(err);
-        }
+    let settings = Settings::from_env();
+
+    // Redis is only needed when it's the selected session store
+    let session_store: Arc<dyn SessionStore> = match settings.session.store {
+        SessionStoreKind::Redis => match create_redis_pool(&settings.redis) {
+            Ok(pool) => Arc::new(RedisSessionStore::new(pool, settings.redis.max_backoff)),
+            Err(err) => {
+                warn!(target: "warnings", "Warn: {:?}", err);
+                panic!(err);
+            }
+        },
+        SessionStoreKind::Postgres => Arc::new(PostgresSessionStore::new(Pool::clone(&db_pool))),
+        SessionStoreKind::Memory => Arc::new(MemorySessionStore::new()),
+    };
+
+    let mailer: Arc<dyn Mailer> = match settings.mailer.kind {
+        MailerKind::Smtp => match SmtpMailer::new(&settings.mailer) {
+            Ok(mailer) => Arc::new(mailer),
+            Err(err) => {
+                warn!(target: "warnings", "Warn: {:?}", err);
+                panic!("Can't create the smtp mailer");
+            }
+        },
+        MailerKind::File => Arc::new(FileMailer::new(&settings.mailer.directory)),
     };
 
     HttpServer::new(move || {
-        let redis_client = Arc::clone(&redis_client);
+        let session_store = Arc::clone(&session_store);
+        let mailer = Arc::clone(&mailer);
         let db_pool = Pool::clone(&db_pool);
+        let settings = settings.clone();
 
         App::new()
             .wrap(middleware::Logger::default())
-            .data(AppState { db_pool, redis_client })
+            .data(AppState {
+                db_pool,
+                session_store,
+                mailer,
+                settings,
+            })
+            .service(
+                web::scope("/api/todos")
+                    .wrap(middlewares::verification::Verification)
+                    .wrap(middlewares::csrf::Csrf)
+                    .wrap(middlewares::auth::Authentication)
+                    .service(
+                        web::resource("")
+                            .route(web::get().to(todo_get))
+                            .route(web::post().to(todos_create)),
+                    )
+                    .service(
+                        web::resource("/{id}")
+                            .route(web::get().to(todo_get_one))
+                            .route(web::patch().to(todo_update))
+                            .route(web::delete().to(todo_remove)),
+                    ),
+            )
+            // Deprecated, kept for the clients which don't use /api/todos yet
             .service(
                 web::scope("/api/todo")
+                    .wrap(middlewares::verification::Verification)
+                    .wrap(middlewares::csrf::Csrf)
                     .wrap(middlewares::auth::Authentication)
+                    .wrap(middleware::DefaultHeaders::new().header("Deprecation", "true"))
                     .route("/create", web::post().to(todo_create))
                     .route("/get", web::get().to(todo_get))
                     .route("/edit", web::post().to(todo_edit))
                     .route("/delete", web::post().to(todo_delete)),
             )
+            .service(
+                web::scope("/api/admin")
+                    .wrap(middlewares::role::Admin)
+                    .wrap(middlewares::csrf::Csrf)
+                    .wrap(middlewares::auth::Authentication)
+                    .route("/accounts", web::get().to(admin_accounts))
+                    .route("/accounts/{id}", web::get().to(admin_account))
+                    .route("/accounts/{id}/disable", web::post().to(admin_account_disable))
+                    .route("/accounts/{id}/enable", web::post().to(admin_account_enable))
+                    .route("/accounts/{id}/logout", web::post().to(admin_account_logout)),
+            )
             .service(
                 web::scope("/api/account")
+                    .service(
+                        web::resource("")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::delete().to(account_delete)),
+                    )
                     .route("/register", web::post().to(account_register))
-                    .route("/login", web::post().to(account_login)),
+                    .route("/login", web::post().to(account_login))
+                    .route("/login/2fa", web::post().to(account_login_two_factor))
+                    .route("/login/link", web::post().to(account_login_link))
+                    .route("/login/link/{token}", web::get().to(account_login_link_use))
+                    .route("/oidc/login", web::get().to(oidc_login))
+                    .route("/oidc/callback", web::get().to(oidc_callback))
+                    .route("/token/refresh", web::post().to(jwt_refresh))
+                    .route("/token/revoke", web::post().to(jwt_revoke))
+                    .service(
+                        web::resource("/logout")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::post().to(account_logout)),
+                    )
+                    .service(
+                        web::resource("/sessions")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::get().to(account_sessions)),
+                    )
+                    .service(
+                        web::resource("/email")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::post().to(account_email)),
+                    )
+                    .route("/email/confirm", web::get().to(account_email_confirm))
+                    .service(
+                        web::resource("/me")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::get().to(account_profile))
+                            .route(web::patch().to(account_profile_update)),
+                    )
+                    .service(
+                        web::resource("/activity")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::get().to(auth_events_get)),
+                    )
+                    .service(
+                        web::resource("/sessions/{id}")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::delete().to(account_session_revoke)),
+                    )
+                    .service(
+                        web::resource("/password")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::post().to(account_password)),
+                    )
+                    .service(
+                        web::resource("/tokens")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::get().to(api_tokens_get))
+                            .route(web::post().to(api_token_create)),
+                    )
+                    .service(
+                        web::resource("/tokens/{id}")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::delete().to(api_token_revoke)),
+                    )
+                    .service(
+                        web::resource("/invites")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::get().to(invites_get))
+                            .route(web::post().to(invite_create)),
+                    )
+                    .service(
+                        web::resource("/invites/{id}")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::delete().to(invite_revoke)),
+                    )
+                    .service(
+                        web::resource("/2fa")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::delete().to(two_factor_disable)),
+                    )
+                    .service(
+                        web::resource("/2fa/enroll")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::post().to(two_factor_enroll)),
+                    )
+                    .service(
+                        web::resource("/2fa/confirm")
+                            .wrap(middlewares::csrf::Csrf)
+                            .wrap(middlewares::auth::Authentication)
+                            .route(web::post().to(two_factor_confirm)),
+                    )
+                    .route("/password/forgot", web::post().to(account_password_forgot))
+                    .route("/password/reset", web::post().to(account_password_reset))
+                    .route("/verify", web::get().to(account_verify))
+                    .route("/verify/resend", web::post().to(account_verification_resend)),
             )
     })
     .bind(format!("{}:{}", host, port))?
@@ -1,15 +1,22 @@
+use crate::api_tokens::api_token_models::{ApiTokenDbExecutor, ApiTokenScope};
+use crate::auth_events::auth_event_controllers::create_auth_event;
+use crate::auth_events::auth_event_models::AuthEventKind;
 use crate::common::responses::ServerResponse;
+use crate::common::{cookies, requests, tokens};
+use crate::jwt::access_tokens::{self, AccessTokenErrors};
+use crate::middlewares::csrf;
+use crate::sessions::session_store::SessionErrors;
 use crate::AppState;
 use actix_http;
 use actix_service::{Service, Transform};
 use actix_web::web::BytesMut;
 use actix_web::{
-    dev::{HttpResponseBuilder, ServiceRequest, ServiceResponse},
-    error, http, Error, HttpMessage,
+    dev::{HttpResponseBuilder, Payload, ServiceRequest, ServiceResponse},
+    error, http, Error, FromRequest, HttpMessage, HttpRequest,
 };
-use futures::future::{ok, Ready};
+use chrono::prelude::*;
+use futures::future::{err, ok, Ready};
 use futures::Future;
-use redis::AsyncCommands;
 use std::cell::RefCell;
 use std::pin::Pin;
 use std::rc::Rc;
@@ -19,6 +26,21 @@ use tokio::stream::StreamExt;
 #[derive(Debug)]
 pub enum AuthErrors {
     Forbidden,
+    SessionExpired,
+    InsufficientScope,
+    Unverified,
+    InvalidCsrfToken,
+    Unavailable,
+    Server,
+}
+
+impl From<SessionErrors> for AuthErrors {
+    fn from(err: SessionErrors) -> AuthErrors {
+        match err {
+            SessionErrors::Unavailable => AuthErrors::Unavailable,
+            _ => AuthErrors::Forbidden,
+        }
+    }
 }
 
 impl std::fmt::Display for AuthErrors {
@@ -31,12 +53,26 @@ impl error::ResponseError for AuthErrors {
     fn status_code(&self) -> http::StatusCode {
         match *self {
             AuthErrors::Forbidden => http::StatusCode::FORBIDDEN,
+            AuthErrors::SessionExpired => http::StatusCode::UNAUTHORIZED,
+            AuthErrors::InsufficientScope => http::StatusCode::FORBIDDEN,
+            AuthErrors::Unverified => http::StatusCode::FORBIDDEN,
+            AuthErrors::InvalidCsrfToken => http::StatusCode::FORBIDDEN,
+            AuthErrors::Unavailable => http::StatusCode::SERVICE_UNAVAILABLE,
+            AuthErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> actix_web::HttpResponse {
         let response_json = match self {
             AuthErrors::Forbidden => ServerResponse::new((), json!({"error": "Access forbidden"})),
+            AuthErrors::SessionExpired => ServerResponse::new((), json!({"error": "Session expired"})),
+            AuthErrors::InsufficientScope => {
+                ServerResponse::new((), json!({"error": "The token's scope doesn't allow this request"}))
+            }
+            AuthErrors::Unverified => ServerResponse::new((), json!({"error": "Email not verified"})),
+            AuthErrors::InvalidCsrfToken => ServerResponse::new((), json!({"error": "Invalid CSRF token"})),
+            AuthErrors::Unavailable => ServerResponse::new((), json!({"error": "Service unavailable"})),
+            AuthErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
         };
 
         HttpResponseBuilder::new(self.status_code())
@@ -45,6 +81,93 @@ impl error::ResponseError for AuthErrors {
     }
 }
 
+// Inserted into the request extensions by the authentication middleware. handlers wrapped by the middleware
+// extract it to know which account and session made the request. requests made with an api token have no session
+#[derive(Debug, Clone)]
+pub struct AuthenticatedAccount {
+    account_id: i32,
+    session_id: Option<String>,
+}
+
+impl AuthenticatedAccount {
+    pub fn account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+}
+
+impl FromRequest for AuthenticatedAccount {
+    type Error = AuthErrors;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        match req.extensions().get::<AuthenticatedAccount>() {
+            Some(account) => ok(account.clone()),
+            None => err(AuthErrors::Forbidden),
+        }
+    }
+}
+
+// Api tokens and access tokens are sent as `Authorization: Bearer <token>`
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let authorization = req.headers().get(http::header::AUTHORIZATION)?.to_str().ok()?;
+    if !authorization.starts_with("Bearer ") {
+        return None;
+    }
+
+    let token = authorization["Bearer ".len()..].trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(token.to_string())
+    }
+}
+
+async fn authenticate_api_token(
+    state: &AppState,
+    method: &http::Method,
+    token: &str,
+) -> Result<AuthenticatedAccount, AuthErrors> {
+    let rows = ApiTokenDbExecutor::authenticate(&state.db_pool, &[&tokens::hash_token(token), &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            AuthErrors::Server
+        })?;
+    let row = rows.first().ok_or(AuthErrors::Forbidden)?;
+
+    let scope: String = row.get("scope");
+    let scope: ApiTokenScope = scope.parse().map_err(|_e| AuthErrors::Forbidden)?;
+    if !scope.allows(method) {
+        return Err(AuthErrors::InsufficientScope);
+    }
+
+    Ok(AuthenticatedAccount {
+        account_id: row.get("account_id"),
+        session_id: None,
+    })
+}
+
+// The access tokens are checked with their signature alone, they last too little to be worth a lookup. the account
+// is only looked up again when the tokens are refreshed
+fn authenticate_access_token(state: &AppState, token: &str) -> Result<AuthenticatedAccount, AuthErrors> {
+    let secret = state.settings.jwt.secret.as_deref().ok_or(AuthErrors::Forbidden)?;
+    let claims = access_tokens::verify(token, secret, Utc::now()).map_err(|err| match err {
+        AccessTokenErrors::Expired => AuthErrors::SessionExpired,
+        AccessTokenErrors::Invalid => AuthErrors::Forbidden,
+    })?;
+
+    Ok(AuthenticatedAccount {
+        account_id: claims.sub.parse().map_err(|_e| AuthErrors::Forbidden)?,
+        session_id: None,
+    })
+}
+
+// Authenticates the request either with the session cookie, with an access token or with an api token
 pub struct Authentication;
 
 impl<S: 'static, B> Transform<S> for Authentication
@@ -91,19 +214,24 @@ where
 
         Box::pin(async move {
             let state = req.app_data::<AppState>().unwrap();
+
+            if let Some(token) = bearer_token(&req) {
+                let account = if access_tokens::is_access_token(&token) {
+                    authenticate_access_token(&state, &token)?
+                } else {
+                    authenticate_api_token(&state, req.method(), &token).await?
+                };
+                req.extensions_mut().insert(account);
+
+                return svc.call(req).await;
+            }
+
             let session_cookie_value = req
-                .cookie("session_id")
+                .cookie(cookies::SESSION_COOKIE)
                 .ok_or(AuthErrors::Forbidden)?
                 .value()
                 .to_string();
 
-            let account_id = req
-                .cookie("account_id")
-                .ok_or(AuthErrors::Forbidden)?
-                .value()
-                .parse::<i32>()
-                .map_err(|_e| AuthErrors::Forbidden)?;
-
             // Get the body out of the request
             let mut body = BytesMut::new();
             let mut stream = req.take_payload();
@@ -111,25 +239,75 @@ where
                 body.extend_from_slice(&chunk?);
             }
 
-            let redis_client = state.redis_client.clone();
-            let session_id: String = redis_client
-                .lock()
-                .await
-                .get(account_id)
+            let idle_timeout = state.settings.session.idle_timeout;
+            let current_date = Utc::now();
+
+            let mut session = state
+                .session_store
+                .get(&session_cookie_value)
                 .await
-                .map_err(|_e| AuthErrors::Forbidden)?;
+                .map_err(AuthErrors::from)?
+                .ok_or(AuthErrors::Forbidden)?;
+
+            // The expired session is removed, so that its expiration is only recorded once
+            if session.is_expired(current_date, idle_timeout) {
+                state
+                    .session_store
+                    .revoke(session.account_id(), session.id())
+                    .await
+                    .map_err(AuthErrors::from)?;
+                let ip = requests::client_ip(req.peer_addr(), req.headers(), &state.settings.proxy.trusted_proxies);
+                let user_agent = requests::user_agent(req.headers());
+                create_auth_event(
+                    &state,
+                    session.account_id(),
+                    AuthEventKind::SessionExpiration,
+                    ip,
+                    user_agent,
+                )
+                .await;
 
-            if session_id != session_cookie_value {
-                return Err(AuthErrors::Forbidden)?;
+                return Err(AuthErrors::SessionExpired)?;
             }
 
+            // Every authenticated request extends the session up to its absolute expiration date
+            state
+                .session_store
+                .touch(&mut session, current_date, idle_timeout)
+                .await
+                .map_err(AuthErrors::from)?;
+
+            req.extensions_mut().insert(AuthenticatedAccount {
+                account_id: session.account_id(),
+                session_id: Some(session.id().to_string()),
+            });
+
             // Put a payload back into the request. needs to be done because it was consumed earlier
             // by the stream
             let mut payload = actix_http::h1::Payload::empty();
             payload.unread_data(body.into());
             req.set_payload(payload.into());
 
-            let res = svc.call(req).await?;
+            let mut res = svc.call(req).await?;
+
+            // Re-issue the cookies so that their expiration follows the renewed session. this also gives a csrf cookie to
+            // the sessions started before there was one. a handler which ended the session has already removed them
+            let session_cookie_set = res
+                .response()
+                .cookies()
+                .any(|cookie| cookie.name() == cookies::SESSION_COOKIE);
+            if !session_cookie_set {
+                let cookie_settings = &state.settings.cookie;
+                let max_age = (session.idle_expiration_date(idle_timeout) - current_date).num_seconds();
+                let session_cookie = cookies::session_cookie(session.id().to_string(), max_age, cookie_settings);
+                let csrf_cookie = cookies::csrf_cookie(
+                    csrf::csrf_token(&cookie_settings.csrf_secret, session.id()),
+                    max_age,
+                    cookie_settings,
+                );
+                res.response_mut().add_cookie(&session_cookie)?;
+                res.response_mut().add_cookie(&csrf_cookie)?;
+            }
 
             Ok(res)
         })
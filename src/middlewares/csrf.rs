@@ -0,0 +1,145 @@
+use crate::middlewares::auth::{AuthErrors, AuthenticatedAccount};
+use crate::AppState;
+use actix_service::{Service, Transform};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, http, Error, HttpMessage};
+use futures::future::{ok, Ready};
+use futures::Future;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+// The token is the session signed with the server's secret, so it needs no storage and can't be used with another
+// session. knowing it doesn't reveal the session id, and it can't be made without the secret
+pub fn csrf_token(secret: &str, session_id: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.input(session_id.as_bytes());
+
+    mac.result().code().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn is_safe_method(method: &http::Method) -> bool {
+    matches!(
+        *method,
+        http::Method::GET | http::Method::HEAD | http::Method::OPTIONS | http::Method::TRACE
+    )
+}
+
+// Compares the whole tokens, so that the time taken doesn't tell how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Rejects the state-changing requests of a session which don't repeat its csrf token in the `X-CSRF-Token` header. a
+// cross-site form can make the browser send the session cookie, but can't read the csrf cookie to set the header.
+// requests made with an api token don't rely on cookies and are let through. must be wrapped by the authentication
+// middleware, which provides the session
+pub struct Csrf;
+
+impl<S: 'static, B> Transform<S> for Csrf
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CsrfMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for CsrfMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let mut svc = self.service.clone();
+
+        Box::pin(async move {
+            if is_safe_method(req.method()) {
+                return svc.call(req).await;
+            }
+
+            let session_id = req
+                .extensions()
+                .get::<AuthenticatedAccount>()
+                .ok_or(AuthErrors::Forbidden)?
+                .session_id()
+                .map(|session_id| session_id.to_string());
+            let session_id = match session_id {
+                Some(session_id) => session_id,
+                None => return svc.call(req).await,
+            };
+
+            let state = req.app_data::<AppState>().unwrap();
+            let header_token = req
+                .headers()
+                .get(CSRF_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .ok_or(AuthErrors::InvalidCsrfToken)?;
+            if !constant_time_eq(
+                header_token.as_bytes(),
+                csrf_token(&state.settings.cookie.csrf_secret, &session_id).as_bytes(),
+            ) {
+                return Err(AuthErrors::InvalidCsrfToken.into());
+            }
+
+            svc.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csrf_token() {
+        let token = csrf_token("secret", "1234");
+
+        assert_eq!(token.len(), 64);
+        assert_eq!(token, csrf_token("secret", "1234"));
+        assert_ne!(token, csrf_token("secret", "1235"));
+        assert_ne!(token, csrf_token("another secret", "1234"));
+        assert!(constant_time_eq(
+            token.as_bytes(),
+            csrf_token("secret", "1234").as_bytes()
+        ));
+        assert!(!constant_time_eq(
+            token.as_bytes(),
+            csrf_token("secret", "1235").as_bytes()
+        ));
+        assert!(!constant_time_eq(token.as_bytes(), b""));
+    }
+}
@@ -1 +1,4 @@
 pub mod auth;
+pub mod csrf;
+pub mod role;
+pub mod verification;
@@ -0,0 +1,89 @@
+use crate::account::account_models::{AccountDbExecutor, AccountRole};
+use crate::middlewares::auth::{AuthErrors, AuthenticatedAccount};
+use crate::AppState;
+use actix_service::{Service, Transform};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpMessage};
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+// Rejects the accounts which aren't admins. api tokens are refused too, so that a leaked token can't give access to
+// the other accounts. must be wrapped by the authentication middleware, which provides the account
+pub struct Admin;
+
+impl<S: 'static, B> Transform<S> for Admin
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AdminMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AdminMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct AdminMiddleware<S> {
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for AdminMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let mut svc = self.service.clone();
+
+        Box::pin(async move {
+            let state = req.app_data::<AppState>().unwrap();
+
+            let account = req
+                .extensions()
+                .get::<AuthenticatedAccount>()
+                .cloned()
+                .ok_or(AuthErrors::Forbidden)?;
+            if account.session_id().is_none() {
+                return Err(AuthErrors::Forbidden.into());
+            }
+
+            // Like the verification, the role is read on every request so that a demotion takes effect immediately
+            let rows = AccountDbExecutor::get(&state.db_pool, &[&account.account_id()])
+                .await
+                .map_err(|err| {
+                    warn!(target: "warnings", "Warn: {:?}", err);
+                    AuthErrors::Server
+                })?;
+            let role: String = match rows.first() {
+                Some(row) => row.get("role"),
+                None => return Err(AuthErrors::Forbidden.into()),
+            };
+            if role.parse() != Ok(AccountRole::Admin) {
+                return Err(AuthErrors::Forbidden.into());
+            }
+
+            svc.call(req).await
+        })
+    }
+}
@@ -0,0 +1,90 @@
+use crate::account::account_models::AccountDbExecutor;
+use crate::middlewares::auth::{AuthErrors, AuthenticatedAccount};
+use crate::settings::VerificationPolicy;
+use crate::AppState;
+use actix_service::{Service, Transform};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpMessage};
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+// Rejects the accounts which didn't verify their email when the verification policy is `Features`. must be wrapped
+// by the authentication middleware, which provides the account
+pub struct Verification;
+
+impl<S: 'static, B> Transform<S> for Verification
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = VerificationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(VerificationMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct VerificationMiddleware<S> {
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for VerificationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let mut svc = self.service.clone();
+
+        Box::pin(async move {
+            let state = req.app_data::<AppState>().unwrap();
+            if state.settings.account.verification_policy != VerificationPolicy::Features {
+                return svc.call(req).await;
+            }
+
+            let account_id = req
+                .extensions()
+                .get::<AuthenticatedAccount>()
+                .map(|account| account.account_id())
+                .ok_or(AuthErrors::Forbidden)?;
+
+            // The account is read on every request so that the verification takes effect without a new login
+            let rows = AccountDbExecutor::get(&state.db_pool, &[&account_id])
+                .await
+                .map_err(|err| {
+                    warn!(target: "warnings", "Warn: {:?}", err);
+                    AuthErrors::Server
+                })?;
+            let verified: bool = match rows.first() {
+                Some(row) => row.get("verified"),
+                None => return Err(AuthErrors::Forbidden.into()),
+            };
+            if !verified {
+                return Err(AuthErrors::Unverified.into());
+            }
+
+            svc.call(req).await
+        })
+    }
+}
@@ -0,0 +1,3 @@
+pub mod oidc_client;
+pub mod oidc_controllers;
+pub mod oidc_models;
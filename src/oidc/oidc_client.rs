@@ -0,0 +1,384 @@
+use crate::settings::OidcSettings;
+use chrono::prelude::*;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT_IN_SECONDS: u64 = 10;
+// Tolerated difference between the clocks of the provider and the api
+const CLOCK_SKEW_IN_SECONDS: i64 = 60;
+const SCOPE: &str = "openid email";
+
+#[derive(Debug)]
+pub enum OidcClientErrors {
+    Request(reqwest::Error),
+    // The provider's configuration doesn't match the settings
+    Configuration,
+    InvalidToken,
+}
+
+impl From<reqwest::Error> for OidcClientErrors {
+    fn from(err: reqwest::Error) -> OidcClientErrors {
+        OidcClientErrors::Request(err)
+    }
+}
+
+// The part of the provider's discovery document which is used
+#[derive(Deserialize)]
+pub struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct JsonWebKeys {
+    keys: Vec<JsonWebKey>,
+}
+
+// Only the RSA keys are used, the other kinds don't have a modulus and an exponent
+#[derive(Deserialize)]
+struct JsonWebKey {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    aud: Audience,
+    // The party the token was issued to, needed when there are several audiences
+    azp: Option<String>,
+    exp: i64,
+    nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+fn client() -> Result<reqwest::Client, OidcClientErrors> {
+    Ok(reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_IN_SECONDS))
+        .build()?)
+}
+
+// The S256 code challenge of PKCE, sent with the authorization request. the verifier is only sent with the code
+pub fn code_challenge(code_verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(code_verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+// The document is fetched on every login, they are rare enough for it not to matter
+pub async fn discover(issuer: &str) -> Result<ProviderMetadata, OidcClientErrors> {
+    let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    let metadata: ProviderMetadata = client()?.get(&url).send().await?.error_for_status()?.json().await?;
+    if metadata.issuer != issuer {
+        return Err(OidcClientErrors::Configuration);
+    }
+
+    Ok(metadata)
+}
+
+// Where the browser is sent to login at the provider
+pub fn authorization_url(
+    metadata: &ProviderMetadata,
+    settings: &OidcSettings,
+    state: &str,
+    nonce: &str,
+    code_challenge: &str,
+) -> Result<Url, OidcClientErrors> {
+    Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", &settings.client_id),
+            ("redirect_uri", &settings.redirect_url),
+            ("scope", SCOPE),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|_err| OidcClientErrors::Configuration)
+}
+
+// Trades the code the provider sent the browser back with for an id token. the confidential clients authenticate
+// with http basic
+pub async fn exchange_code(
+    metadata: &ProviderMetadata,
+    settings: &OidcSettings,
+    code: &str,
+    code_verifier: &str,
+) -> Result<String, OidcClientErrors> {
+    let mut request = client()?.post(&metadata.token_endpoint).form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &settings.redirect_url),
+        ("client_id", &settings.client_id),
+        ("code_verifier", code_verifier),
+    ]);
+    if let Some(client_secret) = &settings.client_secret {
+        request = request.basic_auth(&settings.client_id, Some(client_secret));
+    }
+    let response: TokenResponse = request.send().await?.error_for_status()?.json().await?;
+
+    Ok(response.id_token)
+}
+
+pub async fn fetch_keys(metadata: &ProviderMetadata) -> Result<JsonWebKeys, OidcClientErrors> {
+    Ok(client()?
+        .get(&metadata.jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(part: &str) -> Result<T, OidcClientErrors> {
+    let bytes = base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|_err| OidcClientErrors::InvalidToken)?;
+    serde_json::from_slice(&bytes).map_err(|_err| OidcClientErrors::InvalidToken)
+}
+
+// Whether the RS256 signature was made by the key
+fn verify_signature(key: &JsonWebKey, message: &[u8], signature: &[u8]) -> Result<bool, OidcClientErrors> {
+    let component = |value: &Option<String>| {
+        value
+            .as_ref()
+            .and_then(|value| base64::decode_config(value, base64::URL_SAFE_NO_PAD).ok())
+            .and_then(|bytes| BigNum::from_slice(&bytes).ok())
+            .ok_or(OidcClientErrors::InvalidToken)
+    };
+    let rsa = Rsa::from_public_components(component(&key.n)?, component(&key.e)?)
+        .map_err(|_err| OidcClientErrors::InvalidToken)?;
+    let public_key = PKey::from_rsa(rsa).map_err(|_err| OidcClientErrors::InvalidToken)?;
+    let mut verifier =
+        Verifier::new(MessageDigest::sha256(), &public_key).map_err(|_err| OidcClientErrors::InvalidToken)?;
+    verifier
+        .update(message)
+        .map_err(|_err| OidcClientErrors::InvalidToken)?;
+
+    Ok(verifier.verify(signature).unwrap_or(false))
+}
+
+// Checks that the token was signed by one of the provider's keys, for this client and this login. only RS256 is
+// accepted, which every provider supports
+pub fn validate_id_token(
+    id_token: &str,
+    keys: &JsonWebKeys,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    current_date: DateTime<Utc>,
+) -> Result<IdTokenClaims, OidcClientErrors> {
+    let parts: Vec<&str> = id_token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(OidcClientErrors::InvalidToken);
+    }
+    let header: IdTokenHeader = decode_part(parts[0])?;
+    if header.alg != "RS256" {
+        return Err(OidcClientErrors::InvalidToken);
+    }
+
+    let message = format!("{}.{}", parts[0], parts[1]);
+    let signature =
+        base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).map_err(|_err| OidcClientErrors::InvalidToken)?;
+    let mut signed = false;
+    for key in &keys.keys {
+        let usable = key.kty == "RSA" && key.key_use.as_deref().unwrap_or("sig") == "sig";
+        let matching = header.kid.is_none() || key.kid == header.kid;
+        // A key which can't be read is skipped rather than failing the login, another one may have signed the token
+        if usable && matching && verify_signature(key, message.as_bytes(), &signature).unwrap_or(false) {
+            signed = true;
+            break;
+        }
+    }
+    if !signed {
+        return Err(OidcClientErrors::InvalidToken);
+    }
+
+    let claims: IdTokenClaims = decode_part(parts[1])?;
+    let audience_matches = match &claims.aud {
+        Audience::One(audience) => audience == client_id,
+        Audience::Many(audiences) => {
+            audiences.iter().any(|audience| audience == client_id)
+                && (audiences.len() == 1 || claims.azp.as_deref() == Some(client_id))
+        }
+    };
+    if claims.iss != issuer
+        || !audience_matches
+        || claims.exp + CLOCK_SKEW_IN_SECONDS < current_date.timestamp()
+        || claims.nonce.as_deref() != Some(nonce)
+    {
+        return Err(OidcClientErrors::InvalidToken);
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+
+    const ISSUER: &str = "https://idp.example.com";
+    const CLIENT_ID: &str = "productivity";
+    const NONCE: &str = "n-0S6_WzA2Mj";
+
+    fn sign(private_key: &PKey<Private>, header: &serde_json::Value, claims: &serde_json::Value) -> String {
+        let message = format!(
+            "{}.{}",
+            base64::encode_config(header.to_string(), base64::URL_SAFE_NO_PAD),
+            base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD)
+        );
+        let mut signer = Signer::new(MessageDigest::sha256(), private_key).unwrap();
+        signer.update(message.as_bytes()).unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+
+        format!(
+            "{}.{}",
+            message,
+            base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    fn keys(private_key: &PKey<Private>) -> JsonWebKeys {
+        let rsa = private_key.rsa().unwrap();
+        let jwks = json!({"keys": [{
+            "kty": "RSA",
+            "kid": "key-1",
+            "use": "sig",
+            "n": base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+            "e": base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+        }]});
+
+        serde_json::from_value(jwks).unwrap()
+    }
+
+    fn claims(current_date: DateTime<Utc>) -> serde_json::Value {
+        json!({
+            "iss": ISSUER,
+            "sub": "248289761001",
+            "aud": CLIENT_ID,
+            "exp": current_date.timestamp() + 300,
+            "iat": current_date.timestamp(),
+            "nonce": NONCE,
+            "email": "dimashur@gmail.com",
+            "email_verified": true,
+        })
+    }
+
+    // The unpadded base64url encoding of the verifier's sha256
+    #[test]
+    fn test_code_challenge() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mJ92K9jvJ2LaNsEw6GjHSb3Kc5ymA0"),
+            "pgvtzOcRCX8NGx2bmaYk5Jafp5ZdJxFLs3tAxFFtsXA"
+        );
+    }
+
+    #[test]
+    fn test_validate_id_token() {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let keys = keys(&private_key);
+        let current_date = Utc::now();
+        let header = json!({"alg": "RS256", "kid": "key-1"});
+
+        let id_token = sign(&private_key, &header, &claims(current_date));
+        let claims_result = validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, NONCE, current_date).unwrap();
+        assert_eq!(claims_result.sub, "248289761001");
+        assert_eq!(claims_result.email.as_deref(), Some("dimashur@gmail.com"));
+        assert!(claims_result.email_verified);
+
+        // Signed by another key
+        let other_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let id_token = sign(&other_key, &header, &claims(current_date));
+        assert!(validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, NONCE, current_date).is_err());
+
+        // Not signed at all
+        let id_token = sign(&private_key, &json!({"alg": "none"}), &claims(current_date));
+        assert!(validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, NONCE, current_date).is_err());
+
+        let mut wrong_claims = claims(current_date);
+        wrong_claims["aud"] = json!(["another-client", CLIENT_ID]);
+        let id_token = sign(&private_key, &header, &wrong_claims);
+        assert!(validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, NONCE, current_date).is_err());
+        wrong_claims["azp"] = json!(CLIENT_ID);
+        let id_token = sign(&private_key, &header, &wrong_claims);
+        assert!(validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, NONCE, current_date).is_ok());
+
+        let id_token = sign(&private_key, &header, &claims(current_date));
+        assert!(validate_id_token(
+            &id_token,
+            &keys,
+            "https://evil.example.com",
+            CLIENT_ID,
+            NONCE,
+            current_date
+        )
+        .is_err());
+        assert!(validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, "another-nonce", current_date).is_err());
+
+        let expired_date = current_date + chrono::Duration::seconds(300 + CLOCK_SKEW_IN_SECONDS + 1);
+        assert!(validate_id_token(&id_token, &keys, ISSUER, CLIENT_ID, NONCE, expired_date).is_err());
+
+        // The keys which can't be read are skipped
+        let mut jwks = json!({"keys": [
+            {"kty": "RSA", "kid": "key-0", "n": "not base64!", "e": "AQAB"},
+            {"kty": "RSA", "kid": "key-0"},
+        ]});
+        let rsa = private_key.rsa().unwrap();
+        jwks["keys"].as_array_mut().unwrap().push(json!({
+            "kty": "RSA",
+            "kid": "key-1",
+            "n": base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+            "e": base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+        }));
+        let mixed_keys: JsonWebKeys = serde_json::from_value(jwks).unwrap();
+        let unnamed_key_token = sign(&private_key, &json!({"alg": "RS256"}), &claims(current_date));
+        assert!(validate_id_token(&unnamed_key_token, &mixed_keys, ISSUER, CLIENT_ID, NONCE, current_date).is_ok());
+
+        // Tampered with after the signature
+        let parts: Vec<&str> = id_token.split('.').collect();
+        let mut tampered_claims = claims(current_date);
+        tampered_claims["email"] = json!("admin@gmail.com");
+        let tampered = format!(
+            "{}.{}.{}",
+            parts[0],
+            base64::encode_config(tampered_claims.to_string(), base64::URL_SAFE_NO_PAD),
+            parts[2]
+        );
+        assert!(validate_id_token(&tampered, &keys, ISSUER, CLIENT_ID, NONCE, current_date).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::account::account_controllers::{start_session, start_two_factor_challenge, AccountLoginErrors};
+use crate::account::account_passwords::{self, PasswordErrors};
+use crate::auth_events::auth_event_controllers::record_auth_event;
+use crate::auth_events::auth_event_models::AuthEventKind;
+use crate::common::cookies;
+use crate::common::responses::ServerResponse;
+use crate::common::tokens;
+use crate::common::validators::Validator;
+use crate::oidc::oidc_client::{self, OidcClientErrors};
+use crate::oidc::oidc_models::OidcDbExecutor;
+use crate::sessions::session_store::SessionErrors;
+use crate::settings::RegistrationMode;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web, HttpMessage, HttpRequest};
+use chrono::prelude::*;
+use serde::Deserialize;
+use uuid;
+
+#[derive(Deserialize)]
+pub struct OidcLoginQuery {
+    device: Option<String>,
+}
+
+// Either a code or an error, along with the state the login was started with
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OidcErrors {
+    Disabled,
+    InvalidState,
+    Refused,
+    Provider,
+    InvalidToken,
+    UnverifiedEmail,
+    NoAccount,
+    AccountDisabled,
+    Server,
+}
+
+impl From<OidcClientErrors> for OidcErrors {
+    fn from(err: OidcClientErrors) -> OidcErrors {
+        match err {
+            OidcClientErrors::InvalidToken => OidcErrors::InvalidToken,
+            _ => OidcErrors::Provider,
+        }
+    }
+}
+
+impl From<DbErrors> for OidcErrors {
+    fn from(_err: DbErrors) -> OidcErrors {
+        OidcErrors::Server
+    }
+}
+
+impl From<SessionErrors> for OidcErrors {
+    fn from(_err: SessionErrors) -> OidcErrors {
+        OidcErrors::Server
+    }
+}
+
+impl From<PasswordErrors> for OidcErrors {
+    fn from(_err: PasswordErrors) -> OidcErrors {
+        OidcErrors::Server
+    }
+}
+
+impl From<AccountLoginErrors> for OidcErrors {
+    fn from(_err: AccountLoginErrors) -> OidcErrors {
+        OidcErrors::Server
+    }
+}
+
+impl std::fmt::Display for OidcErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for OidcErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            OidcErrors::Disabled => http::StatusCode::NOT_FOUND,
+            OidcErrors::InvalidState => http::StatusCode::UNAUTHORIZED,
+            OidcErrors::Refused => http::StatusCode::UNAUTHORIZED,
+            OidcErrors::Provider => http::StatusCode::BAD_GATEWAY,
+            OidcErrors::InvalidToken => http::StatusCode::UNAUTHORIZED,
+            OidcErrors::UnverifiedEmail => http::StatusCode::FORBIDDEN,
+            OidcErrors::NoAccount => http::StatusCode::FORBIDDEN,
+            OidcErrors::AccountDisabled => http::StatusCode::FORBIDDEN,
+            OidcErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            OidcErrors::Disabled => ServerResponse::new((), json!({"error": "OpenID Connect login is disabled"})),
+            OidcErrors::InvalidState => ServerResponse::new((), json!({"error": "Invalid or expired login"})),
+            OidcErrors::Refused => {
+                ServerResponse::new((), json!({"error": "The login was refused by the identity provider"}))
+            }
+            OidcErrors::Provider => ServerResponse::new((), json!({"error": "Identity provider error"})),
+            OidcErrors::InvalidToken => ServerResponse::new((), json!({"error": "Invalid identity token"})),
+            OidcErrors::UnverifiedEmail => ServerResponse::new(
+                (),
+                json!({"error": "The identity provider didn't give a verified email"}),
+            ),
+            OidcErrors::NoAccount => ServerResponse::new((), json!({"error": "No account uses this email"})),
+            OidcErrors::AccountDisabled => ServerResponse::new((), json!({"error": "Account disabled"})),
+            OidcErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// Finds the account of the identity. the first login with an identity links it to the account using the same email,
+// or creates one when the registrations allow it. returns the account's row
+async fn identity_account(
+    request: &HttpRequest,
+    state: &AppState,
+    claims: &oidc_client::IdTokenClaims,
+) -> Result<postgres::Row, OidcErrors> {
+    let rows = OidcDbExecutor::get_account(&state.db_pool, &[&claims.iss, &claims.sub])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            OidcErrors::from(err)
+        })?;
+    if let Some(row) = rows.into_iter().next() {
+        return Ok(row);
+    }
+
+    // An unverified email could belong to someone else's account
+    let email = match &claims.email {
+        Some(email) if claims.email_verified && Validator::email(email).is_ok() => email,
+        _ => return Err(OidcErrors::UnverifiedEmail),
+    };
+    let current_date = Utc::now();
+    let rows = OidcDbExecutor::link_account(&state.db_pool, &[&claims.iss, &claims.sub, email, &current_date])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            OidcErrors::from(err)
+        })?;
+    if let Some(row) = rows.into_iter().next() {
+        record_auth_event(state, request, row.get("id"), AuthEventKind::IdentityLink).await;
+        return Ok(row);
+    }
+
+    let settings = &state.settings;
+    if !settings.oidc.create_accounts || settings.account.registration_mode != RegistrationMode::Open {
+        return Err(OidcErrors::NoAccount);
+    }
+    // Nobody knows the password, the account can set one through the forgotten password
+    let password = uuid::Uuid::new_v4().to_simple().to_string();
+    let password_hash = account_passwords::hash_password(&password, &settings.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            OidcErrors::from(err)
+        })?;
+    let rows = OidcDbExecutor::register(
+        &state.db_pool,
+        &[email, &password_hash, &claims.iss, &claims.sub, &current_date],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        OidcErrors::from(err)
+    })?;
+
+    rows.into_iter().next().ok_or(OidcErrors::Server)
+}
+
+// Sends the browser to the provider. the state, the nonce and the code verifier of PKCE are kept until the callback
+pub async fn oidc_login(
+    query: web::Query<OidcLoginQuery>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, OidcErrors> {
+    let settings = &state.settings.oidc;
+    let issuer = settings.issuer.as_deref().ok_or(OidcErrors::Disabled)?;
+    let metadata = oidc_client::discover(issuer).await.map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        OidcErrors::from(err)
+    })?;
+
+    let oidc_state = uuid::Uuid::new_v4().to_simple().to_string();
+    let nonce = uuid::Uuid::new_v4().to_simple().to_string();
+    let code_verifier = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().to_simple(),
+        uuid::Uuid::new_v4().to_simple()
+    );
+    let current_date = Utc::now();
+    let expiration_date = current_date + settings.login_timeout;
+    OidcDbExecutor::create_state(
+        &state.db_pool,
+        &[
+            &tokens::hash_token(&oidc_state),
+            &nonce,
+            &code_verifier,
+            &query.device,
+            &current_date,
+            &expiration_date,
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        OidcErrors::from(err)
+    })?;
+
+    let url = oidc_client::authorization_url(
+        &metadata,
+        settings,
+        &oidc_state,
+        &nonce,
+        &oidc_client::code_challenge(&code_verifier),
+    )
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        OidcErrors::from(err)
+    })?;
+    let state_cookie =
+        cookies::oidc_state_cookie(oidc_state, settings.login_timeout.num_seconds(), &state.settings.cookie);
+
+    Ok(actix_web::HttpResponse::Found()
+        .header(http::header::LOCATION, url.as_str())
+        .cookie(state_cookie)
+        .finish())
+}
+
+// Completes the login like the password would, the second factor is still asked for
+pub async fn oidc_callback(
+    request: HttpRequest,
+    query: web::Query<OidcCallbackQuery>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, OidcErrors> {
+    let settings = &state.settings.oidc;
+    let issuer = settings.issuer.as_deref().ok_or(OidcErrors::Disabled)?;
+
+    // The state must come back to the browser which started the login, otherwise someone could log it into their
+    // own account
+    let oidc_state = query.state.as_deref().ok_or(OidcErrors::InvalidState)?;
+    match request.cookie(cookies::OIDC_STATE_COOKIE) {
+        Some(cookie) if cookie.value() == oidc_state => {}
+        _ => return Err(OidcErrors::InvalidState),
+    }
+    let state_hash = tokens::hash_token(oidc_state);
+    let rows = OidcDbExecutor::use_state(&state.db_pool, &[&state_hash, &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            OidcErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(OidcErrors::InvalidState)?;
+    let nonce: String = row.get("nonce");
+    let code_verifier: String = row.get("code_verifier");
+    let device: Option<String> = row.get("device");
+
+    if query.error.is_some() {
+        return Err(OidcErrors::Refused);
+    }
+    let code = query.code.as_deref().ok_or(OidcErrors::Refused)?;
+
+    let metadata = oidc_client::discover(issuer).await.map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        OidcErrors::from(err)
+    })?;
+    let id_token = oidc_client::exchange_code(&metadata, settings, code, &code_verifier)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            OidcErrors::from(err)
+        })?;
+    let keys = oidc_client::fetch_keys(&metadata).await.map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        OidcErrors::from(err)
+    })?;
+    let claims = oidc_client::validate_id_token(&id_token, &keys, issuer, &settings.client_id, &nonce, Utc::now())
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            OidcErrors::from(err)
+        })?;
+
+    let row = identity_account(&request, &state, &claims).await?;
+    let account_id: i32 = row.get("id");
+    let disabled: bool = row.get("disabled");
+    if disabled {
+        return Err(OidcErrors::AccountDisabled);
+    }
+
+    let two_factor: bool = row.get("two_factor");
+    let mut response = if two_factor {
+        start_two_factor_challenge(&state, account_id, &device).await?
+    } else {
+        start_session(&request, &state, account_id, device).await?
+    };
+    let removed_cookie = cookies::removed_oidc_state_cookie(&state.settings.cookie);
+    if let Err(err) = response.add_cookie(&removed_cookie) {
+        warn!(target: "warnings", "Warn: {:?}", err);
+    }
+
+    Ok(response)
+}
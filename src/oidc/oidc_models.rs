@@ -0,0 +1,117 @@
+use crate::DbErrors;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+
+pub struct OidcDbExecutor;
+
+impl OidcDbExecutor {
+    // The expired logins are cleaned up along the way
+    pub async fn create_state(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM oidc_state WHERE expiration_date < $5
+            )
+            INSERT INTO oidc_state (state_hash, nonce, code_verifier, device, creation_date, expiration_date)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Consumes the login. returns its nonce, code verifier and device, or nothing if it doesn't exist or has expired
+    pub async fn use_state(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            DELETE FROM oidc_state
+            WHERE state_hash = $1 AND expiration_date > $2
+            RETURNING nonce, code_verifier, device",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // The account which logged in with this identity before. the params are the issuer and the subject
+    pub async fn get_account(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT account.id, account.disabled, EXISTS(
+                SELECT 1 FROM two_factor WHERE two_factor.account_id = account.id AND enabled
+            ) AS two_factor
+            FROM oidc_identity
+            INNER JOIN account ON account.id = oidc_identity.account_id
+            WHERE oidc_identity.issuer = $1 AND oidc_identity.subject = $2",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // Links the identity to the account using its email, which the provider verified, so the account's email is
+    // verified too. the params are the issuer, the subject, the email and the current date. returns nothing if no
+    // account uses the email
+    pub async fn link_account(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            WITH linked AS (
+                INSERT INTO oidc_identity (account_id, issuer, subject, creation_date)
+                SELECT id, $1, $2, $4 FROM account WHERE email = $3
+                RETURNING account_id
+            )
+            UPDATE account SET verified = TRUE
+            FROM linked
+            WHERE account.id = linked.account_id
+            RETURNING account.id, account.disabled, EXISTS(
+                SELECT 1 FROM two_factor WHERE two_factor.account_id = account.id AND enabled
+            ) AS two_factor",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+
+    // Creates a verified account along with its identity. the params are the email, the password hash, the issuer,
+    // the subject and the current date
+    pub async fn register(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            WITH registered AS (
+                INSERT INTO account (email, password, verified) VALUES ($1, $2, TRUE)
+                RETURNING id, disabled
+            ), linked AS (
+                INSERT INTO oidc_identity (account_id, issuer, subject, creation_date)
+                SELECT id, $3, $4, $5 FROM registered
+            )
+            SELECT id, disabled, FALSE AS two_factor FROM registered",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(rows)
+    }
+}
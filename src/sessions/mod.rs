@@ -0,0 +1,5 @@
+pub mod session_memory_store;
+pub mod session_models;
+pub mod session_postgres_store;
+pub mod session_redis_store;
+pub mod session_store;
@@ -0,0 +1,278 @@
+use crate::sessions::session_models::{LoginFailures, Session};
+use crate::sessions::session_store::{SessionErrors, SessionStore};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Keeps the sessions in the process memory. they are lost on restart and aren't shared between instances,
+// which is fine for tests and single instance deployments
+#[derive(Default)]
+pub struct MemorySessionStore {
+    // Session id -> the session and the date after which it's purged
+    sessions: Mutex<HashMap<String, (Session, DateTime<Utc>)>>,
+    // Key -> the failures and the date after which they are forgotten
+    login_failures: Mutex<HashMap<String, (LoginFailures, DateTime<Utc>)>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        MemorySessionStore::default()
+    }
+}
+
+#[async_trait(?Send)]
+impl SessionStore for MemorySessionStore {
+    async fn create(&self, session: &Session, idle_timeout: Duration) -> Result<(), SessionErrors> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let current_date = Utc::now();
+        sessions.retain(|_, (_, purge_date)| *purge_date > current_date);
+        sessions.insert(
+            session.id().to_string(),
+            (session.clone(), session.purge_date(idle_timeout)),
+        );
+
+        Ok(())
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionErrors> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(session_id)
+            .filter(|(_, purge_date)| *purge_date > Utc::now())
+            .map(|(session, _)| session.clone());
+
+        Ok(session)
+    }
+
+    async fn touch(
+        &self,
+        session: &mut Session,
+        date: DateTime<Utc>,
+        idle_timeout: Duration,
+    ) -> Result<(), SessionErrors> {
+        session.renew(date);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(stored) = sessions.get_mut(session.id()) {
+            *stored = (session.clone(), session.purge_date(idle_timeout));
+        }
+
+        Ok(())
+    }
+
+    async fn revoke(&self, account_id: i32, session_id: &str) -> Result<(), SessionErrors> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let owned = matches!(sessions.get(session_id), Some((session, _)) if session.account_id() == account_id);
+        if owned {
+            sessions.remove(session_id);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_all(&self, account_id: i32, except_session_id: Option<&str>) -> Result<(), SessionErrors> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|session_id, (session, _)| {
+            session.account_id() != account_id || Some(session_id.as_str()) == except_session_id
+        });
+
+        Ok(())
+    }
+
+    async fn list_for_account(&self, account_id: i32) -> Result<Vec<Session>, SessionErrors> {
+        let sessions = self.sessions.lock().unwrap();
+        let current_date = Utc::now();
+        let account_sessions = sessions
+            .values()
+            .filter(|(session, purge_date)| session.account_id() == account_id && *purge_date > current_date)
+            .map(|(session, _)| session.clone())
+            .collect();
+
+        Ok(account_sessions)
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        date: DateTime<Utc>,
+        ttl: Duration,
+    ) -> Result<LoginFailures, SessionErrors> {
+        let mut login_failures = self.login_failures.lock().unwrap();
+        login_failures.retain(|_, (_, purge_date)| *purge_date > date);
+
+        let count = login_failures
+            .get(key)
+            .map(|(failures, _)| failures.count())
+            .unwrap_or(0);
+        let failures = LoginFailures::new(count + 1, date);
+        login_failures.insert(key.to_string(), (failures.clone(), date + ttl));
+
+        Ok(failures)
+    }
+
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, SessionErrors> {
+        let login_failures = self.login_failures.lock().unwrap();
+        let failures = login_failures
+            .get(key)
+            .filter(|(_, purge_date)| *purge_date > Utc::now())
+            .map(|(failures, _)| failures.clone());
+
+        Ok(failures)
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<(), SessionErrors> {
+        self.login_failures.lock().unwrap().remove(key);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_session(id: &str, account_id: i32, creation_date: DateTime<Utc>) -> Session {
+        Session::new(
+            id.to_string(),
+            account_id,
+            None,
+            None,
+            None,
+            creation_date,
+            creation_date + Duration::days(30),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn test_memory_store_sessions() {
+        let store = MemorySessionStore::new();
+        let idle_timeout = Duration::days(7);
+        let current_date = Utc::now();
+
+        store
+            .create(&create_session("first", 1, current_date), idle_timeout)
+            .await
+            .unwrap();
+        store
+            .create(&create_session("second", 1, current_date), idle_timeout)
+            .await
+            .unwrap();
+        store
+            .create(&create_session("third", 2, current_date), idle_timeout)
+            .await
+            .unwrap();
+
+        assert_eq!(store.list_for_account(1).await.unwrap().len(), 2);
+        assert_eq!(store.list_for_account(2).await.unwrap().len(), 1);
+        assert_eq!(store.get("first").await.unwrap().unwrap().account_id(), 1);
+        assert!(store.get("unknown").await.unwrap().is_none());
+
+        // A session can only be revoked by its own account
+        store.revoke(2, "first").await.unwrap();
+        assert!(store.get("first").await.unwrap().is_some());
+        store.revoke(1, "first").await.unwrap();
+        assert!(store.get("first").await.unwrap().is_none());
+        assert_eq!(store.list_for_account(1).await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_memory_store_revoke_all() {
+        let store = MemorySessionStore::new();
+        let idle_timeout = Duration::days(7);
+        let current_date = Utc::now();
+
+        for (id, account_id) in &[("first", 1), ("second", 1), ("third", 1), ("fourth", 2)] {
+            store
+                .create(&create_session(id, *account_id, current_date), idle_timeout)
+                .await
+                .unwrap();
+        }
+
+        store.revoke_all(1, Some("second")).await.unwrap();
+        let sessions = store.list_for_account(1).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id(), "second");
+        assert_eq!(store.list_for_account(2).await.unwrap().len(), 1);
+
+        store.revoke_all(1, None).await.unwrap();
+        assert!(store.list_for_account(1).await.unwrap().is_empty());
+        assert_eq!(store.list_for_account(2).await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_memory_store_touch() {
+        let store = MemorySessionStore::new();
+        let idle_timeout = Duration::days(7);
+        let creation_date = Utc::now() - Duration::days(3);
+
+        store
+            .create(&create_session("first", 1, creation_date), idle_timeout)
+            .await
+            .unwrap();
+
+        let mut session = store.get("first").await.unwrap().unwrap();
+        let touch_date = Utc::now();
+        store.touch(&mut session, touch_date, idle_timeout).await.unwrap();
+        assert_eq!(session.last_seen_date(), touch_date);
+
+        let session = store.get("first").await.unwrap().unwrap();
+        assert_eq!(session.last_seen_date(), touch_date);
+        assert_eq!(session.idle_expiration_date(idle_timeout), touch_date + idle_timeout);
+    }
+
+    #[actix_rt::test]
+    async fn test_memory_store_purge() {
+        let store = MemorySessionStore::new();
+        let idle_timeout = Duration::days(7);
+
+        // Long past its idle timeout and the grace period after it
+        let creation_date = Utc::now() - Duration::days(9);
+        store
+            .create(&create_session("first", 1, creation_date), idle_timeout)
+            .await
+            .unwrap();
+
+        assert!(store.get("first").await.unwrap().is_none());
+        assert!(store.list_for_account(1).await.unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_memory_store_login_failures() {
+        let store = MemorySessionStore::new();
+        let ttl = Duration::minutes(10);
+        let current_date = Utc::now();
+
+        assert!(store.get_login_failures("email:first").await.unwrap().is_none());
+        store
+            .record_login_failure("email:first", current_date - Duration::minutes(20), ttl)
+            .await
+            .unwrap();
+        assert!(store.get_login_failures("email:first").await.unwrap().is_none());
+
+        // The older failure was forgotten already
+        for count in 1..=3 {
+            let failures = store
+                .record_login_failure("email:first", current_date, ttl)
+                .await
+                .unwrap();
+            assert_eq!(failures.count(), count);
+        }
+        store
+            .record_login_failure("email:second", current_date, ttl)
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_login_failures("email:first").await.unwrap(),
+            Some(LoginFailures::new(3, current_date))
+        );
+
+        store.clear_login_failures("email:first").await.unwrap();
+        assert!(store.get_login_failures("email:first").await.unwrap().is_none());
+        assert_eq!(
+            store.get_login_failures("email:second").await.unwrap().unwrap().count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use postgres::Row;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Expired sessions are kept around for a while so that the clients can be told that their session has expired
+const EXPIRED_SESSION_TTL: i64 = 86400;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Session {
+    id: String,
+    account_id: i32,
+    device: Option<String>,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    creation_date: DateTime<Utc>,
+    last_seen_date: DateTime<Utc>,
+    expiration_date: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn new(
+        id: String,
+        account_id: i32,
+        device: Option<String>,
+        user_agent: Option<String>,
+        ip: Option<String>,
+        creation_date: DateTime<Utc>,
+        expiration_date: DateTime<Utc>,
+    ) -> Self {
+        Session {
+            id,
+            account_id,
+            device,
+            user_agent,
+            ip,
+            creation_date,
+            last_seen_date: creation_date,
+            expiration_date,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    pub fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+
+    pub fn creation_date(&self) -> DateTime<Utc> {
+        self.creation_date
+    }
+
+    pub fn last_seen_date(&self) -> DateTime<Utc> {
+        self.last_seen_date
+    }
+
+    pub fn expiration_date(&self) -> DateTime<Utc> {
+        self.expiration_date
+    }
+
+    pub fn renew(&mut self, date: DateTime<Utc>) {
+        self.last_seen_date = date;
+    }
+
+    // The date at which the session expires unless it's used again before that
+    pub fn idle_expiration_date(&self, idle_timeout: Duration) -> DateTime<Utc> {
+        std::cmp::min(self.expiration_date, self.last_seen_date + idle_timeout)
+    }
+
+    pub fn is_expired(&self, date: DateTime<Utc>, idle_timeout: Duration) -> bool {
+        date >= self.idle_expiration_date(idle_timeout)
+    }
+
+    // The date after which the stores may forget about the session
+    pub fn purge_date(&self, idle_timeout: Duration) -> DateTime<Utc> {
+        self.idle_expiration_date(idle_timeout) + Duration::seconds(EXPIRED_SESSION_TTL)
+    }
+
+    // Builds a session out of a redis hash. returns None if the hash is malformed
+    pub fn from_hash(id: &str, mut hash: HashMap<String, String>) -> Option<Self> {
+        let parse_date = |value: String| {
+            DateTime::parse_from_rfc3339(&value)
+                .ok()
+                .map(|date| date.with_timezone(&Utc))
+        };
+
+        Some(Session {
+            id: id.to_string(),
+            account_id: hash.remove("account_id")?.parse().ok()?,
+            device: hash.remove("device"),
+            user_agent: hash.remove("user_agent"),
+            ip: hash.remove("ip"),
+            creation_date: parse_date(hash.remove("creation_date")?)?,
+            last_seen_date: parse_date(hash.remove("last_seen_date")?)?,
+            expiration_date: parse_date(hash.remove("expiration_date")?)?,
+        })
+    }
+
+    pub fn to_hash(&self) -> Vec<(&'static str, String)> {
+        let mut hash = vec![
+            ("account_id", self.account_id.to_string()),
+            ("creation_date", self.creation_date.to_rfc3339()),
+            ("last_seen_date", self.last_seen_date.to_rfc3339()),
+            ("expiration_date", self.expiration_date.to_rfc3339()),
+        ];
+        if let Some(device) = &self.device {
+            hash.push(("device", device.clone()));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            hash.push(("user_agent", user_agent.clone()));
+        }
+        if let Some(ip) = &self.ip {
+            hash.push(("ip", ip.clone()));
+        }
+
+        hash
+    }
+
+    pub fn from_row(row: &Row) -> Self {
+        Session {
+            id: row.get("id"),
+            account_id: row.get("account_id"),
+            device: row.get("device"),
+            user_agent: row.get("user_agent"),
+            ip: row.get("ip"),
+            creation_date: row.get("creation_date"),
+            last_seen_date: row.get("last_seen_date"),
+            expiration_date: row.get("expiration_date"),
+        }
+    }
+}
+
+// The failed login attempts made with an email or from an ip
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginFailures {
+    count: i32,
+    last_failure_date: DateTime<Utc>,
+}
+
+impl LoginFailures {
+    pub fn new(count: i32, last_failure_date: DateTime<Utc>) -> Self {
+        LoginFailures {
+            count,
+            last_failure_date,
+        }
+    }
+
+    pub fn count(&self) -> i32 {
+        self.count
+    }
+
+    pub fn last_failure_date(&self) -> DateTime<Utc> {
+        self.last_failure_date
+    }
+
+    // The date until which new attempts are refused. the first lockout happens after `max_attempts` failures, and every
+    // further failure doubles its duration up to `max_lockout`
+    pub fn locked_until(&self, max_attempts: i32, lockout: Duration, max_lockout: Duration) -> Option<DateTime<Utc>> {
+        if self.count < max_attempts {
+            return None;
+        }
+
+        let lockout = lockout * (1 << (self.count - max_attempts).min(16));
+        Some(self.last_failure_date + std::cmp::min(lockout, max_lockout))
+    }
+
+    // Builds the failures out of a redis hash. returns None if the hash is malformed
+    pub fn from_hash(mut hash: HashMap<String, String>) -> Option<Self> {
+        Some(LoginFailures {
+            count: hash.remove("count")?.parse().ok()?,
+            last_failure_date: DateTime::parse_from_rfc3339(&hash.remove("last_failure_date")?)
+                .ok()?
+                .with_timezone(&Utc),
+        })
+    }
+
+    pub fn from_row(row: &Row) -> Self {
+        LoginFailures {
+            count: row.get("count"),
+            last_failure_date: row.get("last_failure_date"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_failures_lockout() {
+        let date = Utc::now();
+        let lockout = Duration::seconds(30);
+        let max_lockout = Duration::minutes(5);
+
+        let counts = [1, 4, 5, 6, 8, 20];
+        let expected_lockouts = [
+            None,
+            None,
+            Some(Duration::seconds(30)),
+            Some(Duration::seconds(60)),
+            Some(Duration::seconds(240)),
+            Some(Duration::minutes(5)),
+        ];
+
+        for (index, count) in counts.iter().enumerate() {
+            let failures = LoginFailures::new(*count, date);
+            assert_eq!(
+                failures.locked_until(5, lockout, max_lockout),
+                expected_lockouts[index].map(|lockout| date + lockout)
+            );
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::sessions::session_models::{LoginFailures, Session};
+use crate::sessions::session_store::{SessionErrors, SessionStore};
+use crate::DbErrors;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use deadpool_postgres::Pool;
+
+// Keeps the sessions in the `session` table and the failed logins in the `login_failure` one. rows are purged lazily
+// whenever a new one is created
+pub struct PostgresSessionStore {
+    db_pool: Pool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(db_pool: Pool) -> Self {
+        PostgresSessionStore { db_pool }
+    }
+}
+
+#[async_trait(?Send)]
+impl SessionStore for PostgresSessionStore {
+    async fn create(&self, session: &Session, idle_timeout: Duration) -> Result<(), SessionErrors> {
+        let mut db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        let transaction = db_client.transaction().await.map_err(DbErrors::from)?;
+        transaction
+            .execute("DELETE FROM session WHERE purge_date < $1", &[&session.creation_date()])
+            .await
+            .map_err(DbErrors::from)?;
+        transaction
+            .execute(
+                "
+            INSERT INTO session(
+                id, account_id, device, user_agent, ip, creation_date, last_seen_date, expiration_date, purge_date
+            )
+            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                &[
+                    &session.id(),
+                    &session.account_id(),
+                    &session.device(),
+                    &session.user_agent(),
+                    &session.ip(),
+                    &session.creation_date(),
+                    &session.last_seen_date(),
+                    &session.expiration_date(),
+                    &session.purge_date(idle_timeout),
+                ],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+        transaction.commit().await.map_err(DbErrors::from)?;
+
+        Ok(())
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionErrors> {
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        let rows = db_client
+            .query(
+                "
+            SELECT
+                id, account_id, device, user_agent, ip, creation_date, last_seen_date, expiration_date
+            FROM session
+            WHERE id = $1 AND purge_date > $2",
+                &[&session_id, &Utc::now()],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(rows.first().map(Session::from_row))
+    }
+
+    async fn touch(
+        &self,
+        session: &mut Session,
+        date: DateTime<Utc>,
+        idle_timeout: Duration,
+    ) -> Result<(), SessionErrors> {
+        session.renew(date);
+
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        db_client
+            .execute(
+                "UPDATE session SET last_seen_date = $1, purge_date = $2 WHERE id = $3",
+                &[&date, &session.purge_date(idle_timeout), &session.id()],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(())
+    }
+
+    async fn revoke(&self, account_id: i32, session_id: &str) -> Result<(), SessionErrors> {
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        db_client
+            .execute(
+                "DELETE FROM session WHERE account_id = $1 AND id = $2",
+                &[&account_id, &session_id],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(())
+    }
+
+    async fn revoke_all(&self, account_id: i32, except_session_id: Option<&str>) -> Result<(), SessionErrors> {
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        db_client
+            .execute(
+                "DELETE FROM session WHERE account_id = $1 AND ($2::TEXT IS NULL OR id <> $2)",
+                &[&account_id, &except_session_id],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(())
+    }
+
+    async fn list_for_account(&self, account_id: i32) -> Result<Vec<Session>, SessionErrors> {
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        let rows = db_client
+            .query(
+                "
+            SELECT
+                id, account_id, device, user_agent, ip, creation_date, last_seen_date, expiration_date
+            FROM session
+            WHERE account_id = $1 AND purge_date > $2",
+                &[&account_id, &Utc::now()],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(rows.iter().map(Session::from_row).collect())
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        date: DateTime<Utc>,
+        ttl: Duration,
+    ) -> Result<LoginFailures, SessionErrors> {
+        let mut db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        let transaction = db_client.transaction().await.map_err(DbErrors::from)?;
+        transaction
+            .execute(
+                "DELETE FROM login_failure WHERE purge_date < $1 AND key <> $2",
+                &[&date, &key],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+        let rows = transaction
+            .query(
+                "
+            INSERT INTO login_failure(key, count, last_failure_date, purge_date) VALUES($1, 1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET
+                count = CASE WHEN login_failure.purge_date > $2 THEN login_failure.count + 1 ELSE 1 END,
+                last_failure_date = EXCLUDED.last_failure_date,
+                purge_date = EXCLUDED.purge_date
+            RETURNING count, last_failure_date",
+                &[&key, &date, &(date + ttl)],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+        transaction.commit().await.map_err(DbErrors::from)?;
+
+        Ok(LoginFailures::from_row(&rows[0]))
+    }
+
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, SessionErrors> {
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        let rows = db_client
+            .query(
+                "SELECT count, last_failure_date FROM login_failure WHERE key = $1 AND purge_date > $2",
+                &[&key, &Utc::now()],
+            )
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(rows.first().map(LoginFailures::from_row))
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<(), SessionErrors> {
+        let db_client = self.db_pool.get().await.map_err(DbErrors::from)?;
+        db_client
+            .execute("DELETE FROM login_failure WHERE key = $1", &[&key])
+            .await
+            .map_err(DbErrors::from)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use crate::sessions::session_models::{LoginFailures, Session};
+use crate::sessions::session_store::{SessionErrors, SessionStore};
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use deadpool_redis::{Connection, Pool};
+use redis::{self, AsyncCommands};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Delay before the first reconnection attempt. doubled after every failure up to the configured maximum
+const BASE_BACKOFF_IN_MILLISECONDS: i64 = 100;
+
+fn session_key(session_id: &str) -> String {
+    format!("session:{}", session_id)
+}
+
+fn account_sessions_key(account_id: i32) -> String {
+    format!("account_sessions:{}", account_id)
+}
+
+fn login_failures_key(key: &str) -> String {
+    format!("login_failures:{}", key)
+}
+
+fn ttl_seconds(date: DateTime<Utc>, purge_date: DateTime<Utc>) -> usize {
+    (purge_date - date).num_seconds().max(1) as usize
+}
+
+// Tracks the failed connection attempts so that a Redis outage doesn't make every request wait for a timeout
+#[derive(Default)]
+struct Backoff {
+    failures: u32,
+    retry_date: Option<Instant>,
+}
+
+// Every session is a hash under `session:{id}` which expires by itself. the ids of the account's sessions are
+// kept in the `account_sessions:{account_id}` set. the failed logins are hashes under `login_failures:{key}`
+pub struct RedisSessionStore {
+    pool: Pool,
+    max_backoff: Duration,
+    backoff: Mutex<Backoff>,
+}
+
+impl RedisSessionStore {
+    pub fn new(pool: Pool, max_backoff: Duration) -> Self {
+        RedisSessionStore {
+            pool,
+            max_backoff,
+            backoff: Mutex::new(Backoff::default()),
+        }
+    }
+
+    // Broken connections are dropped by the pool when they are recycled and new ones are opened in their place.
+    // while Redis is down the store fails right away until the backoff delay has passed
+    async fn connection(&self) -> Result<Connection, SessionErrors> {
+        if let Some(retry_date) = self.backoff.lock().unwrap().retry_date {
+            if Instant::now() < retry_date {
+                return Err(SessionErrors::Unavailable);
+            }
+        }
+
+        match self.pool.get().await {
+            Ok(connection) => {
+                *self.backoff.lock().unwrap() = Backoff::default();
+                Ok(connection)
+            }
+            Err(err) => {
+                warn!(target: "warnings", "Warn: {:?}", err);
+
+                let mut backoff = self.backoff.lock().unwrap();
+                let delay = Duration::milliseconds(BASE_BACKOFF_IN_MILLISECONDS << backoff.failures.min(16));
+                let delay = std::cmp::min(delay, self.max_backoff).to_std().unwrap_or_default();
+                backoff.failures += 1;
+                backoff.retry_date = Some(Instant::now() + delay);
+
+                Err(SessionErrors::Unavailable)
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl SessionStore for RedisSessionStore {
+    async fn create(&self, session: &Session, idle_timeout: Duration) -> Result<(), SessionErrors> {
+        let creation_date = session.creation_date();
+        let session_ttl = ttl_seconds(creation_date, session.purge_date(idle_timeout));
+        // The set outlives every session in it
+        let account_sessions_ttl = ttl_seconds(creation_date, session.expiration_date()) + session_ttl;
+
+        redis::pipe()
+            .atomic()
+            .hset_multiple(session_key(session.id()), &session.to_hash())
+            .ignore()
+            .expire(session_key(session.id()), session_ttl)
+            .ignore()
+            .sadd(account_sessions_key(session.account_id()), session.id())
+            .ignore()
+            .expire(account_sessions_key(session.account_id()), account_sessions_ttl)
+            .ignore()
+            .query_async::<_, ()>(&mut **self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionErrors> {
+        let hash: HashMap<String, String> = self.connection().await?.hgetall(session_key(session_id)).await?;
+        if hash.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Session::from_hash(session_id, hash))
+    }
+
+    async fn touch(
+        &self,
+        session: &mut Session,
+        date: DateTime<Utc>,
+        idle_timeout: Duration,
+    ) -> Result<(), SessionErrors> {
+        session.renew(date);
+        let ttl = ttl_seconds(date, session.purge_date(idle_timeout));
+
+        redis::pipe()
+            .atomic()
+            .hset(session_key(session.id()), "last_seen_date", date.to_rfc3339())
+            .ignore()
+            .expire(session_key(session.id()), ttl)
+            .ignore()
+            .query_async::<_, ()>(&mut **self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke(&self, account_id: i32, session_id: &str) -> Result<(), SessionErrors> {
+        redis::pipe()
+            .atomic()
+            .del(session_key(session_id))
+            .ignore()
+            .srem(account_sessions_key(account_id), session_id)
+            .ignore()
+            .query_async::<_, ()>(&mut **self.connection().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_all(&self, account_id: i32, except_session_id: Option<&str>) -> Result<(), SessionErrors> {
+        let session_ids: Vec<String> = self
+            .connection()
+            .await?
+            .smembers(account_sessions_key(account_id))
+            .await?;
+        let revoked_ids: Vec<&String> = session_ids
+            .iter()
+            .filter(|session_id| Some(session_id.as_str()) != except_session_id)
+            .collect();
+        if revoked_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for session_id in revoked_ids {
+            pipe.del(session_key(session_id))
+                .ignore()
+                .srem(account_sessions_key(account_id), session_id)
+                .ignore();
+        }
+        pipe.query_async::<_, ()>(&mut **self.connection().await?).await?;
+
+        Ok(())
+    }
+
+    async fn list_for_account(&self, account_id: i32) -> Result<Vec<Session>, SessionErrors> {
+        let session_ids: Vec<String> = self
+            .connection()
+            .await?
+            .smembers(account_sessions_key(account_id))
+            .await?;
+        let mut sessions = Vec::with_capacity(session_ids.len());
+
+        for session_id in session_ids {
+            match self.get(&session_id).await? {
+                Some(session) => sessions.push(session),
+                // The session hash has expired. remove the dangling id from the account's set
+                None => {
+                    let _: () = self
+                        .connection()
+                        .await?
+                        .srem(account_sessions_key(account_id), &session_id)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        date: DateTime<Utc>,
+        ttl: Duration,
+    ) -> Result<LoginFailures, SessionErrors> {
+        let (hash,): (HashMap<String, String>,) = redis::pipe()
+            .atomic()
+            .hincr(login_failures_key(key), "count", 1)
+            .ignore()
+            .hset(login_failures_key(key), "last_failure_date", date.to_rfc3339())
+            .ignore()
+            .expire(login_failures_key(key), ttl_seconds(date, date + ttl))
+            .ignore()
+            .hgetall(login_failures_key(key))
+            .query_async(&mut **self.connection().await?)
+            .await?;
+
+        Ok(LoginFailures::from_hash(hash).unwrap_or_else(|| LoginFailures::new(1, date)))
+    }
+
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, SessionErrors> {
+        let hash: HashMap<String, String> = self.connection().await?.hgetall(login_failures_key(key)).await?;
+        if hash.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(LoginFailures::from_hash(hash))
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<(), SessionErrors> {
+        let _: () = self.connection().await?.del(login_failures_key(key)).await?;
+
+        Ok(())
+    }
+}
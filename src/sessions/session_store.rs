@@ -0,0 +1,67 @@
+use crate::sessions::session_models::{LoginFailures, Session};
+use crate::DbErrors;
+use async_trait::async_trait;
+use chrono::prelude::*;
+use chrono::Duration;
+use redis::RedisError;
+
+#[derive(Debug)]
+pub enum SessionErrors {
+    Redis(RedisError),
+    Db(DbErrors),
+    // The backend can't be reached at the moment
+    Unavailable,
+}
+
+impl From<RedisError> for SessionErrors {
+    fn from(err: RedisError) -> SessionErrors {
+        if err.is_io_error() || err.is_connection_refusal() || err.is_connection_dropped() || err.is_timeout() {
+            return SessionErrors::Unavailable;
+        }
+
+        SessionErrors::Redis(err)
+    }
+}
+
+impl From<DbErrors> for SessionErrors {
+    fn from(err: DbErrors) -> SessionErrors {
+        SessionErrors::Db(err)
+    }
+}
+
+// Storage of the login sessions and of the failed login attempts. the backend is selected at startup, see
+// `SessionStoreKind`
+#[async_trait(?Send)]
+pub trait SessionStore: Send + Sync {
+    async fn create(&self, session: &Session, idle_timeout: Duration) -> Result<(), SessionErrors>;
+
+    // Expired sessions are still returned until they are purged. the caller decides what to do with them
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionErrors>;
+
+    // Marks the session as used at the given date, pushing back its idle expiration
+    async fn touch(
+        &self,
+        session: &mut Session,
+        date: DateTime<Utc>,
+        idle_timeout: Duration,
+    ) -> Result<(), SessionErrors>;
+
+    async fn revoke(&self, account_id: i32, session_id: &str) -> Result<(), SessionErrors>;
+
+    // Revokes every session of the account, except for the given one if there is any
+    async fn revoke_all(&self, account_id: i32, except_session_id: Option<&str>) -> Result<(), SessionErrors>;
+
+    async fn list_for_account(&self, account_id: i32) -> Result<Vec<Session>, SessionErrors>;
+
+    // Counts a failed login attempt for the key. the failures are forgotten once no new one happened for `ttl`
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        date: DateTime<Utc>,
+        ttl: Duration,
+    ) -> Result<LoginFailures, SessionErrors>;
+
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, SessionErrors>;
+
+    async fn clear_login_failures(&self, key: &str) -> Result<(), SessionErrors>;
+}
@@ -0,0 +1,581 @@
+use chrono::Duration;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+const MONTH_IN_SECONDS: i64 = 2628000;
+const WEEK_IN_SECONDS: i64 = 604800;
+const HOUR_IN_SECONDS: i64 = 3600;
+const DAY_IN_SECONDS: i64 = 86400;
+const REDIS_POOL_SIZE: usize = 16;
+const REDIS_TIMEOUT_IN_SECONDS: i64 = 2;
+const REDIS_MAX_BACKOFF_IN_SECONDS: i64 = 30;
+const LOGIN_ATTEMPTS: i32 = 5;
+const LOGIN_IP_ATTEMPTS: i32 = 50;
+const LOGIN_LOCKOUT_IN_SECONDS: i64 = 30;
+const LOGIN_LINK_TIMEOUT_IN_SECONDS: i64 = 900;
+// The minimum argon2id configuration recommended by OWASP
+const PASSWORD_MEMORY_COST_IN_KIB: u32 = 19456;
+const PASSWORD_TIME_COST: u32 = 2;
+const PASSWORD_PARALLELISM: u32 = 1;
+const TWO_FACTOR_CHALLENGE_TIMEOUT_IN_SECONDS: i64 = 300;
+const TWO_FACTOR_CHALLENGE_ATTEMPTS: i32 = 5;
+const OIDC_LOGIN_TIMEOUT_IN_SECONDS: i64 = 600;
+const JWT_ACCESS_TOKEN_TIMEOUT_IN_SECONDS: i64 = 900;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionStoreKind {
+    Redis,
+    Postgres,
+    Memory,
+}
+
+impl FromStr for SessionStoreKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "redis" => Ok(SessionStoreKind::Redis),
+            "postgres" => Ok(SessionStoreKind::Postgres),
+            "memory" => Ok(SessionStoreKind::Memory),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MailerKind {
+    Smtp,
+    File,
+}
+
+impl FromStr for MailerKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "smtp" => Ok(MailerKind::Smtp),
+            "file" => Ok(MailerKind::File),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    Tls,
+}
+
+impl FromStr for SmtpSecurity {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(SmtpSecurity::None),
+            "starttls" => Ok(SmtpSecurity::StartTls),
+            "tls" => Ok(SmtpSecurity::Tls),
+            _ => Err(()),
+        }
+    }
+}
+
+// The `SameSite` attribute of the cookies. the cookie version used by actix-web can't write `SameSite=None`, so the
+// frontend has to be served from the same site as the api
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+}
+
+impl FromStr for CookieSameSite {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "strict" => Ok(CookieSameSite::Strict),
+            "lax" => Ok(CookieSameSite::Lax),
+            _ => Err(()),
+        }
+    }
+}
+
+// What an account which didn't verify its email yet is allowed to do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerificationPolicy {
+    // Everything, the verification is only informative
+    None,
+    // Nothing, it can't login
+    Login,
+    // It can login and manage the account, but can't use the features behind the verification middleware
+    Features,
+}
+
+impl FromStr for VerificationPolicy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(VerificationPolicy::None),
+            "login" => Ok(VerificationPolicy::Login),
+            "features" => Ok(VerificationPolicy::Features),
+            _ => Err(()),
+        }
+    }
+}
+
+// Who can create an account
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegistrationMode {
+    Open,
+    // Only the holders of an invite code
+    InviteOnly,
+    Closed,
+}
+
+impl FromStr for RegistrationMode {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "open" => Ok(RegistrationMode::Open),
+            "invite_only" => Ok(RegistrationMode::InviteOnly),
+            "closed" => Ok(RegistrationMode::Closed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionSettings {
+    pub store: SessionStoreKind,
+    // A session can't live longer than this, no matter how active it is
+    pub absolute_timeout: Duration,
+    // A session which wasn't used for this long is expired
+    pub idle_timeout: Duration,
+}
+
+impl SessionSettings {
+    pub fn from_env() -> Self {
+        let default = SessionSettings::default();
+
+        SessionSettings {
+            store: env_or("SESSION_STORE", default.store),
+            absolute_timeout: env_seconds("SESSION_ABSOLUTE_TIMEOUT", default.absolute_timeout),
+            idle_timeout: env_seconds("SESSION_IDLE_TIMEOUT", default.idle_timeout),
+        }
+    }
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        SessionSettings {
+            store: SessionStoreKind::Redis,
+            absolute_timeout: Duration::seconds(MONTH_IN_SECONDS),
+            idle_timeout: Duration::seconds(WEEK_IN_SECONDS),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CookieSettings {
+    // Whether the cookies are only sent over https. every deployment behind tls should enable it
+    pub secure: bool,
+    // The cookies are sent to the subdomains of this domain too. without it they are only sent to the api's host
+    pub domain: Option<String>,
+    pub same_site: CookieSameSite,
+    // Signs the csrf tokens. without it a random one is used, and the csrf tokens given out before a restart stop
+    // working until the next request renews them
+    pub csrf_secret: String,
+}
+
+fn random_secret() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().to_simple(),
+        uuid::Uuid::new_v4().to_simple()
+    )
+}
+
+impl CookieSettings {
+    pub fn from_env() -> Self {
+        let default = CookieSettings::default();
+
+        CookieSettings {
+            secure: env_or("COOKIE_SECURE", default.secure),
+            domain: std::env::var("COOKIE_DOMAIN").ok(),
+            same_site: env_or("COOKIE_SAME_SITE", default.same_site),
+            csrf_secret: std::env::var("CSRF_SECRET").unwrap_or(default.csrf_secret),
+        }
+    }
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        CookieSettings {
+            secure: false,
+            domain: None,
+            same_site: CookieSameSite::Strict,
+            csrf_secret: random_secret(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RedisSettings {
+    pub pool_size: usize,
+    // How long to wait for a free connection, for a new connection and for the health check of an idle one
+    pub timeout: Duration,
+    // After a failed connection attempt the store stops trying for a while, doubling the delay up to this value
+    pub max_backoff: Duration,
+}
+
+impl RedisSettings {
+    pub fn from_env() -> Self {
+        let default = RedisSettings::default();
+
+        RedisSettings {
+            pool_size: env_or("REDIS_POOL_SIZE", default.pool_size),
+            timeout: env_seconds("REDIS_TIMEOUT", default.timeout),
+            max_backoff: env_seconds("REDIS_MAX_BACKOFF", default.max_backoff),
+        }
+    }
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        RedisSettings {
+            pool_size: REDIS_POOL_SIZE,
+            timeout: Duration::seconds(REDIS_TIMEOUT_IN_SECONDS),
+            max_backoff: Duration::seconds(REDIS_MAX_BACKOFF_IN_SECONDS),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MailerSettings {
+    pub kind: MailerKind,
+    pub from: String,
+    // Base url of the links sent in the emails
+    pub public_url: String,
+    // Where the file mailer writes the emails
+    pub directory: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_security: SmtpSecurity,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
+impl MailerSettings {
+    pub fn from_env() -> Self {
+        let default = MailerSettings::default();
+
+        MailerSettings {
+            kind: env_or("MAILER", default.kind),
+            from: env_or("MAILER_FROM", default.from),
+            public_url: env_or("PUBLIC_URL", default.public_url),
+            directory: env_or("MAILER_DIRECTORY", default.directory),
+            smtp_host: env_or("SMTP_HOST", default.smtp_host),
+            smtp_port: env_or("SMTP_PORT", default.smtp_port),
+            smtp_security: env_or("SMTP_SECURITY", default.smtp_security),
+            smtp_username: std::env::var("SMTP_USERNAME").ok(),
+            smtp_password: std::env::var("SMTP_PASSWORD").ok(),
+        }
+    }
+}
+
+impl Default for MailerSettings {
+    fn default() -> Self {
+        MailerSettings {
+            kind: MailerKind::File,
+            from: "noreply@localhost".to_string(),
+            public_url: "http://localhost:8080".to_string(),
+            directory: "mails".to_string(),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            smtp_security: SmtpSecurity::StartTls,
+            smtp_username: None,
+            smtp_password: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccountSettings {
+    // How long a password reset token can be used
+    pub password_reset_timeout: Duration,
+    pub verification_policy: VerificationPolicy,
+    // How long an email verification token can be used
+    pub verification_timeout: Duration,
+    // How long the link confirming a new email can be used
+    pub email_change_timeout: Duration,
+    // How many failed logins an email, and an ip, are allowed before they are locked out
+    pub login_attempts: i32,
+    pub login_ip_attempts: i32,
+    // The first lockout's duration. it's doubled by every further failure up to the maximum, after which the failures
+    // are forgotten if there is no new one
+    pub login_lockout: Duration,
+    pub login_max_lockout: Duration,
+    pub registration_mode: RegistrationMode,
+    // Whether the accounts which aren't admins can invite people too
+    pub user_invites: bool,
+    // How long an invite can be used when its creator didn't pick an expiration date
+    pub invite_timeout: Duration,
+    // Whether the accounts can login with a link sent to their email instead of their password
+    pub login_links: bool,
+    pub login_link_timeout: Duration,
+}
+
+impl AccountSettings {
+    pub fn from_env() -> Self {
+        let default = AccountSettings::default();
+
+        AccountSettings {
+            password_reset_timeout: env_seconds("PASSWORD_RESET_TIMEOUT", default.password_reset_timeout),
+            verification_policy: env_or("VERIFICATION_POLICY", default.verification_policy),
+            verification_timeout: env_seconds("VERIFICATION_TIMEOUT", default.verification_timeout),
+            email_change_timeout: env_seconds("EMAIL_CHANGE_TIMEOUT", default.email_change_timeout),
+            login_attempts: env_or("LOGIN_ATTEMPTS", default.login_attempts),
+            login_ip_attempts: env_or("LOGIN_IP_ATTEMPTS", default.login_ip_attempts),
+            login_lockout: env_seconds("LOGIN_LOCKOUT", default.login_lockout),
+            login_max_lockout: env_seconds("LOGIN_MAX_LOCKOUT", default.login_max_lockout),
+            registration_mode: env_or("REGISTRATION_MODE", default.registration_mode),
+            user_invites: env_or("USER_INVITES", default.user_invites),
+            invite_timeout: env_seconds("INVITE_TIMEOUT", default.invite_timeout),
+            login_links: env_or("LOGIN_LINKS", default.login_links),
+            login_link_timeout: env_seconds("LOGIN_LINK_TIMEOUT", default.login_link_timeout),
+        }
+    }
+}
+
+impl Default for AccountSettings {
+    fn default() -> Self {
+        AccountSettings {
+            password_reset_timeout: Duration::seconds(HOUR_IN_SECONDS),
+            verification_policy: VerificationPolicy::None,
+            verification_timeout: Duration::seconds(DAY_IN_SECONDS),
+            email_change_timeout: Duration::seconds(DAY_IN_SECONDS),
+            login_attempts: LOGIN_ATTEMPTS,
+            login_ip_attempts: LOGIN_IP_ATTEMPTS,
+            login_lockout: Duration::seconds(LOGIN_LOCKOUT_IN_SECONDS),
+            login_max_lockout: Duration::seconds(HOUR_IN_SECONDS),
+            registration_mode: RegistrationMode::Open,
+            user_invites: true,
+            invite_timeout: Duration::seconds(WEEK_IN_SECONDS),
+            login_links: false,
+            login_link_timeout: Duration::seconds(LOGIN_LINK_TIMEOUT_IN_SECONDS),
+        }
+    }
+}
+
+// Parameters of the argon2id password hashes. the hashes made with other parameters are upgraded on the next login
+#[derive(Clone)]
+pub struct PasswordSettings {
+    // In KiB
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl PasswordSettings {
+    pub fn from_env() -> Self {
+        let default = PasswordSettings::default();
+
+        PasswordSettings {
+            memory_cost: env_or("PASSWORD_MEMORY_COST", default.memory_cost),
+            time_cost: env_or("PASSWORD_TIME_COST", default.time_cost),
+            parallelism: env_or("PASSWORD_PARALLELISM", default.parallelism),
+        }
+    }
+}
+
+impl Default for PasswordSettings {
+    fn default() -> Self {
+        PasswordSettings {
+            memory_cost: PASSWORD_MEMORY_COST_IN_KIB,
+            time_cost: PASSWORD_TIME_COST,
+            parallelism: PASSWORD_PARALLELISM,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TwoFactorSettings {
+    // Shown by the authenticator apps next to the account's email
+    pub issuer: String,
+    // How long a login has to provide its second factor after the password was checked
+    pub challenge_timeout: Duration,
+    // How many wrong codes a login challenge accepts before it's discarded
+    pub challenge_attempts: i32,
+}
+
+impl TwoFactorSettings {
+    pub fn from_env() -> Self {
+        let default = TwoFactorSettings::default();
+
+        TwoFactorSettings {
+            issuer: env_or("TWO_FACTOR_ISSUER", default.issuer),
+            challenge_timeout: env_seconds("TWO_FACTOR_CHALLENGE_TIMEOUT", default.challenge_timeout),
+            challenge_attempts: env_or("TWO_FACTOR_CHALLENGE_ATTEMPTS", default.challenge_attempts),
+        }
+    }
+}
+
+impl Default for TwoFactorSettings {
+    fn default() -> Self {
+        TwoFactorSettings {
+            issuer: "Productivity".to_string(),
+            challenge_timeout: Duration::seconds(TWO_FACTOR_CHALLENGE_TIMEOUT_IN_SECONDS),
+            challenge_attempts: TWO_FACTOR_CHALLENGE_ATTEMPTS,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OidcSettings {
+    // The login through an OpenID Connect provider is disabled without it. the provider's endpoints are discovered
+    // from `<issuer>/.well-known/openid-configuration`
+    pub issuer: Option<String>,
+    pub client_id: String,
+    // Only the confidential clients have one
+    pub client_secret: Option<String>,
+    // Where the provider sends the browser back to, as registered with it. either the callback route or a page of the
+    // frontend which forwards its query to it
+    pub redirect_url: String,
+    // Whether a login with an email that no account uses creates one. only when the registrations are open
+    pub create_accounts: bool,
+    // How long the login at the provider can take
+    pub login_timeout: Duration,
+}
+
+impl OidcSettings {
+    pub fn from_env() -> Self {
+        let default = OidcSettings::default();
+
+        OidcSettings {
+            issuer: std::env::var("OIDC_ISSUER").ok(),
+            client_id: env_or("OIDC_CLIENT_ID", default.client_id),
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_url: env_or("OIDC_REDIRECT_URL", default.redirect_url),
+            create_accounts: env_or("OIDC_CREATE_ACCOUNTS", default.create_accounts),
+            login_timeout: env_seconds("OIDC_LOGIN_TIMEOUT", default.login_timeout),
+        }
+    }
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        OidcSettings {
+            issuer: None,
+            client_id: "productivity".to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:8080/api/account/oidc/callback".to_string(),
+            create_accounts: true,
+            login_timeout: Duration::seconds(OIDC_LOGIN_TIMEOUT_IN_SECONDS),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JwtSettings {
+    // The logins with tokens are disabled without it. the access tokens are signed with it, so it must be long and
+    // random
+    pub secret: Option<String>,
+    // How long an access token is accepted. it can't be revoked, so it's short
+    pub access_token_timeout: Duration,
+    // How long a login with tokens lasts. refreshing the tokens doesn't extend it
+    pub refresh_token_timeout: Duration,
+}
+
+impl JwtSettings {
+    pub fn from_env() -> Self {
+        let default = JwtSettings::default();
+
+        JwtSettings {
+            secret: std::env::var("JWT_SECRET").ok(),
+            access_token_timeout: env_seconds("JWT_ACCESS_TOKEN_TIMEOUT", default.access_token_timeout),
+            refresh_token_timeout: env_seconds("JWT_REFRESH_TOKEN_TIMEOUT", default.refresh_token_timeout),
+        }
+    }
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        JwtSettings {
+            secret: None,
+            access_token_timeout: Duration::seconds(JWT_ACCESS_TOKEN_TIMEOUT_IN_SECONDS),
+            refresh_token_timeout: Duration::seconds(MONTH_IN_SECONDS),
+        }
+    }
+}
+
+// The client addresses are only taken from the forwarded headers of these proxies, anyone else could forge them
+#[derive(Clone, Default)]
+pub struct ProxySettings {
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl ProxySettings {
+    pub fn from_env() -> Self {
+        let trusted_proxies = match std::env::var("TRUSTED_PROXIES") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|address| !address.is_empty())
+                .map(|address| {
+                    address
+                        .parse()
+                        .unwrap_or_else(|_e| panic!("TRUSTED_PROXIES variable is invalid"))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        ProxySettings { trusted_proxies }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Settings {
+    pub session: SessionSettings,
+    pub cookie: CookieSettings,
+    pub redis: RedisSettings,
+    pub mailer: MailerSettings,
+    pub account: AccountSettings,
+    pub password: PasswordSettings,
+    pub two_factor: TwoFactorSettings,
+    pub oidc: OidcSettings,
+    pub jwt: JwtSettings,
+    pub proxy: ProxySettings,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        Settings {
+            session: SessionSettings::from_env(),
+            cookie: CookieSettings::from_env(),
+            redis: RedisSettings::from_env(),
+            mailer: MailerSettings::from_env(),
+            account: AccountSettings::from_env(),
+            password: PasswordSettings::from_env(),
+            two_factor: TwoFactorSettings::from_env(),
+            oidc: OidcSettings::from_env(),
+            jwt: JwtSettings::from_env(),
+            proxy: ProxySettings::from_env(),
+        }
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_e| panic!("{} variable is invalid", name)),
+        Err(_) => default,
+    }
+}
+
+fn env_seconds(name: &str, default: Duration) -> Duration {
+    Duration::seconds(env_or(name, default.num_seconds()))
+}
@@ -1,31 +1,57 @@
+use crate::common::pagination::{self, InvalidPagination};
 use crate::common::responses::ServerResponse;
-use crate::todos::todo_models::{Todo, TodoDbExecutor};
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::todos::todo_models::{Todo, TodoDbExecutor, TodoDue, TodoSort};
 use crate::AppState;
 use crate::DbErrors;
-use actix_http::httpmessage::HttpMessage;
-use actix_web::{self, dev, error, http, web, HttpRequest};
+use actix_web::{self, dev, error, http, web};
 use chrono::prelude::*;
 use postgres;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+
+// The length of the title column
+const MAX_TITLE_LENGTH: usize = 50;
 
 #[derive(Debug, Deserialize)]
 pub struct TodoCreateRequest {
     title: String,
     body: Option<String>,
+    due_date: Option<NaiveDate>,
 }
 
 #[derive(Deserialize)]
 pub struct TodoGetRequest {
     offset: Option<i64>,
     limit: Option<i64>,
+    sort: Option<TodoSort>,
+    due: Option<TodoDue>,
 }
 
 #[derive(Deserialize)]
 pub struct TodoEditRequest {
     id: i32,
+    #[serde(flatten)]
+    changes: TodoUpdateRequest,
+}
+
+// The todo is the one of the path. the missing fields are left as they are
+#[derive(Deserialize)]
+pub struct TodoUpdateRequest {
     title: Option<String>,
     body: Option<String>,
     done: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    due_date: Option<Option<NaiveDate>>,
+}
+
+// Tells a null, which clears the field, apart from a missing field, which is left as it is
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Deserialize)]
@@ -47,21 +73,42 @@ pub struct TodoGetResponse {
 #[derive(Serialize)]
 pub struct TodoEditResponse {
     id: i32,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    last_edit_date: Option<DateTime<Utc>>,
+    last_edit_date: DateTime<Utc>,
 }
 
+// The ids which weren't deleted either don't exist or belong to other accounts
 #[derive(Serialize)]
 pub struct TodoDeleteResponse {
+    // The deleted ids
     todos: Vec<i32>,
+    not_found: Vec<i32>,
 }
 
 #[derive(Debug)]
 pub enum TodoErrors {
     Db(postgres::Error),
+    NotFound,
+    // Holds what is wrong with the request
+    Validation(&'static str),
+    InvalidPagination,
     Server,
 }
 
+impl From<DbErrors> for TodoErrors {
+    fn from(err: DbErrors) -> TodoErrors {
+        match err {
+            DbErrors::Runtime => TodoErrors::Server,
+            DbErrors::Postgres(err) => TodoErrors::Db(err),
+        }
+    }
+}
+
+impl From<InvalidPagination> for TodoErrors {
+    fn from(_err: InvalidPagination) -> TodoErrors {
+        TodoErrors::InvalidPagination
+    }
+}
+
 impl std::fmt::Display for TodoErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -71,6 +118,9 @@ impl std::fmt::Display for TodoErrors {
 impl error::ResponseError for TodoErrors {
     fn status_code(&self) -> http::StatusCode {
         match *self {
+            TodoErrors::NotFound => http::StatusCode::NOT_FOUND,
+            TodoErrors::Validation(_) => http::StatusCode::UNPROCESSABLE_ENTITY,
+            TodoErrors::InvalidPagination => http::StatusCode::BAD_REQUEST,
             _ => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -79,6 +129,9 @@ impl error::ResponseError for TodoErrors {
         let response_json = match self {
             TodoErrors::Server => ServerResponse::new((), json!({"error": "Interval server error"})),
             TodoErrors::Db(_e) => ServerResponse::new((), json!({"error": "DB error"})),
+            TodoErrors::NotFound => ServerResponse::new((), json!({"error": "Todo not found"})),
+            TodoErrors::Validation(message) => ServerResponse::new((), json!({ "error": message })),
+            TodoErrors::InvalidPagination => ServerResponse::new((), json!({"error": "Invalid offset or limit"})),
         };
 
         dev::HttpResponseBuilder::new(self.status_code())
@@ -87,66 +140,113 @@ impl error::ResponseError for TodoErrors {
     }
 }
 
-pub async fn todo_create(
-    request: HttpRequest,
-    body: web::Json<TodoCreateRequest>,
-    state: web::Data<AppState>,
-) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
-    let account_id = request.cookie("account_id").unwrap().value().parse::<i32>().unwrap();
+fn validate_title(title: &str) -> Result<(), TodoErrors> {
+    if title.trim().is_empty() {
+        return Err(TodoErrors::Validation("The title can't be empty"));
+    }
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(TodoErrors::Validation("The title must be at most 50 characters long"));
+    }
+
+    Ok(())
+}
+
+// Shared by todo_create and todos_create, which only respond differently
+async fn create_todo(
+    state: &AppState,
+    account_id: i32,
+    request: &TodoCreateRequest,
+) -> Result<TodoCreateResponse, TodoErrors> {
+    validate_title(&request.title)?;
 
     let current_date = Utc::now();
     let rows = TodoDbExecutor::create(
         &state.db_pool,
-        &[&account_id, &body.title, &body.body, &current_date, &current_date],
+        &[
+            &account_id,
+            &request.title,
+            &request.body,
+            &current_date,
+            &current_date,
+            &request.due_date,
+        ],
     )
-    .await;
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        TodoErrors::from(err)
+    })?;
+    let row = rows.first().ok_or(TodoErrors::Server)?;
+
+    Ok(TodoCreateResponse {
+        id: row.get("id"),
+        creation_date: row.get("creation_date"),
+    })
+}
 
-    match rows {
-        Ok(rows) => {
-            let row = &rows[0];
-            let data = TodoCreateResponse {
-                id: row.get("id"),
-                creation_date: row.get("creation_date"),
-            };
+// Shared by todo_edit and todo_update. returns the row of the edited todo. nothing matches the todos of other
+// accounts either
+async fn edit_todo(
+    state: &AppState,
+    account_id: i32,
+    todo_id: i32,
+    changes: &TodoUpdateRequest,
+) -> Result<postgres::Row, TodoErrors> {
+    if let Some(title) = &changes.title {
+        validate_title(title)?;
+    }
 
-            let response_json = ServerResponse::new(data, ());
-            Ok(actix_web::HttpResponse::Ok().json(response_json))
-        }
-        Err(err) => {
-            warn!(target: "warnings", "Warn: {:?}", err);
+    let rows = TodoDbExecutor::edit(
+        &state.db_pool,
+        &[
+            &changes.title,
+            &changes.body,
+            &changes.done,
+            &Utc::now(),
+            &account_id,
+            &todo_id,
+            &changes.due_date.flatten(),
+            &changes.due_date.is_some(),
+        ],
+    )
+    .await
+    .map_err(|err| {
+        warn!(target: "warnings", "Warn: {:?}", err);
+        TodoErrors::from(err)
+    })?;
 
-            match err {
-                DbErrors::Runtime => Err(TodoErrors::Server),
-                DbErrors::Postgres(err) => Err(TodoErrors::Db(err)),
-            }
-        }
-    }
+    rows.into_iter().next().ok_or(TodoErrors::NotFound)
+}
+
+pub async fn todo_create(
+    account: AuthenticatedAccount,
+    body: web::Json<TodoCreateRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
+    let data = create_todo(&state, account.account_id(), &body).await?;
+
+    let response_json = ServerResponse::new(data, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
 }
 
 pub async fn todo_get(
-    request: HttpRequest,
+    account: AuthenticatedAccount,
     query: web::Query<TodoGetRequest>,
     state: web::Data<AppState>,
 ) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
-    let account_id = request.cookie("account_id").unwrap().value().parse::<i32>().unwrap();
+    let account_id = account.account_id();
+    let (offset, limit) = pagination::page(query.offset, query.limit)?;
 
-    let rows = TodoDbExecutor::get(&state.db_pool, &[&account_id, &query.offset, &query.limit]).await;
+    let sort = query.sort.map(|sort| sort.as_str());
+    let due = query.due.map(|due| due.as_str());
+    let rows = TodoDbExecutor::get(
+        &state.db_pool,
+        &[&account_id, &offset, &limit, &sort, &Utc::now(), &due],
+    )
+    .await;
     match rows {
         Ok(rows) => {
-            let todos: Vec<Todo> = rows
-                .iter()
-                .map(|row| {
-                    let id = row.get("id");
-                    let account_id = row.get("account_id");
-                    let title = row.get("title");
-                    let body = row.get("body");
-                    let creation_date = row.get("creation_date");
-                    let last_edit_date = row.get("last_edit_date");
-                    let done = row.get("done");
-
-                    Todo::new(id, account_id, title, body, creation_date, last_edit_date, done)
-                })
-                .collect();
+            let todos: Vec<Todo> = rows.iter().map(Todo::from_row).collect();
 
             let data = TodoGetResponse { todos };
             let response_json = ServerResponse::new(data, ());
@@ -164,42 +264,47 @@ pub async fn todo_get(
 }
 
 pub async fn todo_edit(
-    request: HttpRequest,
+    account: AuthenticatedAccount,
     body: web::Json<TodoEditRequest>,
     state: web::Data<AppState>,
 ) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
-    let account_id = request.cookie("account_id").unwrap().value().parse::<i32>().unwrap();
-    let todo_id = body.id;
-    let current_date = Utc::now();
+    let row = edit_todo(&state, account.account_id(), body.id, &body.changes).await?;
+    let data = TodoEditResponse {
+        id: row.get("id"),
+        last_edit_date: row.get("last_edit_date"),
+    };
+
+    let response_json = ServerResponse::new(data, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
 
-    let rows = TodoDbExecutor::edit(
-        &state.db_pool,
-        &[
-            &body.title,
-            &body.body,
-            &body.done,
-            &current_date,
-            &account_id,
-            &body.id,
-        ],
-    )
-    .await;
+pub async fn todo_delete(
+    account: AuthenticatedAccount,
+    body: web::Json<TodoDeleteRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
+    if body.todos.is_empty() {
+        return Err(TodoErrors::Validation("No todos to delete"));
+    }
+    let account_id = account.account_id();
 
+    let rows = TodoDbExecutor::delete(&state.db_pool, &[&account_id, &body.todos]).await;
     match rows {
         Ok(rows) => {
-            let data = if rows.is_empty() {
-                TodoEditResponse {
-                    id: todo_id,
-                    last_edit_date: None,
-                }
-            } else {
-                let row = &rows[0];
-                TodoEditResponse {
-                    id: row.get("id"),
-                    last_edit_date: row.get("last_edit_date"),
-                }
-            };
+            let todo_ids: Vec<i32> = rows.iter().map(|row| row.get("id")).collect();
+            // Seen holds the deleted ids along with the ones already reported, so that each id is reported once
+            let mut seen: HashSet<i32> = todo_ids.iter().copied().collect();
+            let not_found: Vec<i32> = body
+                .todos
+                .iter()
+                .copied()
+                .filter(|todo_id| seen.insert(*todo_id))
+                .collect();
 
+            let data = TodoDeleteResponse {
+                todos: todo_ids,
+                not_found,
+            };
             let response_json = ServerResponse::new(data, ());
             Ok(actix_web::HttpResponse::Ok().json(response_json))
         }
@@ -214,31 +319,74 @@ pub async fn todo_edit(
     }
 }
 
-pub async fn todo_delete(
-    request: HttpRequest,
-    body: web::Json<TodoDeleteRequest>,
+// The resource routes below replace the ones above, which are kept as deprecated aliases. the list is served by
+// todo_get on both
+
+// Like todo_create, but responds with 201 and where the todo can be found
+pub async fn todos_create(
+    account: AuthenticatedAccount,
+    body: web::Json<TodoCreateRequest>,
     state: web::Data<AppState>,
 ) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
-    let account_id = request.cookie("account_id").unwrap().value().parse::<i32>().unwrap();
+    let data = create_todo(&state, account.account_id(), &body).await?;
 
-    let rows = TodoDbExecutor::delete(&state.db_pool, &[&account_id, &body.todos]).await;
-    match rows {
-        Ok(rows) => {
-            let todo_ids: Vec<i32> = rows.iter().map(|row| row.get("id")).collect();
+    let location = format!("/api/todos/{}", data.id);
+    let response_json = ServerResponse::new(data, ());
+    Ok(actix_web::HttpResponse::Created()
+        .header(http::header::LOCATION, location)
+        .json(response_json))
+}
 
-            let data = TodoDeleteResponse { todos: todo_ids };
-            let response_json = ServerResponse::new(data, ());
-            Ok(actix_web::HttpResponse::Ok().json(response_json))
-        }
-        Err(err) => {
+pub async fn todo_get_one(
+    account: AuthenticatedAccount,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
+    let account_id = account.account_id();
+    let rows = TodoDbExecutor::get_one(&state.db_pool, &[&account_id, &path.into_inner()])
+        .await
+        .map_err(|err| {
             warn!(target: "warnings", "Warn: {:?}", err);
+            TodoErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(TodoErrors::NotFound)?;
 
-            match err {
-                DbErrors::Runtime => Err(TodoErrors::Server),
-                DbErrors::Postgres(err) => Err(TodoErrors::Db(err)),
-            }
-        }
+    let response_json = ServerResponse::new(Todo::from_row(row), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// Responds with the whole todo once edited
+pub async fn todo_update(
+    account: AuthenticatedAccount,
+    path: web::Path<i32>,
+    body: web::Json<TodoUpdateRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
+    let row = edit_todo(&state, account.account_id(), path.into_inner(), &body).await?;
+
+    let response_json = ServerResponse::new(Todo::from_row(&row), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn todo_remove(
+    account: AuthenticatedAccount,
+    path: web::Path<i32>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
+    let account_id = account.account_id();
+    let todo_ids = vec![path.into_inner()];
+
+    let rows = TodoDbExecutor::delete(&state.db_pool, &[&account_id, &todo_ids])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TodoErrors::from(err)
+        })?;
+    if rows.is_empty() {
+        return Err(TodoErrors::NotFound);
     }
+
+    Ok(actix_web::HttpResponse::NoContent().finish())
 }
 
 pub async fn todo_reset(state: web::Data<AppState>) -> actix_web::Result<actix_web::HttpResponse, TodoErrors> {
@@ -3,7 +3,63 @@ use chrono::prelude::*;
 use deadpool_postgres::Pool;
 use postgres::types::ToSql;
 use postgres::{self, Row};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// The order of the todos when none is asked for is picked by the account, see `AccountProfile`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSort {
+    // Most recent first
+    LastEditDate,
+    CreationDate,
+    Title,
+    // Soonest first, the todos without a due date last
+    DueDate,
+}
+
+impl TodoSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TodoSort::LastEditDate => "last_edit_date",
+            TodoSort::CreationDate => "creation_date",
+            TodoSort::Title => "title",
+            TodoSort::DueDate => "due_date",
+        }
+    }
+}
+
+impl FromStr for TodoSort {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "last_edit_date" => Ok(TodoSort::LastEditDate),
+            "creation_date" => Ok(TodoSort::CreationDate),
+            "title" => Ok(TodoSort::Title),
+            "due_date" => Ok(TodoSort::DueDate),
+            _ => Err(()),
+        }
+    }
+}
+
+// The days are the ones of the account's timezone
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoDue {
+    Today,
+    // Not done and due before today
+    Overdue,
+}
+
+impl TodoDue {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TodoDue::Today => "today",
+            TodoDue::Overdue => "overdue",
+        }
+    }
+}
 
 #[derive(Serialize, Debug)]
 pub struct Todo {
@@ -13,27 +69,21 @@ pub struct Todo {
     body: Option<String>,
     creation_date: DateTime<Utc>,
     last_edit_date: DateTime<Utc>,
+    due_date: Option<NaiveDate>,
     done: bool,
 }
 
 impl Todo {
-    pub fn new(
-        id: i32,
-        account_id: i32,
-        title: String,
-        body: Option<String>,
-        creation_date: DateTime<Utc>,
-        last_edit_date: DateTime<Utc>,
-        done: bool,
-    ) -> Self {
+    pub fn from_row(row: &Row) -> Self {
         Todo {
-            id,
-            account_id,
-            title,
-            body,
-            creation_date,
-            last_edit_date,
-            done,
+            id: row.get("id"),
+            account_id: row.get("account_id"),
+            title: row.get("title"),
+            body: row.get("body"),
+            creation_date: row.get("creation_date"),
+            last_edit_date: row.get("last_edit_date"),
+            due_date: row.get("due_date"),
+            done: row.get("done"),
         }
     }
 }
@@ -42,13 +92,13 @@ pub struct TodoDbExecutor;
 
 impl TodoDbExecutor {
     pub async fn create(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
-        let mut db_client = db_pool.get().await.unwrap();
+        let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
         let rows = transaction
             .query(
                 "
-            INSERT INTO todo(account_id, title, body, creation_date, last_edit_date)
-            VALUES($1, $2, $3, $4, $5)
+            INSERT INTO todo(account_id, title, body, creation_date, last_edit_date, due_date)
+            VALUES($1, $2, $3, $4, $5, $6)
             RETURNING id, creation_date",
                 params,
             )
@@ -58,17 +108,29 @@ impl TodoDbExecutor {
         Ok(rows)
     }
 
+    // The sort defaults to the account's one. the due filter compares the due dates to the current date, $5, in the
+    // account's timezone
     pub async fn get(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
-        let mut db_client = db_pool.get().await.unwrap();
+        let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
         let rows = transaction
             .query(
                 "
             SELECT
-                id, account_id, title, body, creation_date, last_edit_date, done
+                todo.id, todo.account_id, title, body, creation_date, last_edit_date, due_date, done
             FROM todo
-            WHERE account_id = $1
-            ORDER BY last_edit_date DESC
+            JOIN account ON account.id = todo.account_id
+            WHERE todo.account_id = $1
+                AND CASE $6::TEXT
+                    WHEN 'today' THEN due_date = ($5::TIMESTAMPTZ AT TIME ZONE account.timezone)::DATE
+                    WHEN 'overdue' THEN due_date < ($5::TIMESTAMPTZ AT TIME ZONE account.timezone)::DATE AND NOT done
+                    ELSE TRUE
+                END
+            ORDER BY
+                CASE WHEN COALESCE($4, account.todo_sort) = 'creation_date' THEN creation_date END DESC,
+                CASE WHEN COALESCE($4, account.todo_sort) = 'title' THEN title END ASC,
+                CASE WHEN COALESCE($4, account.todo_sort) = 'due_date' THEN due_date END ASC NULLS LAST,
+                last_edit_date DESC
             OFFSET $2
             LIMIT $3",
                 params,
@@ -79,8 +141,25 @@ impl TodoDbExecutor {
         Ok(rows)
     }
 
+    // Todos of other accounts aren't found
+    pub async fn get_one(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT id, account_id, title, body, creation_date, last_edit_date, due_date, done
+            FROM todo
+            WHERE account_id = $1 AND id = $2",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // The due date is only set when the 8th param is true, so that it can be cleared
     pub async fn edit(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
-        let mut db_client = db_pool.get().await.unwrap();
+        let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
         let rows = transaction
             .query(
@@ -89,9 +168,10 @@ impl TodoDbExecutor {
             SET title = COALESCE($1, title),
                 body = COALESCE($2, body),
                 done = COALESCE($3, done),
-                last_edit_date = $4
+                last_edit_date = $4,
+                due_date = CASE WHEN $8 THEN $7 ELSE due_date END
             WHERE account_id = $5 AND id = $6
-            RETURNING id, last_edit_date",
+            RETURNING id, account_id, title, body, creation_date, last_edit_date, due_date, done",
                 params,
             )
             .await?;
@@ -101,7 +181,7 @@ impl TodoDbExecutor {
     }
 
     pub async fn delete(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
-        let mut db_client = db_pool.get().await.unwrap();
+        let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
         let rows = transaction
             .query(
@@ -118,7 +198,7 @@ impl TodoDbExecutor {
     }
 
     pub async fn reset(db_pool: &Pool) -> Result<(), DbErrors> {
-        let mut db_client = db_pool.get().await.unwrap();
+        let mut db_client = db_pool.get().await?;
         let transaction = db_client.transaction().await?;
         let _rows = transaction.execute("DELETE FROM todo", &[]).await?;
         transaction.commit().await?;
@@ -0,0 +1,3 @@
+pub mod totp;
+pub mod two_factor_controllers;
+pub mod two_factor_models;
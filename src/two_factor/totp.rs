@@ -0,0 +1,145 @@
+use base32::Alphabet;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+// RFC 6238 defaults, which are the only values the authenticator apps reliably support
+const STEP_IN_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+const SECRET_LENGTH: usize = 20;
+// Codes of the previous and the next steps are accepted too, so that slightly wrong clocks still work
+const ALLOWED_DRIFT: i64 = 1;
+
+const BASE32: Alphabet = Alphabet::RFC4648 { padding: false };
+
+pub struct Totp {
+    secret: Vec<u8>,
+}
+
+impl Totp {
+    pub fn generate() -> Self {
+        let mut secret = Vec::new();
+        secret.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
+        secret.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
+        secret.truncate(SECRET_LENGTH);
+
+        Totp { secret }
+    }
+
+    // Parses the base32 secret. returns None if it's malformed
+    pub fn from_base32(secret: &str) -> Option<Self> {
+        let secret = base32::decode(BASE32, secret)?;
+        if secret.is_empty() {
+            return None;
+        }
+
+        Some(Totp { secret })
+    }
+
+    pub fn to_base32(&self) -> String {
+        base32::encode(BASE32, &self.secret)
+    }
+
+    // The uri shown as a QR code by the clients. authenticator apps add the account by scanning it
+    pub fn uri(&self, issuer: &str, email: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            percent_encode(issuer),
+            percent_encode(email),
+            self.to_base32(),
+            percent_encode(issuer),
+            DIGITS,
+            STEP_IN_SECONDS
+        )
+    }
+
+    pub fn step(timestamp: i64) -> i64 {
+        timestamp.div_euclid(STEP_IN_SECONDS)
+    }
+
+    pub fn code(&self, step: i64) -> String {
+        let mut mac = Hmac::<Sha1>::new_varkey(&self.secret).expect("HMAC accepts keys of any length");
+        mac.input(&step.to_be_bytes());
+        let hash = mac.result().code();
+
+        // Dynamic truncation, see RFC 4226 section 5.3
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+    }
+
+    // Returns the step matched by the code, so that the caller can refuse to accept it twice
+    pub fn verify(&self, code: &str, timestamp: i64) -> Option<i64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != DIGITS as usize {
+            return None;
+        }
+
+        let current_step = Totp::step(timestamp);
+        (current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT).find(|step| self.code(*step) == code)
+    }
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA1 test vectors of RFC 6238 appendix B, truncated to 6 digits
+    #[test]
+    fn test_totp_codes() {
+        let totp = Totp {
+            secret: b"12345678901234567890".to_vec(),
+        };
+        let timestamps = [59, 1111111109, 1111111111, 1234567890, 2000000000, 20000000000];
+        let expected_codes = ["287082", "081804", "050471", "005924", "279037", "353130"];
+
+        for (index, timestamp) in timestamps.iter().enumerate() {
+            assert_eq!(totp.code(Totp::step(*timestamp)), expected_codes[index]);
+        }
+    }
+
+    #[test]
+    fn test_totp_verify() {
+        let totp = Totp::generate();
+        let timestamp = 1600000000;
+        let step = Totp::step(timestamp);
+
+        assert_eq!(totp.verify(&totp.code(step), timestamp), Some(step));
+        assert_eq!(totp.verify(&totp.code(step - 1), timestamp), Some(step - 1));
+        assert_eq!(totp.verify(&totp.code(step + 1), timestamp), Some(step + 1));
+        assert_eq!(totp.verify(&totp.code(step - 2), timestamp), None);
+        assert_eq!(totp.verify("", timestamp), None);
+        assert_eq!(totp.verify("12345a", timestamp), None);
+    }
+
+    #[test]
+    fn test_totp_base32() {
+        let totp = Totp::generate();
+        let secret = totp.to_base32();
+
+        assert_eq!(secret.len(), 32);
+        assert_eq!(
+            Totp::from_base32(&secret).map(|totp| totp.secret),
+            Some(totp.secret.clone())
+        );
+        assert!(Totp::from_base32("not base32!").is_none());
+        assert!(totp
+            .uri("Productivity", "dimashur@gmail.com")
+            .starts_with("otpauth://totp/Productivity:dimashur@gmail.com?secret="));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::account::account_models::AccountDbExecutor;
+use crate::account::account_passwords::{self, PasswordErrors};
+use crate::auth_events::auth_event_controllers::record_auth_event;
+use crate::auth_events::auth_event_models::AuthEventKind;
+use crate::common::responses::ServerResponse;
+use crate::common::tokens;
+use crate::middlewares::auth::AuthenticatedAccount;
+use crate::two_factor::totp::Totp;
+use crate::two_factor::two_factor_models::TwoFactorDbExecutor;
+use crate::AppState;
+use crate::DbErrors;
+use actix_web::{self, dev, error, http, web, HttpRequest};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const RECOVERY_CODES_COUNT: usize = 10;
+
+#[derive(Deserialize)]
+pub struct TwoFactorConfirmRequest {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorDisableRequest {
+    password: String,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorEnrollResponse {
+    secret: String,
+    uri: String,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorConfirmResponse {
+    // The only time the codes are shown. each one can be used once instead of a code
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum TwoFactorErrors {
+    AlreadyEnabled,
+    NotEnrolled,
+    InvalidCode,
+    WrongPassword,
+    SessionRequired,
+    Server,
+}
+
+impl From<DbErrors> for TwoFactorErrors {
+    fn from(_err: DbErrors) -> TwoFactorErrors {
+        TwoFactorErrors::Server
+    }
+}
+
+impl From<PasswordErrors> for TwoFactorErrors {
+    fn from(_err: PasswordErrors) -> TwoFactorErrors {
+        TwoFactorErrors::Server
+    }
+}
+
+impl std::fmt::Display for TwoFactorErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl error::ResponseError for TwoFactorErrors {
+    fn status_code(&self) -> http::StatusCode {
+        match *self {
+            TwoFactorErrors::AlreadyEnabled => http::StatusCode::CONFLICT,
+            TwoFactorErrors::NotEnrolled => http::StatusCode::CONFLICT,
+            TwoFactorErrors::InvalidCode => http::StatusCode::UNPROCESSABLE_ENTITY,
+            TwoFactorErrors::WrongPassword => http::StatusCode::FORBIDDEN,
+            TwoFactorErrors::SessionRequired => http::StatusCode::FORBIDDEN,
+            TwoFactorErrors::Server => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let response_json = match self {
+            TwoFactorErrors::AlreadyEnabled => {
+                ServerResponse::new((), json!({"error": "Two-factor authentication is already enabled"}))
+            }
+            TwoFactorErrors::NotEnrolled => {
+                ServerResponse::new((), json!({"error": "Two-factor authentication isn't enrolled"}))
+            }
+            TwoFactorErrors::InvalidCode => ServerResponse::new((), json!({"error": "Invalid code"})),
+            TwoFactorErrors::WrongPassword => ServerResponse::new((), json!({"error": "Wrong password"})),
+            TwoFactorErrors::SessionRequired => ServerResponse::new(
+                (),
+                json!({"error": "Two-factor authentication can't be managed with an API token"}),
+            ),
+            TwoFactorErrors::Server => ServerResponse::new((), json!({"error": "Server error"})),
+        };
+
+        dev::HttpResponseBuilder::new(self.status_code())
+            .set_header(http::header::CONTENT_TYPE, "application/json")
+            .json(response_json)
+    }
+}
+
+// The recovery codes are shown as two groups of 5 characters, but are accepted in any case and with any separator
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            let code = uuid::Uuid::new_v4().to_simple().to_string();
+            format!("{}-{}", &code[..5], &code[5..10])
+        })
+        .collect()
+}
+
+// Like the api tokens, a leaked token mustn't be able to change how the account is protected
+fn require_session(account: &AuthenticatedAccount) -> Result<(), TwoFactorErrors> {
+    match account.session_id() {
+        Some(_) => Ok(()),
+        None => Err(TwoFactorErrors::SessionRequired),
+    }
+}
+
+pub async fn two_factor_enroll(
+    account: AuthenticatedAccount,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TwoFactorErrors> {
+    require_session(&account)?;
+
+    let account_id = account.account_id();
+    let rows = AccountDbExecutor::get(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    let email: String = rows.first().ok_or(TwoFactorErrors::Server)?.get("email");
+
+    let totp = Totp::generate();
+    let secret = totp.to_base32();
+    let rows_count = TwoFactorDbExecutor::enroll(&state.db_pool, &[&account_id, &secret, &Utc::now()])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(TwoFactorErrors::AlreadyEnabled);
+    }
+
+    let uri = totp.uri(&state.settings.two_factor.issuer, &email);
+    let response_json = ServerResponse::new(TwoFactorEnrollResponse { secret, uri }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+// Enables the two-factor authentication once the authenticator app proved that it was set up correctly
+pub async fn two_factor_confirm(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    body: web::Json<TwoFactorConfirmRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TwoFactorErrors> {
+    require_session(&account)?;
+
+    let account_id = account.account_id();
+    let rows = TwoFactorDbExecutor::get(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    let row = rows.first().ok_or(TwoFactorErrors::NotEnrolled)?;
+    let enabled: bool = row.get("enabled");
+    if enabled {
+        return Err(TwoFactorErrors::AlreadyEnabled);
+    }
+
+    let totp = Totp::from_base32(row.get("secret")).ok_or(TwoFactorErrors::Server)?;
+    let step = totp
+        .verify(&body.code, Utc::now().timestamp())
+        .ok_or(TwoFactorErrors::InvalidCode)?;
+
+    let recovery_codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| tokens::hash_token(&normalize_recovery_code(code)))
+        .collect();
+    let rows_count = TwoFactorDbExecutor::enable(&state.db_pool, &[&account_id, &step, &code_hashes])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    if rows_count == 0 {
+        return Err(TwoFactorErrors::AlreadyEnabled);
+    }
+    record_auth_event(&state, &request, account_id, AuthEventKind::TwoFactorActivation).await;
+
+    let response_json = ServerResponse::new(TwoFactorConfirmResponse { recovery_codes }, ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
+
+pub async fn two_factor_disable(
+    request: HttpRequest,
+    account: AuthenticatedAccount,
+    body: web::Json<TwoFactorDisableRequest>,
+    state: web::Data<AppState>,
+) -> actix_web::Result<actix_web::HttpResponse, TwoFactorErrors> {
+    require_session(&account)?;
+
+    let account_id = account.account_id();
+    let rows = TwoFactorDbExecutor::get(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    if rows.is_empty() {
+        return Err(TwoFactorErrors::NotEnrolled);
+    }
+
+    let password_matches = account_passwords::check_account_password(&state, account_id, &body.password)
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    if !password_matches {
+        return Err(TwoFactorErrors::WrongPassword);
+    }
+
+    TwoFactorDbExecutor::disable(&state.db_pool, &[&account_id])
+        .await
+        .map_err(|err| {
+            warn!(target: "warnings", "Warn: {:?}", err);
+            TwoFactorErrors::from(err)
+        })?;
+    record_auth_event(&state, &request, account_id, AuthEventKind::TwoFactorDeactivation).await;
+
+    let response_json = ServerResponse::new((), ());
+    Ok(actix_web::HttpResponse::Ok().json(response_json))
+}
@@ -0,0 +1,195 @@
+use crate::DbErrors;
+use deadpool_postgres::Pool;
+use postgres::types::ToSql;
+use postgres::Row;
+
+pub struct TwoFactorDbExecutor;
+
+// Like the other tokens, only the hashes of the recovery codes and of the challenges are stored
+impl TwoFactorDbExecutor {
+    // Starts an enrollment with a new secret, replacing a previous unconfirmed one. returns the number of stored
+    // secrets, which is 0 if the two-factor authentication is already enabled
+    pub async fn enroll(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            INSERT INTO two_factor (account_id, secret, creation_date) VALUES ($1, $2, $3)
+            ON CONFLICT (account_id) DO UPDATE SET secret = EXCLUDED.secret, creation_date = EXCLUDED.creation_date
+            WHERE NOT two_factor.enabled",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    pub async fn get(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "SELECT secret, enabled, last_used_step FROM two_factor WHERE account_id = $1",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // Enables the enrolled secret and stores the hashes of its recovery codes. the confirmation code's step is recorded
+    // so that it can't be used again to login. returns the number of enabled secrets
+    pub async fn enable(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let rows = transaction
+            .query(
+                "
+            UPDATE two_factor SET enabled = TRUE, last_used_step = $2
+            WHERE account_id = $1 AND NOT enabled
+            RETURNING account_id",
+                &params[..2],
+            )
+            .await?;
+        if !rows.is_empty() {
+            transaction
+                .execute(
+                    "
+                INSERT INTO two_factor_recovery_code (account_id, code_hash)
+                SELECT $1, code_hash FROM unnest($2::TEXT[]) AS code_hash",
+                    &[params[0], params[2]],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(rows.len() as u64)
+    }
+
+    // Records the step of a login code. returns 0 if that step, or a later one, was already used
+    pub async fn use_step(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            UPDATE two_factor SET last_used_step = $2
+            WHERE account_id = $1 AND enabled AND (last_used_step IS NULL OR last_used_step < $2)",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Consumes a recovery code. returns the number of consumed codes
+    pub async fn use_recovery_code(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            DELETE FROM two_factor_recovery_code
+            WHERE account_id = $1 AND code_hash = $2",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Removes the secret and its recovery codes. returns the number of removed secrets
+    pub async fn disable(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute("DELETE FROM two_factor WHERE account_id = $1", params)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Stores the challenge of a login which is waiting for its second factor, cleaning up the expired ones
+    pub async fn create_challenge(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            WITH deleted AS (
+                DELETE FROM two_factor_challenge WHERE expiration_date < $4
+            )
+            INSERT INTO two_factor_challenge (token_hash, account_id, device, creation_date, expiration_date)
+            VALUES ($1, $2, $3, $4, $5)",
+                params,
+            )
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    pub async fn get_challenge(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, DbErrors> {
+        let db_client = db_pool.get().await?;
+        let rows = db_client
+            .query(
+                "
+            SELECT two_factor_challenge.account_id, two_factor_challenge.device, account.email
+            FROM two_factor_challenge
+            JOIN account ON account.id = two_factor_challenge.account_id
+            WHERE token_hash = $1 AND expiration_date > $2
+                AND NOT account.disabled",
+                params,
+            )
+            .await?;
+
+        Ok(rows)
+    }
+
+    // Counts a wrong code against the challenge, and removes the challenge once it reached the maximum number of
+    // attempts. returns the number of removed challenges
+    pub async fn fail_challenge(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute(
+                "
+            UPDATE two_factor_challenge SET attempts = attempts + 1
+            WHERE token_hash = $1",
+                &params[..1],
+            )
+            .await?;
+        let count = match count {
+            0 => 0,
+            _ => {
+                transaction
+                    .execute(
+                        "
+                    DELETE FROM two_factor_challenge
+                    WHERE token_hash = $1 AND attempts >= $2",
+                        params,
+                    )
+                    .await?
+            }
+        };
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+
+    // Returns the number of consumed challenges, which is 0 if it was consumed already by a concurrent request
+    pub async fn consume_challenge(db_pool: &Pool, params: &[&(dyn ToSql + Sync)]) -> Result<u64, DbErrors> {
+        let mut db_client = db_pool.get().await?;
+        let transaction = db_client.transaction().await?;
+        let count = transaction
+            .execute("DELETE FROM two_factor_challenge WHERE token_hash = $1", params)
+            .await?;
+        transaction.commit().await?;
+
+        Ok(count)
+    }
+}
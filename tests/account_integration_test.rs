@@ -6,14 +6,19 @@ extern crate serde_json;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use actix_http::cookie::Cookie;
     use actix_http::http::StatusCode;
     use actix_rt;
     use actix_service::Service;
     use actix_web::{http, test, App};
     use deadpool_postgres::Pool;
+    use productivity::sessions::session_redis_store::RedisSessionStore;
+    use productivity::settings::{AccountSettings, RedisSettings, SessionSettings, Settings, VerificationPolicy};
     use productivity::AppState;
+    use regex::Regex;
     use std::sync::Arc;
-    use tokio::sync::Mutex;
+    use std::time::{Duration, Instant};
+    use tokio::time::delay_for;
 
     #[test]
     fn test_account_register() {
@@ -21,14 +26,16 @@ mod tests {
         let db_pool = Pool::clone(&db_pool);
 
         actix_rt::System::new("test_account_register_runtime".to_string()).block_on(async move {
-            let redis_client = common::create_redis_client()
-                .await
-                .expect("Can't create redis connection");
-            let redis_client = Arc::new(Mutex::new(redis_client));
+            let session_store = common::create_session_store(&db_pool).await;
 
             let mut app = test::init_service(
                 App::new()
-                    .data(AppState { db_pool, redis_client })
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
                     .configure(common::test_config_app),
             )
             .await;
@@ -86,14 +93,16 @@ mod tests {
         let db_pool = Pool::clone(&db_pool);
 
         actix_rt::System::new("test_account_login_runtime".to_string()).block_on(async move {
-            let redis_client = common::create_redis_client()
-                .await
-                .expect("Can't create redis connection");
-            let redis_client = Arc::new(Mutex::new(redis_client));
+            let session_store = common::create_session_store(&db_pool).await;
 
             let mut app = test::init_service(
                 App::new()
-                    .data(AppState { db_pool, redis_client })
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
                     .configure(common::test_config_app),
             )
             .await;
@@ -156,4 +165,1653 @@ mod tests {
             assert_eq!(session_id.len() > 0, true);
         });
     }
+
+    #[test]
+    fn test_account_login_lockout() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_login_lockout_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            let settings = Settings {
+                account: AccountSettings {
+                    login_attempts: 3,
+                    login_ip_attempts: 5,
+                    login_lockout: chrono::Duration::seconds(1),
+                    login_max_lockout: chrono::Duration::seconds(2),
+                    ..AccountSettings::default()
+                },
+                ..Settings::default()
+            };
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The email is locked after 3 wrong passwords, even for the right one
+            for _ in 0..3 {
+                let payload = json!({"email": "dimashur@gmail.com", "password": "87654321"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            }
+
+            let payload = json!({"email": "DimaShur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(
+                response
+                    .headers()
+                    .get(http::header::RETRY_AFTER)
+                    .unwrap()
+                    .to_str()
+                    .unwrap(),
+                "1"
+            );
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(
+                response_value["meta"]["error"],
+                "Too many failed attempts. try again later"
+            );
+
+            // Until the lockout is over
+            delay_for(Duration::from_millis(1100)).await;
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The ip is locked after trying 5 different emails
+            for index in 0..5 {
+                let payload = json!({"email": format!("dimashur{}@gmail.com", index), "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login")
+                    .peer_addr("10.0.0.1:40000".parse().unwrap())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            }
+
+            // A forwarded address isn't trusted from the clients
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .peer_addr("10.0.0.1:40000".parse().unwrap())
+                .header("x-forwarded-for", "10.0.0.2")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .peer_addr("10.0.0.2:40000".parse().unwrap())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_password_rehash() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_password_rehash_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // An account from when the passwords were hashed by pgcrypto, with `crypt('12345678', gen_salt('bf'))`
+            let db_client = db_pool.get().await.expect("Can't get db client");
+            db_client
+                .execute(
+                    "INSERT INTO account (email, password, verified) VALUES ($1, $2, TRUE)",
+                    &[
+                        &"dimashur@gmail.com",
+                        &"$2a$06$PyfKtobUBYrfOJBabMEVqu4YRiCmMmzwShR9aCniE8IMjJUL.njyG",
+                    ],
+                )
+                .await
+                .expect("Can't insert account");
+
+            let get_password_hash = || async {
+                let row = db_client
+                    .query_one(
+                        "SELECT password FROM account WHERE email = $1",
+                        &[&"dimashur@gmail.com"],
+                    )
+                    .await
+                    .expect("Can't get account");
+                row.get::<_, String>("password")
+            };
+            assert!(get_password_hash().await.starts_with("$2a$"));
+
+            // A wrong password keeps the old hash
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert!(get_password_hash().await.starts_with("$2a$"));
+
+            // The right one upgrades it
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let password_hash = get_password_hash().await;
+            assert!(password_hash.starts_with("$argon2id$"));
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(get_password_hash().await, password_hash);
+        });
+    }
+
+    #[test]
+    fn test_account_logout() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_logout_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Successful login
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            // Logout without cookies
+            let request = test::TestRequest::post().uri("/api/account/logout").to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // Successful logout. the session cookie should be expired
+            let request = test::TestRequest::post()
+                .uri("/api/account/logout")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let expired_cookie = response
+                .headers()
+                .get_all(http::header::SET_COOKIE)
+                .map(|value| value.to_str().expect("Can't parse cookie"))
+                .find(|cookie| cookie.starts_with("session_id="))
+                .expect("Session cookie wasn't expired");
+            assert!(expired_cookie.contains("Max-Age=0"));
+
+            // The revoked session can't be used anymore
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // Logout with the revoked session
+            let request = test::TestRequest::post()
+                .uri("/api/account/logout")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+        });
+    }
+
+    #[test]
+    fn test_account_sessions() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_sessions_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Login from the laptop
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "laptop"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(http::header::USER_AGENT, "Firefox")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let laptop_session_id = common::get_session_id(response.headers()).to_string();
+
+            // Login from the phone
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "phone"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(http::header::USER_AGENT, "Safari")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let phone_session_id = common::get_session_id(response.headers()).to_string();
+            let phone_csrf_token = common::get_csrf_token(response.headers()).to_string();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_ne!(laptop_session_id, phone_session_id);
+
+            // Both sessions are valid
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", laptop_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", phone_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let sessions = response_value["data"]["sessions"]
+                .as_array()
+                .expect("Can't parse sessions response");
+            assert_eq!(sessions.len(), 2);
+
+            let laptop_session = sessions
+                .iter()
+                .find(|session| session["id"] == laptop_session_id.as_str())
+                .expect("Laptop session is missing");
+            assert_eq!(laptop_session["device"], "laptop");
+            assert_eq!(laptop_session["user_agent"], "Firefox");
+            assert_eq!(laptop_session["current"], false);
+
+            let phone_session = sessions
+                .iter()
+                .find(|session| session["id"] == phone_session_id.as_str())
+                .expect("Phone session is missing");
+            assert_eq!(phone_session["device"], "phone");
+            assert_eq!(phone_session["user_agent"], "Safari");
+            assert_eq!(phone_session["current"], true);
+
+            // Revoke a session which doesn't exist
+            let request = test::TestRequest::delete()
+                .uri("/api/account/sessions/unknown")
+                .cookie(Cookie::new("session_id", phone_session_id.clone()))
+                .header("x-csrf-token", phone_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // Revoke the laptop session from the phone
+            let request = test::TestRequest::delete()
+                .uri(&format!("/api/account/sessions/{}", laptop_session_id))
+                .cookie(Cookie::new("session_id", phone_session_id.clone()))
+                .header("x-csrf-token", phone_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The laptop session can't be used anymore
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", laptop_session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // The phone session is still valid
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", phone_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let sessions = response_value["data"]["sessions"]
+                .as_array()
+                .expect("Can't parse sessions response");
+            assert_eq!(sessions.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_account_password_change() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_password_change_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Login from the laptop and the phone
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "laptop"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let laptop_session_id = common::get_session_id(response.headers()).to_string();
+            let laptop_csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "phone"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let phone_session_id = common::get_session_id(response.headers()).to_string();
+
+            // Without a session
+            let payload = json!({"current_password": "12345678", "new_password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // Wrong current password
+            let payload = json!({"current_password": "11111111", "new_password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .cookie(Cookie::new("session_id", laptop_session_id.clone()))
+                .header("x-csrf-token", laptop_csrf_token.clone())
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // Invalid new password
+            let payload = json!({"current_password": "12345678", "new_password": "1234"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .cookie(Cookie::new("session_id", laptop_session_id.clone()))
+                .header("x-csrf-token", laptop_csrf_token.clone())
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            // Successful change from the laptop
+            let payload = json!({"current_password": "12345678", "new_password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .cookie(Cookie::new("session_id", laptop_session_id.clone()))
+                .header("x-csrf-token", laptop_csrf_token.clone())
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The phone was logged out while the laptop stays logged in
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", phone_session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", laptop_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let sessions = response_value["data"]["sessions"]
+                .as_array()
+                .expect("Can't parse sessions response");
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0]["id"], laptop_session_id.as_str());
+
+            // Only the new password can be used to login
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_password_reset() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_password_reset_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            let settings = Settings {
+                account: AccountSettings {
+                    password_reset_timeout: chrono::Duration::seconds(2),
+                    ..AccountSettings::default()
+                },
+                ..Settings::default()
+            };
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let token_regex = Regex::new(r"reset your password: (\w+)").expect("Couldn't create token regex");
+            let get_reset_token = |to: &str| -> Option<String> {
+                let email = common::get_last_email(to)?;
+                let body = email["body"].as_str().expect("Can't parse email body");
+                Some(token_regex.captures(body)?.get(1)?.as_str().to_string())
+            };
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+
+            // Unknown emails get the same response, but no email is sent
+            let payload = json!({"email": "dimashur1@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/forgot")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(get_reset_token("dimashur1@gmail.com").is_none());
+
+            // The token expires
+            let payload = json!({"email": "dimashur@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/forgot")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let expired_token = get_reset_token("dimashur@gmail.com").expect("Reset email wasn't sent");
+
+            delay_for(Duration::from_millis(2500)).await;
+            let payload = json!({"token": expired_token, "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/reset")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // A new token
+            let payload = json!({"email": "dimashur@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/forgot")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let token = get_reset_token("dimashur@gmail.com").expect("Reset email wasn't sent");
+            assert_ne!(token, expired_token);
+
+            // Invalid new password
+            let payload = json!({"token": token, "password": "1234"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/reset")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            // Successful reset
+            let payload = json!({"token": token, "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/reset")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The token can only be used once
+            let payload = json!({"token": token, "password": "11111111"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/reset")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // The existing sessions were logged out
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_verification() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_verification_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            let settings = Settings {
+                account: AccountSettings {
+                    verification_policy: VerificationPolicy::Login,
+                    ..AccountSettings::default()
+                },
+                ..Settings::default()
+            };
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let token_regex = Regex::new(r"verify\?token=(\w+)").expect("Couldn't create token regex");
+            let get_verification_token = |to: &str| -> Option<String> {
+                let email = common::get_last_email(to)?;
+                let body = email["body"].as_str().expect("Can't parse email body");
+                Some(token_regex.captures(body)?.get(1)?.as_str().to_string())
+            };
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The registration sends the verification email
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let first_token = get_verification_token("dimashur@gmail.com").expect("Verification email wasn't sent");
+
+            // Can't login before the verification
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Email not verified");
+
+            // Unknown emails get the same response, but no email is sent
+            let payload = json!({"email": "dimashur1@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/verify/resend")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(get_verification_token("dimashur1@gmail.com").is_none());
+
+            // A new email replaces the previous token
+            let payload = json!({"email": "dimashur@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/verify/resend")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let token = get_verification_token("dimashur@gmail.com").expect("Verification email wasn't sent");
+            assert_ne!(token, first_token);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/verify?token={}", first_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // Successful verification
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/verify?token={}", token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The token can only be used once
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/verify?token={}", token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // Verified accounts don't get new emails
+            let payload = json!({"email": "dimashur@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/verify/resend")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(get_verification_token("dimashur@gmail.com"), Some(token));
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_verification_features() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_verification_features_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            let settings = Settings {
+                account: AccountSettings {
+                    verification_policy: VerificationPolicy::Features,
+                    ..AccountSettings::default()
+                },
+                ..Settings::default()
+            };
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The login works, but the todos can't be used yet
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let error = app.call(request).await.expect_err("Unverified account used the todos");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+
+            let response_value = common::get_error_body(&error).await;
+            assert_eq!(response_value["meta"]["error"], "Email not verified");
+
+            // The session doesn't need to be renewed after the verification
+            let email = common::get_last_email("dimashur@gmail.com").expect("Verification email wasn't sent");
+            let body = email["body"].as_str().expect("Can't parse email body");
+            let link_start = body.find("/api/account/verify").expect("Verification link is missing");
+            let link = body[link_start..].split_whitespace().next().unwrap();
+
+            let request = test::TestRequest::get().uri(link).to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_login_link() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_login_link_runtime".to_string()).block_on(async move {
+            let mut disabled_app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let settings = Settings {
+                account: AccountSettings {
+                    verification_policy: VerificationPolicy::Login,
+                    login_links: true,
+                    ..AccountSettings::default()
+                },
+                ..Settings::default()
+            };
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let token_regex = Regex::new(r"login/link/(\w+)").expect("Couldn't create token regex");
+            let get_login_token = |to: &str| -> Option<String> {
+                let email = common::get_last_email(to)?;
+                let body = email["body"].as_str().expect("Can't parse email body");
+                Some(token_regex.captures(body)?.get(1)?.as_str().to_string())
+            };
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The deployments which don't want them can turn them off
+            let payload = json!({"email": "dimashur@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/link")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut disabled_app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // Unknown emails get the same response, but no email is sent
+            let payload = json!({"email": "dimashur1@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/link")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(get_login_token("dimashur1@gmail.com").is_none());
+
+            // A new link replaces the previous one
+            let mut tokens = vec![];
+            for _ in 0..2 {
+                let payload = json!({"email": "dimashur@gmail.com", "device": "Phone"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login/link")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                tokens.push(get_login_token("dimashur@gmail.com").expect("Login link wasn't sent"));
+            }
+            assert_ne!(tokens[0], tokens[1]);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/login/link/{}", tokens[0]))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Invalid or expired link");
+
+            // The link logs in like the password does
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/login/link/{}", tokens[1]))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            assert!(!session_id.is_empty());
+            assert!(!common::get_csrf_token(response.headers()).is_empty());
+            assert!(common::get_session_max_age(response.headers()).is_some());
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["sessions"][0]["device"], "Phone");
+
+            // The links can only be used once
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/login/link/{}", tokens[1]))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // The link proved the email belongs to the account, so it's verified now
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_email_change() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_email_change_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings {
+                            account: AccountSettings {
+                                login_links: true,
+                                ..AccountSettings::default()
+                            },
+                            ..Settings::default()
+                        },
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let token_regex = Regex::new(r"confirm\?token=(\w+)").expect("Couldn't create token regex");
+            let get_confirmation_token = |to: &str| -> Option<String> {
+                let email = common::get_last_email(to)?;
+                let body = email["body"].as_str().expect("Can't parse email body");
+                Some(token_regex.captures(body)?.get(1)?.as_str().to_string())
+            };
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            for email in &["dimashur@gmail.com", "taken@gmail.com"] {
+                let payload = json!({"email": email, "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/register")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            // The password is needed, and the email must be free
+            let payloads = vec![
+                (
+                    json!({"email": "new", "password": "12345678"}),
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                ),
+                (
+                    json!({"email": "new@gmail.com", "password": "87654321"}),
+                    StatusCode::FORBIDDEN,
+                ),
+                (
+                    json!({"email": "taken@gmail.com", "password": "12345678"}),
+                    StatusCode::CONFLICT,
+                ),
+            ];
+            for (payload, status) in payloads {
+                let request = test::TestRequest::post()
+                    .uri("/api/account/email")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), status);
+            }
+
+            // A second request replaces the pending email
+            for email in &["first@gmail.com", "new@gmail.com"] {
+                let payload = json!({"email": email, "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/email")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["data"]["pending_email"], *email);
+            }
+            let first_token = get_confirmation_token("first@gmail.com").expect("Confirmation email wasn't sent");
+            let token = get_confirmation_token("new@gmail.com").expect("Confirmation email wasn't sent");
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/email/confirm?token={}", first_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // The email doesn't change until it's confirmed
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Links sent to the previous email before the change
+            let get_previous_email_token = |pattern: &str| -> String {
+                let email = common::get_last_email("dimashur@gmail.com").expect("Email wasn't sent");
+                let body = email["body"].as_str().expect("Can't parse email body");
+                let regex = Regex::new(pattern).expect("Couldn't create token regex");
+                regex.captures(body).expect("Token wasn't sent")[1].to_string()
+            };
+            let payload = json!({"email": "dimashur@gmail.com"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/forgot")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let reset_token = get_previous_email_token(r"reset your password: (\w+)");
+
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/link")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let login_token = get_previous_email_token(r"login/link/(\w+)");
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/email/confirm?token={}", token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // They can't take the account over anymore
+            let payload = json!({"token": reset_token, "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password/reset")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/login/link/{}", login_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/email/confirm?token={}", token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // The previous email is told about it
+            let email = common::get_last_email("dimashur@gmail.com").expect("Notification wasn't sent");
+            assert_eq!(email["subject"], "Your email was changed");
+            assert!(email["body"]
+                .as_str()
+                .expect("Can't parse email body")
+                .contains("new@gmail.com"));
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let payload = json!({"email": "new@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["email"], "new@gmail.com");
+            assert_eq!(response_value["data"]["verified"], true);
+
+            // Both the request and the change are in the account's history
+            let request = test::TestRequest::get()
+                .uri("/api/account/activity")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            let kinds: Vec<&str> = response_value["data"]["events"]
+                .as_array()
+                .expect("Can't parse events")
+                .iter()
+                .filter_map(|event| event["kind"].as_str())
+                .collect();
+            assert!(kinds.contains(&"email_change_request"));
+            assert!(kinds.contains(&"email_change"));
+
+            // An email taken while the change was pending can't be swapped in
+            let payload = json!({"email": "late@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/email")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let token = get_confirmation_token("late@gmail.com").expect("Confirmation email wasn't sent");
+
+            let payload = json!({"email": "late@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/email/confirm?token={}", token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+        });
+    }
+
+    #[test]
+    fn test_account_delete() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_delete_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Two sessions and two todos
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "laptop"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "phone"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let phone_session_id = common::get_session_id(response.headers()).to_string();
+
+            for title in &["first", "second"] {
+                let payload = json!({"title": title, "body": "world"});
+                let request = test::TestRequest::post()
+                    .uri("/api/todo/create")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            // Without a session
+            let payload = json!({"password": "12345678"});
+            let request = test::TestRequest::delete()
+                .uri("/api/account")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // Wrong password
+            let payload = json!({"password": "87654321"});
+            let request = test::TestRequest::delete()
+                .uri("/api/account")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // Successful deletion. the receipt tells what was deleted
+            let payload = json!({"password": "12345678"});
+            let request = test::TestRequest::delete()
+                .uri("/api/account")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let expired_cookie = response
+                .headers()
+                .get_all(http::header::SET_COOKIE)
+                .map(|value| value.to_str().expect("Can't parse cookie"))
+                .find(|cookie| cookie.starts_with("session_id="))
+                .expect("Session cookie wasn't expired");
+            assert!(expired_cookie.contains("Max-Age=0"));
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["email"], "dimashur@gmail.com");
+            assert_eq!(response_value["data"]["deleted_todos"], 2);
+            assert_eq!(response_value["data"]["revoked_sessions"], 2);
+
+            // Every session is gone
+            for session_id in &[session_id, phone_session_id] {
+                let request = test::TestRequest::get()
+                    .uri("/api/todo/get")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .to_request();
+                let response = app.call(request).await;
+                assert!(response.is_err());
+            }
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // The email can be registered again
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_account_session_expiry() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_session_expiry_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            let settings = Settings {
+                session: SessionSettings {
+                    absolute_timeout: chrono::Duration::seconds(4),
+                    idle_timeout: chrono::Duration::seconds(2),
+                    ..SessionSettings::default()
+                },
+                ..Settings::default()
+            };
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Successful login. the cookie lives as long as the idle timeout
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            assert_eq!(common::get_session_max_age(response.headers()), Some(2));
+
+            // Activity renews the session and re-issues the cookie
+            delay_for(Duration::from_millis(1500)).await;
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(common::get_session_id(response.headers()), session_id);
+            assert_eq!(common::get_session_max_age(response.headers()), Some(2));
+
+            delay_for(Duration::from_millis(1500)).await;
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The renewal can't go past the absolute timeout
+            let max_age = common::get_session_max_age(response.headers()).expect("Session cookie wasn't renewed");
+            assert!(max_age < 2);
+
+            // The absolute timeout has passed
+            delay_for(Duration::from_millis(1500)).await;
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let error = app.call(request).await.expect_err("Expired session was accepted");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+            let response_value = common::get_error_body(&error).await;
+            assert_eq!(response_value["meta"]["error"], "Session expired");
+
+            // A new session which isn't used expires after the idle timeout
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+
+            delay_for(Duration::from_millis(2500)).await;
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let error = app.call(request).await.expect_err("Expired session was accepted");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+        });
+    }
+
+    #[test]
+    fn test_account_redis_unavailable() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_account_redis_unavailable_runtime".to_string()).block_on(async move {
+            // Nothing listens on this port
+            let redis_settings = RedisSettings {
+                timeout: chrono::Duration::seconds(1),
+                ..RedisSettings::default()
+            };
+            let redis_pool = common::create_redis_pool("redis://127.0.0.1:1".to_string(), &redis_settings)
+                .expect("Can't create redis pool");
+            let session_store = Arc::new(RedisSessionStore::new(redis_pool, redis_settings.max_backoff));
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", "1234"))
+                .to_request();
+            let error = app
+                .call(request)
+                .await
+                .expect_err("Request was accepted without a session store");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+            let response_value = common::get_error_body(&error).await;
+            assert_eq!(response_value["meta"]["error"], "Service unavailable");
+
+            // While backing off the store fails without trying to connect again
+            let start = Instant::now();
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", "1234"))
+                .to_request();
+            let error = app
+                .call(request)
+                .await
+                .expect_err("Request was accepted without a session store");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::SERVICE_UNAVAILABLE);
+            assert!(start.elapsed() < Duration::from_millis(50));
+        });
+    }
 }
@@ -0,0 +1,316 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_service::Service;
+    use actix_web::{http, test, App};
+    use deadpool_postgres::Pool;
+    use productivity::settings::Settings;
+    use productivity::AppState;
+
+    #[test]
+    fn test_admin() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_admin_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            for email in &["admin@gmail.com", "dimashur@gmail.com"] {
+                let payload = json!({"email": email, "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/register")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            // Admins are promoted by hand
+            let db_client = db_pool.get().await.expect("Can't get db client");
+            db_client
+                .execute(
+                    "UPDATE account SET role = 'admin' WHERE email = $1",
+                    &[&"admin@gmail.com"],
+                )
+                .await
+                .expect("Can't promote account");
+
+            let payload = json!({"email": "admin@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let admin_session_id = common::get_session_id(response.headers()).to_string();
+            let admin_csrf_token = common::get_csrf_token(response.headers()).to_string();
+            let response_value = common::get_response_body(response).await;
+            let admin_id = response_value["data"]["account_id"]
+                .as_i64()
+                .expect("Can't parse account_id");
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+            let response_value = common::get_response_body(response).await;
+            let account_id = response_value["data"]["account_id"]
+                .as_i64()
+                .expect("Can't parse account_id");
+
+            // Regular accounts are refused
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let error = app.call(request).await.expect_err("Request wasn't refused");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+
+            // So are the api tokens, even the admins' ones
+            let payload = json!({"name": "cli", "scope": "read_write"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .header("x-csrf-token", admin_csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            let admin_token = response_value["data"]["token"]
+                .as_str()
+                .expect("Can't parse token")
+                .to_string();
+
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+                .to_request();
+            let error = app.call(request).await.expect_err("Request wasn't refused");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+
+            // List and search
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts")
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["total"], 2);
+            let accounts = response_value["data"]["accounts"]
+                .as_array()
+                .expect("Can't parse accounts");
+            assert_eq!(accounts[0]["email"], "admin@gmail.com");
+            assert_eq!(accounts[0]["role"], "admin");
+            assert_eq!(accounts[1]["role"], "user");
+            assert_eq!(accounts[1]["disabled"], false);
+
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts?search=DIMA")
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["total"], 1);
+            assert_eq!(response_value["data"]["accounts"][0]["email"], "dimashur@gmail.com");
+
+            // The wildcards are searched as they are
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts?search=%25")
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["total"], 0);
+
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts?offset=1&limit=1")
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["total"], 2);
+            assert_eq!(response_value["data"]["accounts"].as_array().map(Vec::len), Some(1));
+            assert_eq!(response_value["data"]["accounts"][0]["id"], account_id);
+
+            // The total counts the matching accounts, even past the last page
+            for uri in &["/api/admin/accounts?offset=10", "/api/admin/accounts?limit=0"] {
+                let request = test::TestRequest::get()
+                    .uri(uri)
+                    .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["data"]["total"], 2);
+                assert_eq!(response_value["data"]["accounts"].as_array().map(Vec::len), Some(0));
+            }
+
+            for uri in &["/api/admin/accounts?offset=-1", "/api/admin/accounts?limit=-1"] {
+                let request = test::TestRequest::get()
+                    .uri(uri)
+                    .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            }
+
+            let request = test::TestRequest::get()
+                .uri("/api/admin/accounts?limit=1000")
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Usage
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/admin/accounts/{}", account_id))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["email"], "dimashur@gmail.com");
+            assert_eq!(response_value["data"]["usage"]["todos"], 1);
+            assert_eq!(response_value["data"]["usage"]["api_tokens"], 0);
+            assert_eq!(response_value["data"]["usage"]["sessions"], 1);
+
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/admin/accounts/{}", account_id + 100))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // Admins can't lock themselves out
+            let request = test::TestRequest::post()
+                .uri(&format!("/api/admin/accounts/{}/disable", admin_id))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .header("x-csrf-token", admin_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+
+            // A disabled account loses its sessions and can't login
+            let request = test::TestRequest::post()
+                .uri(&format!("/api/admin/accounts/{}/disable", account_id))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .header("x-csrf-token", admin_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["disabled"], true);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Account disabled");
+
+            // Enabled again
+            let request = test::TestRequest::post()
+                .uri(&format!("/api/admin/accounts/{}/enable", account_id))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .header("x-csrf-token", admin_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+
+            // Force logout
+            let request = test::TestRequest::post()
+                .uri(&format!("/api/admin/accounts/{}/logout", account_id))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .header("x-csrf-token", admin_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["revoked_sessions"], 1);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            let request = test::TestRequest::post()
+                .uri(&format!("/api/admin/accounts/{}/logout", account_id + 100))
+                .cookie(Cookie::new("session_id", admin_session_id.clone()))
+                .header("x-csrf-token", admin_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        });
+    }
+}
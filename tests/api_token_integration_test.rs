@@ -0,0 +1,198 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_service::Service;
+    use actix_web::{http, test, App};
+    use deadpool_postgres::Pool;
+    use productivity::settings::Settings;
+    use productivity::AppState;
+
+    #[test]
+    fn test_api_tokens() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_api_tokens_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            // Invalid name
+            let payload = json!({"name": " ", "scope": "read_only"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            // A read only and a read write token
+            let payload = json!({"name": "backup script", "scope": "read_only"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["name"], "backup script");
+            assert_eq!(response_value["data"]["scope"], "read_only");
+            let read_token = response_value["data"]["token"]
+                .as_str()
+                .expect("Can't parse token")
+                .to_string();
+
+            let payload = json!({"name": "cli", "scope": "read_write"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let write_token = response_value["data"]["token"]
+                .as_str()
+                .expect("Can't parse token")
+                .to_string();
+            let write_token_id = response_value["data"]["id"].as_i64().expect("Can't parse token id");
+
+            // The tokens themselves aren't listed
+            let request = test::TestRequest::get()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let api_tokens = response_value["data"]["api_tokens"]
+                .as_array()
+                .expect("Can't parse tokens response");
+            assert_eq!(api_tokens.len(), 2);
+            assert!(api_tokens.iter().all(|api_token| api_token["token"].is_null()));
+
+            // The read only token can only read
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", read_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", read_token))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let error = app.call(request).await.expect_err("Read only token created a todo");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+
+            // The read write token can do both
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", write_token))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Unknown token
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, "Bearer 1234")
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // The tokens can't be managed with a token
+            let request = test::TestRequest::get()
+                .uri("/api/account/tokens")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", write_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // A revoked token can't be used anymore
+            let request = test::TestRequest::delete()
+                .uri(&format!("/api/account/tokens/{}", write_token_id))
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", write_token))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            let request = test::TestRequest::delete()
+                .uri(&format!("/api/account/tokens/{}", write_token_id))
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        });
+    }
+}
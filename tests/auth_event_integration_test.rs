@@ -0,0 +1,239 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_web::{http, test, App};
+    use deadpool_postgres::Pool;
+    use productivity::settings::{AccountSettings, Settings};
+    use productivity::AppState;
+    use serde_json::Value;
+
+    fn get_kinds(response_value: &Value) -> Vec<&str> {
+        response_value["data"]["events"]
+            .as_array()
+            .expect("Can't parse events")
+            .iter()
+            .map(|event| event["kind"].as_str().expect("Can't parse kind"))
+            .collect()
+    }
+
+    #[test]
+    fn test_auth_events() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_auth_events_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings {
+                            account: AccountSettings {
+                                login_attempts: 2,
+                                ..AccountSettings::default()
+                            },
+                            ..Settings::default()
+                        },
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            for email in &["dimashur@gmail.com", "locked@gmail.com"] {
+                let payload = json!({"email": email, "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/register")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            // Unknown emails leave no trace
+            let payload = json!({"email": "unknown@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(http::header::USER_AGENT, "curl/7.68.0")
+                .peer_addr("203.0.113.7:40000".parse().unwrap())
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(http::header::USER_AGENT, "Firefox")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            let payload = json!({"name": "cli", "scope": "read_only"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"current_password": "12345678", "new_password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The newest events come first
+            let request = test::TestRequest::get()
+                .uri("/api/account/activity")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(
+                get_kinds(&response_value),
+                vec![
+                    "password_change",
+                    "api_token_creation",
+                    "login_success",
+                    "login_failure"
+                ]
+            );
+            let events = &response_value["data"]["events"];
+            assert_eq!(events[2]["user_agent"], "Firefox");
+            assert_eq!(events[3]["user_agent"], "curl/7.68.0");
+            assert_eq!(events[3]["ip"], "203.0.113.7");
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/activity?offset=1&limit=1")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(get_kinds(&response_value), vec!["api_token_creation"]);
+
+            // The history can't be rewritten
+            let db_client = db_pool.get().await.expect("Can't get db client");
+            let result = db_client
+                .execute("UPDATE auth_event SET kind = 'login_success'", &[])
+                .await;
+            assert!(result.is_err());
+
+            // The history is served a page at a time, however long it gets
+            db_client
+                .execute(
+                    "
+                INSERT INTO auth_event (account_id, kind, creation_date)
+                SELECT id, 'login_failure', NOW() - INTERVAL '1 day' FROM account, generate_series(1, 120)
+                WHERE email = 'dimashur@gmail.com'",
+                    &[],
+                )
+                .await
+                .expect("Can't insert auth events");
+            for (uri, count) in &[("/api/account/activity", 50), ("/api/account/activity?limit=1000", 100)] {
+                let request = test::TestRequest::get()
+                    .uri(uri)
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(get_kinds(&response_value).len(), *count);
+            }
+
+            for uri in &["/api/account/activity?offset=-1", "/api/account/activity?limit=-1"] {
+                let request = test::TestRequest::get()
+                    .uri(uri)
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            }
+
+            let request = test::TestRequest::post()
+                .uri("/api/account/logout")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The lockout is recorded along with the failure which caused it
+            for _ in 0..2 {
+                let payload = json!({"email": "locked@gmail.com", "password": "87654321"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            }
+
+            let kinds: Vec<String> = db_client
+                .query(
+                    "SELECT kind FROM auth_event JOIN account ON account.id = auth_event.account_id \
+                     WHERE account.email = $1 ORDER BY auth_event.id",
+                    &[&"locked@gmail.com"],
+                )
+                .await
+                .expect("Can't get events")
+                .iter()
+                .map(|row| row.get("kind"))
+                .collect();
+            assert_eq!(kinds, vec!["login_failure", "login_failure", "lockout"]);
+
+            let kinds: Vec<String> = db_client
+                .query(
+                    "SELECT kind FROM auth_event JOIN account ON account.id = auth_event.account_id \
+                     WHERE account.email = $1 ORDER BY auth_event.id DESC LIMIT 1",
+                    &[&"dimashur@gmail.com"],
+                )
+                .await
+                .expect("Can't get events")
+                .iter()
+                .map(|row| row.get("kind"))
+                .collect();
+            assert_eq!(kinds, vec!["logout"]);
+        });
+    }
+}
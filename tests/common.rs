@@ -1,30 +1,210 @@
 use actix_http::{body::MessageBody, http::header::HeaderMap};
-use actix_web::{dev::ServiceResponse, test, web};
+use actix_web::{dev::ServiceResponse, http, middleware, test, web, Error};
+use deadpool::managed::{PoolConfig, Timeouts};
 use deadpool_postgres::{config::ConfigError, Config, Pool};
-use productivity::{account::account_controllers, middlewares, todos::todo_controllers};
+use productivity::mail::{file_mailer::FileMailer, mailer::Mailer};
+use productivity::sessions::{
+    session_memory_store::MemorySessionStore, session_postgres_store::PostgresSessionStore,
+    session_redis_store::RedisSessionStore, session_store::SessionStore,
+};
+use productivity::settings::{RedisSettings, SessionSettings, SessionStoreKind};
+use productivity::{
+    account::account_controllers, admin::admin_controllers, api_tokens::api_token_controllers,
+    auth_events::auth_event_controllers, invites::invite_controllers, jwt::jwt_controllers, middlewares,
+    oidc::oidc_controllers, todos::todo_controllers, two_factor::two_factor_controllers,
+};
 use redis;
 use redis::ConnectionLike;
 use regex::Regex;
 use serde_json::Value;
-use std::{sync::mpsc, thread, time::Duration};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
 use tokio_postgres::NoTls;
 
 #[cfg(test)]
 pub fn test_config_app(config: &mut web::ServiceConfig) {
     config
+        .service(
+            web::scope("/api/todos")
+                .wrap(middlewares::verification::Verification)
+                .wrap(middlewares::csrf::Csrf)
+                .wrap(middlewares::auth::Authentication)
+                .service(
+                    web::resource("")
+                        .route(web::get().to(todo_controllers::todo_get))
+                        .route(web::post().to(todo_controllers::todos_create)),
+                )
+                .service(
+                    web::resource("/{id}")
+                        .route(web::get().to(todo_controllers::todo_get_one))
+                        .route(web::patch().to(todo_controllers::todo_update))
+                        .route(web::delete().to(todo_controllers::todo_remove)),
+                ),
+        )
         .service(
             web::scope("/api/todo")
+                .wrap(middlewares::verification::Verification)
+                .wrap(middlewares::csrf::Csrf)
                 .wrap(middlewares::auth::Authentication)
+                .wrap(middleware::DefaultHeaders::new().header("Deprecation", "true"))
                 .route("/create", web::post().to(todo_controllers::todo_create))
                 .route("/get", web::get().to(todo_controllers::todo_get))
                 .route("/edit", web::post().to(todo_controllers::todo_edit))
                 .route("/delete", web::post().to(todo_controllers::todo_delete))
                 .route("/reset", web::post().to(todo_controllers::todo_reset)),
         )
+        .service(
+            web::scope("/api/admin")
+                .wrap(middlewares::role::Admin)
+                .wrap(middlewares::csrf::Csrf)
+                .wrap(middlewares::auth::Authentication)
+                .route("/accounts", web::get().to(admin_controllers::admin_accounts))
+                .route("/accounts/{id}", web::get().to(admin_controllers::admin_account))
+                .route(
+                    "/accounts/{id}/disable",
+                    web::post().to(admin_controllers::admin_account_disable),
+                )
+                .route(
+                    "/accounts/{id}/enable",
+                    web::post().to(admin_controllers::admin_account_enable),
+                )
+                .route(
+                    "/accounts/{id}/logout",
+                    web::post().to(admin_controllers::admin_account_logout),
+                ),
+        )
         .service(
             web::scope("/api/account")
+                .service(
+                    web::resource("")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::delete().to(account_controllers::account_delete)),
+                )
                 .route("/register", web::post().to(account_controllers::account_register))
                 .route("/login", web::post().to(account_controllers::account_login))
+                .route(
+                    "/login/2fa",
+                    web::post().to(account_controllers::account_login_two_factor),
+                )
+                .route("/login/link", web::post().to(account_controllers::account_login_link))
+                .route(
+                    "/login/link/{token}",
+                    web::get().to(account_controllers::account_login_link_use),
+                )
+                .route("/oidc/login", web::get().to(oidc_controllers::oidc_login))
+                .route("/oidc/callback", web::get().to(oidc_controllers::oidc_callback))
+                .route("/token/refresh", web::post().to(jwt_controllers::jwt_refresh))
+                .route("/token/revoke", web::post().to(jwt_controllers::jwt_revoke))
+                .service(
+                    web::resource("/logout")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::post().to(account_controllers::account_logout)),
+                )
+                .service(
+                    web::resource("/sessions")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::get().to(account_controllers::account_sessions)),
+                )
+                .service(
+                    web::resource("/email")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::post().to(account_controllers::account_email)),
+                )
+                .route(
+                    "/email/confirm",
+                    web::get().to(account_controllers::account_email_confirm),
+                )
+                .service(
+                    web::resource("/me")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::get().to(account_controllers::account_profile))
+                        .route(web::patch().to(account_controllers::account_profile_update)),
+                )
+                .service(
+                    web::resource("/activity")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::get().to(auth_event_controllers::auth_events_get)),
+                )
+                .service(
+                    web::resource("/sessions/{id}")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::delete().to(account_controllers::account_session_revoke)),
+                )
+                .service(
+                    web::resource("/password")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::post().to(account_controllers::account_password)),
+                )
+                .service(
+                    web::resource("/tokens")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::get().to(api_token_controllers::api_tokens_get))
+                        .route(web::post().to(api_token_controllers::api_token_create)),
+                )
+                .service(
+                    web::resource("/tokens/{id}")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::delete().to(api_token_controllers::api_token_revoke)),
+                )
+                .service(
+                    web::resource("/invites")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::get().to(invite_controllers::invites_get))
+                        .route(web::post().to(invite_controllers::invite_create)),
+                )
+                .service(
+                    web::resource("/invites/{id}")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::delete().to(invite_controllers::invite_revoke)),
+                )
+                .service(
+                    web::resource("/2fa")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::delete().to(two_factor_controllers::two_factor_disable)),
+                )
+                .service(
+                    web::resource("/2fa/enroll")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::post().to(two_factor_controllers::two_factor_enroll)),
+                )
+                .service(
+                    web::resource("/2fa/confirm")
+                        .wrap(middlewares::csrf::Csrf)
+                        .wrap(middlewares::auth::Authentication)
+                        .route(web::post().to(two_factor_controllers::two_factor_confirm)),
+                )
+                .route(
+                    "/password/forgot",
+                    web::post().to(account_controllers::account_password_forgot),
+                )
+                .route(
+                    "/password/reset",
+                    web::post().to(account_controllers::account_password_reset),
+                )
+                .route("/verify", web::get().to(account_controllers::account_verify))
+                .route(
+                    "/verify/resend",
+                    web::post().to(account_controllers::account_verification_resend),
+                )
                 .route("/reset", web::post().to(account_controllers::accounts_reset)),
         );
 }
@@ -48,17 +228,81 @@ pub fn create_db_pool() -> Result<Pool, ConfigError> {
     })
 }
 
-pub async fn create_redis_client() -> redis::RedisResult<redis::aio::Connection> {
+pub fn create_redis_pool(url: String, settings: &RedisSettings) -> redis::RedisResult<deadpool_redis::Pool> {
+    let timeout = settings.timeout.to_std().ok();
+
+    let cfg = deadpool_redis::Config {
+        url: Some(url),
+        pool: Some(PoolConfig {
+            max_size: settings.pool_size,
+            timeouts: Timeouts {
+                wait: timeout,
+                create: timeout,
+                recycle: timeout,
+            },
+        }),
+    };
+    cfg.create_pool()
+}
+
+pub fn get_redis_url() -> String {
     let host = std::env::var("REDIS_HOST").expect("REDIS_HOST variable missing");
     let port = std::env::var("REDIS_PORT").expect("REDIS_PORT variable missing");
-    let mut client = redis::Client::open(format!("redis://{}:{}", host, port))?;
+    let url = format!("redis://{}:{}", host, port);
+
+    let mut client = redis::Client::open(url.as_str()).expect("Can't create redis client");
     if !client.check_connection() {
         panic!("Can't connect to redis");
     }
 
-    let connection = client.get_async_connection().await;
+    url
+}
+
+// The store is picked the same way the server does it, so the tests can run against every backend
+#[allow(dead_code)]
+pub async fn create_session_store(db_pool: &Pool) -> Arc<dyn SessionStore> {
+    match SessionSettings::from_env().store {
+        SessionStoreKind::Redis => {
+            let settings = RedisSettings::default();
+            let redis_pool = create_redis_pool(get_redis_url(), &settings).expect("Can't create redis pool");
+            Arc::new(RedisSessionStore::new(redis_pool, settings.max_backoff))
+        }
+        SessionStoreKind::Postgres => Arc::new(PostgresSessionStore::new(Pool::clone(db_pool))),
+        SessionStoreKind::Memory => Arc::new(MemorySessionStore::new()),
+    }
+}
+
+fn get_mail_directory() -> PathBuf {
+    std::env::temp_dir().join("productivity_test_mails")
+}
+
+// The emails are written into a directory which is emptied for every test
+pub fn create_mailer() -> Arc<dyn Mailer> {
+    let directory = get_mail_directory();
+    if directory.exists() {
+        fs::remove_dir_all(&directory).expect("Can't clear the mail directory");
+    }
+
+    Arc::new(FileMailer::new(directory))
+}
 
-    connection
+// Returns the last email sent to the address
+#[allow(dead_code)]
+pub fn get_last_email(to: &str) -> Option<Value> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(get_mail_directory())
+        .ok()?
+        .map(|entry| entry.expect("Can't read the mail directory").path())
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .rev()
+        .map(|path| {
+            let content = fs::read(path).expect("Can't read email");
+            serde_json::from_slice::<Value>(&content).expect("Can't parse email")
+        })
+        .find(|email| email["to"] == to)
 }
 
 pub fn get_session_id(response_headers: &HeaderMap) -> &str {
@@ -76,6 +320,30 @@ pub fn get_session_id(response_headers: &HeaderMap) -> &str {
     session_id
 }
 
+// The state-changing requests of a session repeat this token in the `X-CSRF-Token` header
+#[allow(dead_code)]
+pub fn get_csrf_token(response_headers: &HeaderMap) -> &str {
+    let cookie_regex = Regex::new(r###"csrf_token=(.+?);"###).expect("Couldn't create cookie regex");
+
+    response_headers
+        .get_all(http::header::SET_COOKIE)
+        .filter_map(|val| cookie_regex.captures(val.to_str().expect("Can't parse cookie")))
+        .filter_map(|groups| Some(groups.get(1)?.as_str()))
+        .next()
+        .unwrap_or("")
+}
+
+#[allow(dead_code)]
+pub fn get_session_max_age(response_headers: &HeaderMap) -> Option<i64> {
+    let cookie_regex = Regex::new(r###"session_id=.+?; .*Max-Age=(\d+)"###).expect("Couldn't create cookie regex");
+
+    response_headers
+        .get_all(http::header::SET_COOKIE)
+        .filter_map(|val| cookie_regex.captures(val.to_str().expect("Can't parse cookie")))
+        .filter_map(|groups| groups.get(1)?.as_str().parse().ok())
+        .next()
+}
+
 #[allow(dead_code)]
 pub async fn get_response_body<B>(response: ServiceResponse<B>) -> Value
 where
@@ -85,6 +353,17 @@ where
     serde_json::from_slice(response_body.as_ref()).expect("Can't parse to serde Value")
 }
 
+// Errors returned by middlewares don't go through the response. the body is rendered out of the error itself
+#[allow(dead_code)]
+pub async fn get_error_body(error: &Error) -> Value {
+    let response = ServiceResponse::new(
+        test::TestRequest::default().to_http_request(),
+        error.as_response_error().error_response(),
+    );
+
+    get_response_body(response).await
+}
+
 fn panic_after<T, F>(d: Duration, message: &'static str, f: F) -> T
 where
     T: Send + 'static,
@@ -0,0 +1,194 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_service::Service;
+    use actix_web::{http, test, App};
+    use deadpool_postgres::Pool;
+    use productivity::settings::{CookieSameSite, CookieSettings, Settings};
+    use productivity::AppState;
+
+    fn get_set_cookie<'a>(headers: &'a http::HeaderMap, name: &str) -> Option<&'a str> {
+        headers
+            .get_all(http::header::SET_COOKIE)
+            .map(|value| value.to_str().expect("Can't parse cookie"))
+            .find(|cookie| cookie.starts_with(&format!("{}=", name)))
+    }
+
+    #[test]
+    fn test_csrf() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_csrf_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings {
+                            cookie: CookieSettings {
+                                secure: true,
+                                domain: Some("example.com".to_string()),
+                                same_site: CookieSameSite::Lax,
+                                ..CookieSettings::default()
+                            },
+                            ..Settings::default()
+                        },
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The login sets a csrf cookie next to the session cookie. only the csrf one is readable by the frontend
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+            assert!(!csrf_token.is_empty());
+
+            let session_cookie = get_set_cookie(response.headers(), "session_id").expect("Session cookie wasn't set");
+            let csrf_cookie = get_set_cookie(response.headers(), "csrf_token").expect("Csrf cookie wasn't set");
+            for cookie in &[session_cookie, csrf_cookie] {
+                assert!(cookie.contains("Path=/"));
+                assert!(cookie.contains("Domain=example.com"));
+                assert!(cookie.contains("Secure"));
+                assert!(cookie.contains("SameSite=Lax"));
+            }
+            assert!(session_cookie.contains("HttpOnly"));
+            assert!(!csrf_cookie.contains("HttpOnly"));
+
+            // A second session has another token
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let other_csrf_token = common::get_csrf_token(response.headers()).to_string();
+            assert_ne!(csrf_token, other_csrf_token);
+
+            // Without the header, with a wrong token and with the token of another session
+            let payload = json!({"title": "hello", "body": "world"});
+            for header in &[None, Some("1234"), Some(other_csrf_token.as_str())] {
+                let mut request = test::TestRequest::post()
+                    .uri("/api/todo/create")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .cookie(Cookie::new("csrf_token", csrf_token.clone()))
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string());
+                if let Some(header) = header {
+                    request = request.header("x-csrf-token", *header);
+                }
+                let error = app
+                    .call(request.to_request())
+                    .await
+                    .expect_err("Request wasn't refused");
+                assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+
+                let response_value = common::get_error_body(&error).await;
+                assert_eq!(response_value["meta"]["error"], "Invalid CSRF token");
+            }
+
+            // With the session's token
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Reads don't need it, and renew both cookies
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(get_set_cookie(response.headers(), "session_id").is_some());
+            assert_eq!(common::get_csrf_token(response.headers()), csrf_token);
+
+            // Api tokens don't use cookies, so they don't need it either
+            let payload = json!({"name": "cli", "scope": "read_write"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let api_token = response_value["data"]["token"]
+                .as_str()
+                .expect("Can't parse token")
+                .to_string();
+
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", api_token))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Logout is protected too, and removes both cookies
+            let request = test::TestRequest::post()
+                .uri("/api/account/logout")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            let request = test::TestRequest::post()
+                .uri("/api/account/logout")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            for name in &["session_id", "csrf_token"] {
+                let removed_cookie = get_set_cookie(response.headers(), name).expect("Cookie wasn't removed");
+                assert!(removed_cookie.contains("Max-Age=0"));
+                assert!(removed_cookie.contains("Domain=example.com"));
+            }
+        });
+    }
+}
@@ -0,0 +1,319 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_web::{http, test, App};
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use deadpool_postgres::Pool;
+    use productivity::settings::{AccountSettings, RegistrationMode, Settings};
+    use productivity::AppState;
+
+    fn registration_settings(registration_mode: RegistrationMode) -> Settings {
+        Settings {
+            account: AccountSettings {
+                registration_mode,
+                user_invites: false,
+                ..AccountSettings::default()
+            },
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn test_invites() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_invites_runtime".to_string()).block_on(async move {
+            let mut open_app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: registration_settings(RegistrationMode::Open),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: registration_settings(RegistrationMode::InviteOnly),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let mut closed_app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: registration_settings(RegistrationMode::Closed),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Closed
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut closed_app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Registration is closed");
+
+            // Open. an invite isn't needed
+            for email in &["admin@gmail.com", "dimashur@gmail.com"] {
+                let payload = json!({"email": email, "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/register")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut open_app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            let db_client = db_pool.get().await.expect("Can't get db client");
+            db_client
+                .execute(
+                    "UPDATE account SET role = 'admin' WHERE email = $1",
+                    &[&"admin@gmail.com"],
+                )
+                .await
+                .expect("Can't promote account");
+
+            // Invite only. the accounts which aren't admins can't invite people here
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            let request = test::TestRequest::post()
+                .uri("/api/account/invites")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(json!({}).to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let payload = json!({"email": "admin@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            let expired_date = Utc::now() - Duration::hours(1);
+            for payload in &[json!({"max_uses": 0}), json!({"expiration_date": expired_date})] {
+                let request = test::TestRequest::post()
+                    .uri("/api/account/invites")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            }
+
+            let mut codes = vec![];
+            for payload in &[json!({}), json!({"max_uses": 2})] {
+                let request = test::TestRequest::post()
+                    .uri("/api/account/invites")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["data"]["uses"], 0);
+                codes.push(
+                    response_value["data"]["code"]
+                        .as_str()
+                        .expect("Can't parse code")
+                        .to_string(),
+                );
+            }
+
+            // Missing and unknown invites
+            for payload in &[
+                json!({"email": "new@gmail.com", "password": "12345678"}),
+                json!({"email": "new@gmail.com", "password": "12345678", "invite": "unknown"}),
+            ] {
+                let request = test::TestRequest::post()
+                    .uri("/api/account/register")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["meta"]["error"], "Missing, expired or used up invite");
+            }
+
+            // A failed registration doesn't use the invite up
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "invite": codes[0]});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+
+            let payload = json!({"email": "new@gmail.com", "password": "12345678", "invite": codes[0]});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "other@gmail.com", "password": "12345678", "invite": codes[0]});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let payload = json!({"email": "other@gmail.com", "password": "12345678", "invite": codes[1]});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/invites")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let invites = response_value["data"]["invites"]
+                .as_array()
+                .expect("Can't parse invites");
+            assert_eq!(invites.len(), 2);
+            assert_eq!(invites[0]["max_uses"], 2);
+            assert_eq!(invites[0]["uses"], 1);
+            assert_eq!(invites[1]["max_uses"], 1);
+            assert_eq!(invites[1]["uses"], 1);
+            assert!(invites[0].get("code").is_none());
+
+            // The invites are only managed from a session, not with a token
+            let invite_id = invites[0]["id"].as_i64().expect("Can't parse id");
+            let payload = json!({"name": "cli", "scope": "read_write"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/tokens")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            let api_token = response_value["data"]["token"]
+                .as_str()
+                .expect("Can't parse token")
+                .to_string();
+
+            let request = test::TestRequest::post()
+                .uri("/api/account/invites")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", api_token))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(json!({}).to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Invites can't be managed with a token");
+
+            let request = test::TestRequest::delete()
+                .uri(&format!("/api/account/invites/{}", invite_id))
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", api_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // A revoked invite can't be used anymore
+            let request = test::TestRequest::delete()
+                .uri(&format!("/api/account/invites/{}", invite_id))
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::delete()
+                .uri(&format!("/api/account/invites/{}", invite_id))
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let payload = json!({"email": "third@gmail.com", "password": "12345678", "invite": codes[1]});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // The accounts which registered with it are kept
+            let payload = json!({"email": "other@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+}
@@ -0,0 +1,300 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_service::Service;
+    use actix_web::{http, test, App};
+    use chrono::Duration;
+    use deadpool_postgres::Pool;
+    use productivity::settings::{JwtSettings, Settings};
+    use productivity::AppState;
+    use serde_json::Value;
+
+    const SECRET: &str = "0f1e2d3c4b5a69788796a5b4c3d2e1f0";
+
+    fn jwt_settings(access_token_timeout: i64) -> Settings {
+        Settings {
+            jwt: JwtSettings {
+                secret: Some(SECRET.to_string()),
+                access_token_timeout: Duration::seconds(access_token_timeout),
+                ..JwtSettings::default()
+            },
+            ..Settings::default()
+        }
+    }
+
+    fn refresh_request(uri: &str, refresh_token: &str) -> actix_http::Request {
+        let payload = json!({ "refresh_token": refresh_token });
+        test::TestRequest::post()
+            .uri(uri)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .set_payload(payload.to_string())
+            .to_request()
+    }
+
+    fn tokens(response_value: &Value) -> (String, String) {
+        let data = &response_value["data"];
+        assert_eq!(data["token_type"], "Bearer");
+        (
+            data["access_token"].as_str().expect("No access token").to_string(),
+            data["refresh_token"].as_str().expect("No refresh token").to_string(),
+        )
+    }
+
+    #[test]
+    fn test_jwt() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_jwt_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: db_pool.clone(),
+                        session_store: session_store.clone(),
+                        mailer: common::create_mailer(),
+                        settings: jwt_settings(900),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Logins with tokens don't get a cookie
+            let payload =
+                json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "phone", "mode": "token"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["expires_in"], 900);
+            let (access_token, refresh_token) = tokens(&response_value);
+
+            // The access token is enough to read and write, without a csrf token
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", access_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", access_token))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // A token with a changed account isn't accepted
+            let parts: Vec<&str> = access_token.split('.').collect();
+            let claims = base64::encode_config(
+                r#"{"sub":"1","sid":"a","iat":0,"exp":9999999999}"#,
+                base64::URL_SAFE_NO_PAD,
+            );
+            let tampered_token = format!("{}.{}.{}", parts[0], claims, parts[2]);
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", tampered_token))
+                .to_request();
+            let error = app.call(request).await.expect_err("Tampered token was accepted");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+
+            // The refresh token gives new tokens, once
+            let request = refresh_request("/api/account/token/refresh", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let (next_access_token, next_refresh_token) = tokens(&common::get_response_body(response).await);
+            assert_ne!(next_refresh_token, refresh_token);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", next_access_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Using the old refresh token again revokes the whole login, the latest refresh token included
+            let request = refresh_request("/api/account/token/refresh", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let request = refresh_request("/api/account/token/refresh", &next_refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let request = refresh_request("/api/account/token/refresh", "unknown");
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/activity")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", next_access_token))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["events"][0]["kind"], "refresh_token_reuse");
+
+            // A revoked login can't be refreshed
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "mode": "token"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let (_access_token, refresh_token) = tokens(&common::get_response_body(response).await);
+
+            let request = refresh_request("/api/account/token/revoke", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = refresh_request("/api/account/token/refresh", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let request = refresh_request("/api/account/token/revoke", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // Changing the password ends the logins with tokens
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "mode": "token"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let (access_token, refresh_token) = tokens(&common::get_response_body(response).await);
+
+            let payload = json!({"current_password": "12345678", "new_password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/password")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", access_token))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = refresh_request("/api/account/token/refresh", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // The cookie login still works as before
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let response_value = common::get_response_body(response).await;
+            assert!(response_value["data"]["access_token"].is_null());
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The access tokens expire
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: db_pool.clone(),
+                        session_store: session_store.clone(),
+                        mailer: common::create_mailer(),
+                        settings: jwt_settings(0),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321", "mode": "token"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let (access_token, _refresh_token) = tokens(&common::get_response_body(response).await);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", access_token))
+                .to_request();
+            let error = app.call(request).await.expect_err("Expired token was accepted");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+            // Without a secret, the logins with tokens are disabled
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "87654321", "mode": "token"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let request = refresh_request("/api/account/token/refresh", &refresh_token);
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .header(http::header::AUTHORIZATION, format!("Bearer {}", access_token))
+                .to_request();
+            let error = app
+                .call(request)
+                .await
+                .expect_err("Token was accepted without a secret");
+            assert_eq!(error.as_response_error().status_code(), StatusCode::FORBIDDEN);
+        });
+    }
+}
@@ -0,0 +1,490 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_http::Request;
+    use actix_service::Service;
+    use actix_web::dev::ServiceResponse;
+    use actix_web::{http, test, web, App, Error, HttpRequest, HttpResponse};
+    use deadpool_postgres::Pool;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::sign::Signer;
+    use productivity::settings::{AccountSettings, OidcSettings, RegistrationMode, Settings};
+    use productivity::AppState;
+    use reqwest::Url;
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const CLIENT_ID: &str = "productivity";
+
+    // What the provider gives back for a code, once the browser logged in
+    struct MockGrant {
+        code_challenge: String,
+        claims: Value,
+    }
+
+    // A minimal OpenID Connect provider. the test plays the browser: it reads the authorization request the api
+    // redirects to and registers the code the provider would send the browser back with
+    #[derive(Clone)]
+    struct MockProvider {
+        private_key: Arc<PKey<Private>>,
+        grants: Arc<Mutex<HashMap<String, MockGrant>>>,
+    }
+
+    fn encode(bytes: &[u8]) -> String {
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    // The issuer is the address the provider is reached at
+    fn mock_issuer(request: &HttpRequest) -> String {
+        format!("http://{}", request.connection_info().host())
+    }
+
+    async fn mock_discovery(request: HttpRequest) -> HttpResponse {
+        let issuer = mock_issuer(&request);
+
+        HttpResponse::Ok().json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+        }))
+    }
+
+    async fn mock_keys(provider: web::Data<MockProvider>) -> HttpResponse {
+        let rsa = provider.private_key.rsa().expect("Can't get the rsa key");
+
+        HttpResponse::Ok().json(json!({"keys": [{
+            "kty": "RSA",
+            "kid": "mock-key",
+            "use": "sig",
+            "alg": "RS256",
+            "n": encode(&rsa.n().to_vec()),
+            "e": encode(&rsa.e().to_vec()),
+        }]}))
+    }
+
+    // Checks the code verifier of PKCE before signing the id token
+    async fn mock_token(
+        request: HttpRequest,
+        form: web::Form<HashMap<String, String>>,
+        provider: web::Data<MockProvider>,
+    ) -> HttpResponse {
+        let grant = match form.get("code") {
+            Some(code) => provider.grants.lock().expect("Can't lock the grants").remove(code),
+            None => None,
+        };
+        let grant = match grant {
+            Some(grant) => grant,
+            None => return HttpResponse::BadRequest().json(json!({"error": "invalid_grant"})),
+        };
+        let code_verifier = form.get("code_verifier").map(String::as_str).unwrap_or("");
+        if encode(&Sha256::digest(code_verifier.as_bytes())) != grant.code_challenge
+            || form.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+        {
+            return HttpResponse::BadRequest().json(json!({"error": "invalid_grant"}));
+        }
+
+        let mut claims = grant.claims;
+        claims["iss"] = json!(mock_issuer(&request));
+        let message = format!(
+            "{}.{}",
+            encode(
+                json!({"alg": "RS256", "typ": "JWT", "kid": "mock-key"})
+                    .to_string()
+                    .as_bytes()
+            ),
+            encode(claims.to_string().as_bytes())
+        );
+        let mut signer = Signer::new(MessageDigest::sha256(), &provider.private_key).expect("Can't create signer");
+        signer.update(message.as_bytes()).expect("Can't sign");
+        let signature = signer.sign_to_vec().expect("Can't sign");
+
+        HttpResponse::Ok().json(json!({
+            "access_token": "mock-access-token",
+            "token_type": "Bearer",
+            "id_token": format!("{}.{}", message, encode(&signature)),
+        }))
+    }
+
+    // Starts a login, returns the state cookie along with the query of the authorization request
+    async fn start_login<S, B>(app: &mut S) -> (String, HashMap<String, String>)
+    where
+        S: Service<Request = Request, Response = ServiceResponse<B>, Error = Error>,
+    {
+        let request = test::TestRequest::get()
+            .uri("/api/account/oidc/login?device=laptop")
+            .to_request();
+        let response = test::call_service(app, request).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+
+        let state_cookie = response
+            .headers()
+            .get_all(http::header::SET_COOKIE)
+            .map(|value| value.to_str().expect("Can't parse cookie").to_string())
+            .find(|value| value.starts_with("oidc_state="))
+            .expect("Missing state cookie");
+        assert!(state_cookie.contains("SameSite=Lax"));
+        assert!(state_cookie.contains("HttpOnly"));
+        let state_value = state_cookie["oidc_state=".len()..]
+            .split(';')
+            .next()
+            .expect("Can't parse state cookie")
+            .to_string();
+
+        let location = response
+            .headers()
+            .get(http::header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .expect("Missing location");
+        let url = Url::parse(location).expect("Can't parse location");
+        assert_eq!(url.path(), "/authorize");
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        (state_value, query)
+    }
+
+    // The browser logs in at the provider, which sends it back to the callback
+    async fn finish_login<S, B>(
+        app: &mut S,
+        provider: &MockProvider,
+        state_value: &str,
+        query: &HashMap<String, String>,
+        claims: Value,
+    ) -> ServiceResponse<B>
+    where
+        S: Service<Request = Request, Response = ServiceResponse<B>, Error = Error>,
+    {
+        let code = uuid::Uuid::new_v4().to_simple().to_string();
+        provider.grants.lock().expect("Can't lock the grants").insert(
+            code.clone(),
+            MockGrant {
+                code_challenge: query["code_challenge"].clone(),
+                claims,
+            },
+        );
+
+        let request = test::TestRequest::get()
+            .uri(&format!(
+                "/api/account/oidc/callback?code={}&state={}",
+                code, query["state"]
+            ))
+            .cookie(Cookie::new("oidc_state", state_value.to_string()))
+            .to_request();
+        test::call_service(app, request).await
+    }
+
+    fn claims(subject: &str, email: &str, email_verified: bool, nonce: &str) -> Value {
+        let current_date = chrono::Utc::now().timestamp();
+
+        json!({
+            "sub": subject,
+            "aud": CLIENT_ID,
+            "exp": current_date + 300,
+            "iat": current_date,
+            "nonce": nonce,
+            "email": email,
+            "email_verified": email_verified,
+        })
+    }
+
+    #[test]
+    fn test_oidc_login() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        let provider = MockProvider {
+            private_key: Arc::new(PKey::from_rsa(Rsa::generate(2048).expect("Can't create key")).expect("Bad key")),
+            grants: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let server_provider = provider.clone();
+        let server = test::start(move || {
+            App::new()
+                .data(server_provider.clone())
+                .route("/.well-known/openid-configuration", web::get().to(mock_discovery))
+                .route("/jwks", web::get().to(mock_keys))
+                .route("/token", web::post().to(mock_token))
+        });
+        let issuer = format!("http://{}", server.addr());
+
+        actix_rt::System::new("test_oidc_login_runtime".to_string()).block_on(async move {
+            let mut disabled_app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let oidc_settings = OidcSettings {
+                issuer: Some(issuer.clone()),
+                client_id: CLIENT_ID.to_string(),
+                ..OidcSettings::default()
+            };
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: Settings {
+                            oidc: oidc_settings.clone(),
+                            ..Settings::default()
+                        },
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+            let mut closed_app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store: common::create_session_store(&db_pool).await,
+                        mailer: common::create_mailer(),
+                        settings: Settings {
+                            oidc: oidc_settings.clone(),
+                            account: AccountSettings {
+                                registration_mode: RegistrationMode::Closed,
+                                ..AccountSettings::default()
+                            },
+                            ..Settings::default()
+                        },
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Without an issuer there is nothing to login with
+            let request = test::TestRequest::get().uri("/api/account/oidc/login").to_request();
+            let response = test::call_service(&mut disabled_app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            let account_id = response_value["data"]["account_id"]
+                .as_i64()
+                .expect("Can't parse account_id");
+
+            // The authorization request
+            let (state_value, query) = start_login(&mut app).await;
+            assert_eq!(query["response_type"], "code");
+            assert_eq!(query["client_id"], CLIENT_ID);
+            assert_eq!(query["redirect_uri"], oidc_settings.redirect_url);
+            assert_eq!(query["scope"], "openid email");
+            assert_eq!(query["code_challenge_method"], "S256");
+            assert_eq!(query["state"], state_value);
+            assert!(!query["nonce"].is_empty());
+
+            // The callback is refused in another browser
+            let request = test::TestRequest::get()
+                .uri(&format!("/api/account/oidc/callback?code=123&state={}", query["state"]))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // The first login links the identity to the account using the same email
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-1", "dimashur@gmail.com", true, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            assert!(!session_id.is_empty());
+            assert!(response
+                .headers()
+                .get_all(http::header::SET_COOKIE)
+                .any(|value| value.as_bytes().starts_with(b"oidc_state=;")));
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["account_id"], account_id);
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/activity")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            let events = response_value["data"]["events"].as_array().expect("Can't parse events");
+            assert_eq!(events[0]["kind"], "login_success");
+            assert_eq!(events[1]["kind"], "identity_link");
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            let sessions = response_value["data"]["sessions"]
+                .as_array()
+                .expect("Can't parse sessions");
+            assert!(sessions.iter().any(|session| session["device"] == "laptop"));
+
+            // The state can't be used twice
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-1", "dimashur@gmail.com", true, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // The identity is found by its subject afterwards, even if its email changed
+            let (state_value, query) = start_login(&mut app).await;
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-1", "other@gmail.com", false, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["account_id"], account_id);
+
+            // A token made for another login
+            let (state_value, query) = start_login(&mut app).await;
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-1", "dimashur@gmail.com", true, "another-nonce"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Invalid identity token");
+
+            // A code stolen from another login doesn't come with the right code verifier
+            let (state_value, query) = start_login(&mut app).await;
+            let mut stolen_query = query.clone();
+            stolen_query.insert("code_challenge".to_string(), "stolen".to_string());
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &stolen_query,
+                claims("subject-1", "dimashur@gmail.com", true, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+            // The provider refused the login
+            let (state_value, query) = start_login(&mut app).await;
+            let request = test::TestRequest::get()
+                .uri(&format!(
+                    "/api/account/oidc/callback?error=access_denied&state={}",
+                    query["state"]
+                ))
+                .cookie(Cookie::new("oidc_state", state_value.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            // An unverified email isn't linked to anything
+            let (state_value, query) = start_login(&mut app).await;
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-2", "dimashur@gmail.com", false, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // Unknown emails get an account, unless the registrations are closed
+            let (state_value, query) = start_login(&mut closed_app).await;
+            let response = finish_login(
+                &mut closed_app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-2", "new@gmail.com", true, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "No account uses this email");
+
+            let (state_value, query) = start_login(&mut app).await;
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-2", "new@gmail.com", true, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            let new_account_id = response_value["data"]["account_id"]
+                .as_i64()
+                .expect("Can't parse account_id");
+            assert_ne!(new_account_id, account_id);
+
+            // Disabled accounts are refused like with a password
+            let db_client = db_pool.get().await.expect("Can't get db client");
+            db_client
+                .execute(
+                    "UPDATE account SET disabled = TRUE WHERE id = $1",
+                    &[&(new_account_id as i32)],
+                )
+                .await
+                .expect("Can't disable account");
+
+            let (state_value, query) = start_login(&mut app).await;
+            let response = finish_login(
+                &mut app,
+                &provider,
+                &state_value,
+                &query,
+                claims("subject-2", "new@gmail.com", true, &query["nonce"]),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Account disabled");
+        });
+    }
+}
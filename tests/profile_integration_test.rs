@@ -0,0 +1,257 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_web::{http, test, App};
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use deadpool_postgres::Pool;
+    use productivity::settings::Settings;
+    use productivity::AppState;
+    use serde_json::Value;
+
+    fn get_titles(response_value: &Value) -> Vec<&str> {
+        response_value["data"]["todos"]
+            .as_array()
+            .expect("Can't parse todos")
+            .iter()
+            .map(|todo| todo["title"].as_str().expect("Can't parse title"))
+            .collect()
+    }
+
+    #[test]
+    fn test_profile() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_profile_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            // Defaults
+            let request = test::TestRequest::get()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["email"], "dimashur@gmail.com");
+            assert_eq!(response_value["data"]["display_name"], Value::Null);
+            assert_eq!(response_value["data"]["timezone"], "UTC");
+            assert_eq!(response_value["data"]["locale"], "en");
+            assert_eq!(response_value["data"]["week_start"], "monday");
+            assert_eq!(response_value["data"]["todo_sort"], "last_edit_date");
+
+            // Invalid fields
+            let long_name = "a".repeat(51);
+            for payload in &[
+                json!({"timezone": "Mars/Olympus_Mons"}),
+                json!({"locale": "en_US"}),
+                json!({"display_name": long_name}),
+            ] {
+                let request = test::TestRequest::patch()
+                    .uri("/api/account/me")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            }
+
+            let payload = json!({"week_start": "friday"});
+            let request = test::TestRequest::patch()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            // Kiritimati is 14 hours ahead of UTC, all year long
+            let payload = json!({
+                "display_name": " Dima ",
+                "timezone": "Pacific/Kiritimati",
+                "locale": "ru",
+                "week_start": "sunday",
+                "todo_sort": "title",
+            });
+            let request = test::TestRequest::patch()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["display_name"], "Dima");
+            assert_eq!(response_value["data"]["timezone"], "Pacific/Kiritimati");
+
+            // Only the given fields change
+            let payload = json!({"locale": "pt-BR"});
+            let request = test::TestRequest::patch()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["display_name"], "Dima");
+            assert_eq!(response_value["data"]["timezone"], "Pacific/Kiritimati");
+            assert_eq!(response_value["data"]["locale"], "pt-BR");
+            assert_eq!(response_value["data"]["week_start"], "sunday");
+            assert_eq!(response_value["data"]["todo_sort"], "title");
+
+            // Today in Kiritimati. the overdue todo is due early enough to be overdue anywhere
+            let today = (Utc::now() + Duration::hours(14)).naive_utc().date();
+            for payload in &[
+                json!({"title": "b", "due_date": today}),
+                json!({"title": "a", "due_date": today - Duration::days(3)}),
+                json!({"title": "c"}),
+            ] {
+                let request = test::TestRequest::post()
+                    .uri("/api/todo/create")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+
+            // The account's sort is the default one
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(get_titles(&response_value), vec!["a", "b", "c"]);
+            assert_eq!(response_value["data"]["todos"][1]["due_date"], json!(today));
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get?sort=last_edit_date")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(get_titles(&response_value), vec!["c", "a", "b"]);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get?due=today")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(get_titles(&response_value), vec!["b"]);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get?due=overdue")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(get_titles(&response_value), vec!["a"]);
+
+            // Pago Pago is 11 hours behind UTC, so it's always another day there
+            let payload = json!({"timezone": "Pacific/Pago_Pago", "todo_sort": "due_date"});
+            let request = test::TestRequest::patch()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get?due=today")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert!(get_titles(&response_value).is_empty());
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(get_titles(&response_value), vec!["a", "b", "c"]);
+
+            // An empty display name removes it
+            let payload = json!({"display_name": ""});
+            let request = test::TestRequest::patch()
+                .uri("/api/account/me")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["display_name"], Value::Null);
+        });
+    }
+}
@@ -12,10 +12,9 @@ mod tests {
     use actix_service::Service;
     use actix_web::{http, test, App};
     use deadpool_postgres::Pool;
+    use productivity::settings::Settings;
     use productivity::AppState;
     use serde_json::{self, Value};
-    use std::sync::Arc;
-    use tokio::sync::Mutex;
 
     #[test]
     fn test_todos_create() {
@@ -23,14 +22,16 @@ mod tests {
         let db_pool = Pool::clone(&db_pool);
 
         actix_rt::System::new("test_todos_system".to_string()).block_on(async move {
-            let redis_client = common::create_redis_client()
-                .await
-                .expect("Can't create redis connection");
-            let redis_client = Arc::new(Mutex::new(redis_client));
+            let session_store = common::create_session_store(&db_pool).await;
 
             let mut app = test::init_service(
                 App::new()
-                    .data(AppState { db_pool, redis_client })
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
                     .configure(common::test_config_app),
             )
             .await;
@@ -59,6 +60,7 @@ mod tests {
                 .to_request();
             let response = test::call_service(&mut app, request).await;
             let session_id = common::get_session_id(&response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
             let response_body = test::read_body(response).await;
             let response_body: Value =
                 serde_json::from_slice(response_body.as_ref()).expect("Can't parse to serde Value");
@@ -70,7 +72,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/reset")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -91,12 +93,55 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/create")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // The account is taken from the session. a forged account_id cookie is ignored
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/create")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .cookie(Cookie::new("account_id", (account_id + 1).to_string()))
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
             assert_eq!(response.status(), StatusCode::OK);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let todos = response_value["data"]["todos"]
+                .as_array()
+                .expect("Can't parse get todos response");
+            assert_eq!(todos.len(), 2);
+            for todo in todos {
+                assert_eq!(todo["account_id"].as_u64(), Some(account_id));
+            }
+
+            // The title must fit its column
+            for title in &["", "   ", &"a".repeat(51)] {
+                let payload = json!({ "title": title });
+                let request = test::TestRequest::post()
+                    .uri("/api/todo/create")
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .set_payload(payload.to_string())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            }
         });
     }
 
@@ -106,14 +151,16 @@ mod tests {
         let db_pool = Pool::clone(&db_pool);
 
         actix_rt::System::new("test_todos_system".to_string()).block_on(async move {
-            let redis_client = common::create_redis_client()
-                .await
-                .expect("Can't create redis connection");
-            let redis_client = Arc::new(Mutex::new(redis_client));
+            let session_store = common::create_session_store(&db_pool).await;
 
             let mut app = test::init_service(
                 App::new()
-                    .data(AppState { db_pool, redis_client })
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
                     .configure(common::test_config_app),
             )
             .await;
@@ -142,16 +189,13 @@ mod tests {
                 .to_request();
             let response = test::call_service(&mut app, request).await;
             let session_id = common::get_session_id(&response.headers()).to_string();
-            let response_value = common::get_response_body(response).await;
-            let account_id = response_value["data"]["account_id"]
-                .as_u64()
-                .expect("Can't parse account_id");
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
 
             // Delete all existing data. USED IN TESTS ONLY
             let request = test::TestRequest::post()
                 .uri("/api/todo/reset")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -162,7 +206,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/create")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
@@ -173,7 +217,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/create")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
@@ -184,7 +228,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/create")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
@@ -195,7 +239,6 @@ mod tests {
             let request = test::TestRequest::get()
                 .uri("/api/todo/get")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -211,7 +254,6 @@ mod tests {
             let request = test::TestRequest::get()
                 .uri("/api/todo/get?limit=2")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -227,7 +269,6 @@ mod tests {
             let request = test::TestRequest::get()
                 .uri("/api/todo/get?limit=2&offset=1")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -247,14 +288,16 @@ mod tests {
         let db_pool = Pool::clone(&db_pool);
 
         actix_rt::System::new("test_todos_system".to_string()).block_on(async move {
-            let redis_client = common::create_redis_client()
-                .await
-                .expect("Can't create redis connection");
-            let redis_client = Arc::new(Mutex::new(redis_client));
+            let session_store = common::create_session_store(&db_pool).await;
 
             let mut app = test::init_service(
                 App::new()
-                    .data(AppState { db_pool, redis_client })
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
                     .configure(common::test_config_app),
             )
             .await;
@@ -283,16 +326,13 @@ mod tests {
                 .to_request();
             let response = test::call_service(&mut app, request).await;
             let session_id = common::get_session_id(&response.headers()).to_string();
-            let response_value = common::get_response_body(response).await;
-            let account_id = response_value["data"]["account_id"]
-                .as_u64()
-                .expect("Can't parse account_id");
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
 
             // Delete all existing data. USED IN TESTS ONLY
             let request = test::TestRequest::post()
                 .uri("/api/todo/reset")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -303,7 +343,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/create")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
@@ -314,7 +354,6 @@ mod tests {
             let request = test::TestRequest::get()
                 .uri("/api/todo/get")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -330,7 +369,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/edit")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
@@ -349,7 +388,6 @@ mod tests {
             let request = test::TestRequest::get()
                 .uri("/api/todo/get")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -365,6 +403,29 @@ mod tests {
             assert_eq!(edited_todo_title, "edited_title");
             assert_eq!(edited_todo_body, "edited_body");
             assert_eq!(edited_todo_last_edit_date, last_edit_date);
+
+            // Unknown todos can't be edited
+            let payload = json!({"id": todo_id + 1000, "title": "edited_title"});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/edit")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let payload = json!({"id": todo_id, "title": ""});
+            let request = test::TestRequest::post()
+                .uri("/api/todo/edit")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
         });
     }
 
@@ -374,14 +435,16 @@ mod tests {
         let db_pool = Pool::clone(&db_pool);
 
         actix_rt::System::new("test_todos_system".to_string()).block_on(async move {
-            let redis_client = common::create_redis_client()
-                .await
-                .expect("Can't create redis connection");
-            let redis_client = Arc::new(Mutex::new(redis_client));
+            let session_store = common::create_session_store(&db_pool).await;
 
             let mut app = test::init_service(
                 App::new()
-                    .data(AppState { db_pool, redis_client })
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
                     .configure(common::test_config_app),
             )
             .await;
@@ -410,16 +473,13 @@ mod tests {
                 .to_request();
             let response = test::call_service(&mut app, request).await;
             let session_id = common::get_session_id(&response.headers()).to_string();
-            let response_value = common::get_response_body(response).await;
-            let account_id = response_value["data"]["account_id"]
-                .as_u64()
-                .expect("Can't parse account_id");
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
 
             // Delete all existing data. USED IN TESTS ONLY
             let request = test::TestRequest::post()
                 .uri("/api/todo/reset")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -430,7 +490,7 @@ mod tests {
             let request = test::TestRequest::post()
                 .uri("/api/todo/create")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
@@ -441,7 +501,6 @@ mod tests {
             let request = test::TestRequest::get()
                 .uri("/api/todo/get")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -451,23 +510,38 @@ mod tests {
             let todo = response_value["data"]["todos"].as_array().unwrap().get(0).unwrap();
             let todo_id = todo["id"].as_i64().unwrap();
 
-            // Delete one of the todos
-            let payload = json!({ "todos": vec![todo_id] });
+            // Delete one of the todos, along with one which doesn't exist
+            let payload = json!({ "todos": vec![todo_id, todo_id + 1000] });
             let request = test::TestRequest::post()
                 .uri("/api/todo/delete")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
+                .header("x-csrf-token", csrf_token.clone())
                 .set_payload(payload.to_string())
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
             assert_eq!(response.status(), StatusCode::OK);
 
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["todos"], json!([todo_id]));
+            assert_eq!(response_value["data"]["not_found"], json!([todo_id + 1000]));
+
+            // Nothing to delete
+            let payload = json!({ "todos": [] });
+            let request = test::TestRequest::post()
+                .uri("/api/todo/delete")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
             // Get all todos after the delete. should be empty
             let request = test::TestRequest::get()
                 .uri("/api/todo/get")
                 .cookie(Cookie::new("session_id", session_id.clone()))
-                .cookie(Cookie::new("account_id", account_id.to_string()))
                 .header(http::header::CONTENT_TYPE, "application/json")
                 .to_request();
             let response = test::call_service(&mut app, request).await;
@@ -478,4 +552,261 @@ mod tests {
             assert_eq!(todo.len(), 0);
         });
     }
+
+    #[test]
+    fn test_todo_resources() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_todos_system".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool: Pool::clone(&db_pool),
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings: Settings::default(),
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing account data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Two accounts, the second one mustn't see the todos of the first one
+            let mut sessions = Vec::new();
+            for email in &["dimashur@gmail.com", "someone@gmail.com"] {
+                let payload = json!({"email": email, "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/register")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                sessions.push((
+                    common::get_session_id(response.headers()).to_string(),
+                    common::get_csrf_token(response.headers()).to_string(),
+                ));
+            }
+            let (session_id, csrf_token) = sessions[0].clone();
+            let (other_session_id, other_csrf_token) = sessions[1].clone();
+
+            // Creation tells where the todo is
+            let payload = json!({"title": "hello", "body": "world"});
+            let request = test::TestRequest::post()
+                .uri("/api/todos")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let location = response
+                .headers()
+                .get(http::header::LOCATION)
+                .expect("No location")
+                .to_str()
+                .unwrap()
+                .to_string();
+            let response_value = common::get_response_body(response).await;
+            let todo_id = response_value["data"]["id"].as_i64().unwrap();
+            assert_eq!(location, format!("/api/todos/{}", todo_id));
+
+            let request = test::TestRequest::get()
+                .uri("/api/todos")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["todos"].as_array().unwrap().len(), 1);
+
+            let request = test::TestRequest::get()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["id"], todo_id);
+            assert_eq!(response_value["data"]["title"], "hello");
+            assert_eq!(response_value["data"]["done"], false);
+
+            // Partial edit, the other fields are kept
+            let payload = json!({"done": true});
+            let request = test::TestRequest::patch()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["title"], "hello");
+            assert_eq!(response_value["data"]["body"], "world");
+            assert_eq!(response_value["data"]["done"], true);
+
+            // The due date is cleared with a null, and kept when it's missing
+            for (payload, due_date) in &[
+                (json!({"due_date": "2020-05-01"}), json!("2020-05-01")),
+                (json!({"title": "hello"}), json!("2020-05-01")),
+                (json!({"due_date": null}), json!(null)),
+            ] {
+                let request = test::TestRequest::patch()
+                    .uri(&location)
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .header("x-csrf-token", csrf_token.clone())
+                    .set_payload(payload.to_string())
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(&response_value["data"]["due_date"], due_date);
+            }
+
+            let payload = json!({"title": "a".repeat(51)});
+            let request = test::TestRequest::patch()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .set_payload(payload.to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(
+                response_value["meta"]["error"],
+                "The title must be at most 50 characters long"
+            );
+
+            // The todos of other accounts are missing to them
+            let request = test::TestRequest::get()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", other_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let request = test::TestRequest::patch()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", other_session_id.clone()))
+                .header("x-csrf-token", other_csrf_token.clone())
+                .set_payload(json!({"title": "mine"}).to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let request = test::TestRequest::delete()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", other_session_id.clone()))
+                .header("x-csrf-token", other_csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let request = test::TestRequest::get()
+                .uri("/api/todos/not_an_id")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // Deletion
+            let request = test::TestRequest::delete()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            let request = test::TestRequest::get()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            let request = test::TestRequest::delete()
+                .uri(&location)
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // The old routes still work, but are marked as deprecated
+            let request = test::TestRequest::get()
+                .uri("/api/todo/get")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get("deprecation").unwrap(), "true");
+
+            // The new ones need the csrf token too
+            let request = test::TestRequest::post()
+                .uri("/api/todos")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .set_payload(json!({"title": "hello"}).to_string())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .to_request();
+            let response = app.call(request).await;
+            assert!(response.is_err());
+
+            // The list is served a page at a time
+            let db_client = db_pool.get().await.expect("Can't get db client");
+            db_client
+                .execute(
+                    "
+                INSERT INTO todo (account_id, title, creation_date, last_edit_date)
+                SELECT id, 'bulk', NOW(), NOW() FROM account, generate_series(1, 120)
+                WHERE email = 'dimashur@gmail.com'",
+                    &[],
+                )
+                .await
+                .expect("Can't insert todos");
+            for (uri, count) in &[("/api/todos", 50), ("/api/todos?limit=1000", 100)] {
+                let request = test::TestRequest::get()
+                    .uri(uri)
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["data"]["todos"].as_array().map(Vec::len), Some(*count));
+            }
+
+            for uri in &["/api/todos?offset=-1", "/api/todos?limit=-1"] {
+                let request = test::TestRequest::get()
+                    .uri(uri)
+                    .cookie(Cookie::new("session_id", session_id.clone()))
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["meta"]["error"], "Invalid offset or limit");
+            }
+        });
+    }
 }
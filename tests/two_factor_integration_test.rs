@@ -0,0 +1,299 @@
+mod common;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::cookie::Cookie;
+    use actix_http::http::StatusCode;
+    use actix_web::{http, test, App};
+    use chrono::Utc;
+    use deadpool_postgres::Pool;
+    use productivity::settings::{AccountSettings, Settings};
+    use productivity::two_factor::totp::Totp;
+    use productivity::AppState;
+
+    #[test]
+    fn test_two_factor() {
+        let db_pool = common::create_db_pool().expect("Can't create db pool");
+        let db_pool = Pool::clone(&db_pool);
+
+        actix_rt::System::new("test_two_factor_runtime".to_string()).block_on(async move {
+            let session_store = common::create_session_store(&db_pool).await;
+            // The wrong codes count as failed logins, which mustn't lock the account out in the middle of the test
+            let settings = Settings {
+                account: AccountSettings {
+                    login_attempts: 20,
+                    ..AccountSettings::default()
+                },
+                ..Settings::default()
+            };
+
+            let mut app = test::init_service(
+                App::new()
+                    .data(AppState {
+                        db_pool,
+                        session_store,
+                        mailer: common::create_mailer(),
+                        settings,
+                    })
+                    .configure(common::test_config_app),
+            )
+            .await;
+
+            // Delete all existing data. USED IN TESTS ONLY
+            let request = test::TestRequest::post().uri("/api/account/reset").to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // Initial registration
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let session_id = common::get_session_id(response.headers()).to_string();
+            let csrf_token = common::get_csrf_token(response.headers()).to_string();
+
+            // Confirmation without an enrollment
+            let payload = json!({"code": "123456"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/2fa/confirm")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+
+            // Enrollment
+            let request = test::TestRequest::post()
+                .uri("/api/account/2fa/enroll")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let secret = response_value["data"]["secret"].as_str().expect("Can't parse secret");
+            let uri = response_value["data"]["uri"].as_str().expect("Can't parse uri");
+            assert!(uri.starts_with("otpauth://totp/Productivity:dimashur@gmail.com?"));
+            assert!(uri.contains(&format!("secret={}", secret)));
+            let totp = Totp::from_base32(secret).expect("Can't parse secret");
+
+            // Invalid confirmation code
+            let payload = json!({"code": "abcdef"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/2fa/confirm")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+            let step = Totp::step(Utc::now().timestamp());
+            let payload = json!({ "code": totp.code(step) });
+            let request = test::TestRequest::post()
+                .uri("/api/account/2fa/confirm")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let response_value = common::get_response_body(response).await;
+            let recovery_codes = response_value["data"]["recovery_codes"]
+                .as_array()
+                .expect("Can't parse recovery codes");
+            assert_eq!(recovery_codes.len(), 10);
+            let recovery_code = recovery_codes[0]
+                .as_str()
+                .expect("Can't parse recovery code")
+                .to_string();
+
+            // Enabled already
+            let request = test::TestRequest::post()
+                .uri("/api/account/2fa/enroll")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+
+            // The password only gives a challenge
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678", "device": "Laptop"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            assert!(response.headers().get(http::header::SET_COOKIE).is_none());
+
+            let response_value = common::get_response_body(response).await;
+            let challenge = response_value["data"]["challenge"]
+                .as_str()
+                .expect("Can't parse challenge")
+                .to_string();
+
+            // The confirmation code can't be replayed
+            let payload = json!({"challenge": challenge, "code": totp.code(step)});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/2fa")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Invalid code");
+
+            let payload = json!({"challenge": challenge, "code": totp.code(step + 1)});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/2fa")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let second_session_id = common::get_session_id(response.headers()).to_string();
+
+            // The device given with the password is kept
+            let request = test::TestRequest::get()
+                .uri("/api/account/sessions")
+                .cookie(Cookie::new("session_id", second_session_id.clone()))
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["data"]["sessions"][0]["device"], "Laptop");
+
+            // A challenge can only be used once
+            let payload = json!({"challenge": challenge, "code": totp.code(step + 1)});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/2fa")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Invalid or expired challenge");
+
+            // A recovery code instead of a code, in any case
+            for expected_status in &[StatusCode::OK, StatusCode::UNAUTHORIZED] {
+                let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                let response_value = common::get_response_body(response).await;
+                let challenge = response_value["data"]["challenge"]
+                    .as_str()
+                    .expect("Can't parse challenge");
+
+                let payload = json!({"challenge": challenge, "recovery_code": recovery_code.to_uppercase()});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login/2fa")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                assert_eq!(response.status(), *expected_status);
+            }
+
+            // The challenge is discarded after too many wrong codes
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            let challenge = response_value["data"]["challenge"]
+                .as_str()
+                .expect("Can't parse challenge")
+                .to_string();
+
+            for _ in 0..5 {
+                let payload = json!({"challenge": challenge, "code": "abcdef"});
+                let request = test::TestRequest::post()
+                    .uri("/api/account/login/2fa")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .set_payload(payload.to_string())
+                    .to_request();
+                let response = test::call_service(&mut app, request).await;
+                let response_value = common::get_response_body(response).await;
+                assert_eq!(response_value["meta"]["error"], "Invalid code");
+            }
+
+            let payload = json!({"challenge": challenge, "recovery_code": recovery_codes[1]});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login/2fa")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            let response_value = common::get_response_body(response).await;
+            assert_eq!(response_value["meta"]["error"], "Invalid or expired challenge");
+
+            // Disabling needs the password
+            let payload = json!({"password": "87654321"});
+            let request = test::TestRequest::delete()
+                .uri("/api/account/2fa")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            let payload = json!({"password": "12345678"});
+            let request = test::TestRequest::delete()
+                .uri("/api/account/2fa")
+                .cookie(Cookie::new("session_id", session_id.clone()))
+                .header("x-csrf-token", csrf_token.clone())
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let payload = json!({"email": "dimashur@gmail.com", "password": "12345678"});
+            let request = test::TestRequest::post()
+                .uri("/api/account/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .set_payload(payload.to_string())
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get(http::header::SET_COOKIE).is_some());
+        });
+    }
+}